@@ -0,0 +1,101 @@
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hackdose_sml_parser::{
+    encode::encode_frame,
+    reader::SmlReader,
+    transport::{FrameDecoder, SMLMessageBuilder},
+};
+
+/// Open, list and close response of an ISKRA meter
+static TELEGRAM: &[u8] = include_bytes!("data/telegram.bin");
+
+const FRAMES: usize = 10_000;
+
+/// About 2 MB of the same telegram repeated with line noise in between
+///
+/// Synthetic rather than a recording, so every frame has the same length and content.
+fn repeated_telegram() -> Vec<u8> {
+    let frame = encode_frame(TELEGRAM);
+    let mut input = Vec::with_capacity(FRAMES * (frame.len() + 8));
+    for index in 0..FRAMES {
+        input.extend_from_slice(&[0x00, 0x1b, 0x1b, 0x42][..index % 5]);
+        input.extend_from_slice(&frame);
+    }
+    input
+}
+
+fn builder(c: &mut Criterion) {
+    let input = repeated_telegram();
+    let mut group = c.benchmark_group("SMLMessageBuilder");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+    for chunk_size in [1, 64, 512, 4096] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(chunk_size),
+            &chunk_size,
+            |b, chunk_size| {
+                b.iter(|| {
+                    let mut builder = SMLMessageBuilder::Empty;
+                    let mut frames = 0;
+                    for chunk in input.chunks(*chunk_size) {
+                        builder.record(chunk);
+                        while let SMLMessageBuilder::Complete { rest, .. } = &mut builder {
+                            let rest = std::mem::take(rest);
+                            frames += 1;
+                            builder = SMLMessageBuilder::Empty;
+                            builder.record(&rest);
+                        }
+                    }
+                    assert_eq!(frames, FRAMES);
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn decoder(c: &mut Criterion) {
+    let input = repeated_telegram();
+    let mut group = c.benchmark_group("FrameDecoder");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+    group.bench_function("bytewise", |b| {
+        let mut buf = [0; 1024];
+        b.iter(|| {
+            let mut decoder = FrameDecoder::new(&mut buf);
+            let mut frames = 0;
+            for byte in input.iter() {
+                if let Some(frame) = decoder.push(*byte) {
+                    black_box(frame.unwrap());
+                    frames += 1;
+                }
+            }
+            assert_eq!(frames, FRAMES);
+        })
+    });
+    group.finish();
+}
+
+fn reader(c: &mut Criterion) {
+    let input = repeated_telegram();
+    let mut group = c.benchmark_group("SmlReader");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+    for buffer_size in [64, 512, 4096] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(buffer_size),
+            &buffer_size,
+            |b, buffer_size| {
+                b.iter(|| {
+                    let reader = SmlReader::with_buffer_size(Cursor::new(&input), *buffer_size);
+                    assert_eq!(reader.filter(|frame| frame.is_ok()).count(), FRAMES);
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, builder, decoder, reader);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hackdose_sml_parser::application::obis::Obis;
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Obis::from_number");
+    group.bench_function("first", |b| {
+        b.iter(|| Obis::from_number(black_box(&[1, 0, 1, 8, 0, 255])))
+    });
+    group.bench_function("last", |b| {
+        b.iter(|| Obis::from_number(black_box(&[1, 0, 14, 7, 0, 255])))
+    });
+    group.bench_function("unknown", |b| {
+        b.iter(|| Obis::from_number(black_box(&[129, 129, 199, 130, 3, 255])))
+    });
+    group.finish();
+
+    c.bench_function("Obis::obis_number", |b| {
+        b.iter(|| black_box(Obis::Frequency).obis_number())
+    });
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{
+    black_box, criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    Criterion, Throughput,
+};
+use hackdose_sml_parser::{
+    application::parser::{parse_body, parse_body_borrowed, parse_message},
+    encode::encode_frame,
+};
+
+/// Open, list and close response of an ISKRA meter
+static TELEGRAM: &[u8] = include_bytes!("data/telegram.bin");
+
+/// Counts allocations to compare owned and borrowed parsing
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Measures allocations instead of time, so criterion reports allocations per telegram
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for Allocations {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "allocations"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        _throughput: &Throughput,
+        _values: &mut [f64],
+    ) -> &'static str {
+        "allocations"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "allocations"
+    }
+}
+
+fn owned_vs_borrowed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_body");
+    group.bench_function("owned", |b| b.iter(|| parse_body(black_box(TELEGRAM))));
+    group.bench_function("borrowed", |b| {
+        b.iter(|| parse_body_borrowed(black_box(TELEGRAM)))
+    });
+    group.finish();
+}
+
+fn allocations(c: &mut Criterion<Allocations>) {
+    let mut group = c.benchmark_group("parse_body_allocations");
+    group.bench_function("owned", |b| b.iter(|| parse_body(black_box(TELEGRAM))));
+    group.bench_function("borrowed", |b| {
+        b.iter(|| parse_body_borrowed(black_box(TELEGRAM)))
+    });
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    let frame = encode_frame(TELEGRAM);
+    c.bench_function("parse_message", |b| {
+        b.iter(|| parse_message(black_box(&frame)))
+    });
+}
+
+criterion_group!(benches, owned_vs_borrowed, frame);
+criterion_group! {
+    name = allocation_benches;
+    // the count is the same in every sample, which the plots cannot show
+    config = Criterion::default().with_measurement(Allocations).without_plots();
+    targets = allocations
+}
+criterion_main!(benches, allocation_benches);
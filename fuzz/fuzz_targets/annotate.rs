@@ -0,0 +1,13 @@
+#![no_main]
+
+use hackdose_sml_parser::annotate::annotate;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let annotated = annotate(data);
+    for annotation in annotated.annotations.iter() {
+        assert!(annotation.range.start <= annotation.range.end);
+        assert!(annotation.range.end <= data.len());
+    }
+    let _ = annotated.to_string();
+});
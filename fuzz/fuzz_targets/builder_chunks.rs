@@ -0,0 +1,51 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use hackdose_sml_parser::transport::SMLMessageBuilder;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    data: Vec<u8>,
+    chunk_sizes: Vec<u8>,
+}
+
+/// Record the chunks and collect the complete bodies like a reader would
+fn decode<'a>(chunks: impl Iterator<Item = &'a [u8]>, limit: usize) -> Vec<Vec<u8>> {
+    let mut builder = SMLMessageBuilder::Empty;
+    let mut bodies = vec![];
+    for chunk in chunks {
+        builder.record_bounded(chunk, usize::MAX);
+        while let SMLMessageBuilder::Complete { .. } = builder {
+            let complete = std::mem::replace(&mut builder, SMLMessageBuilder::Empty);
+            if let SMLMessageBuilder::Complete { data, rest } = complete {
+                bodies.push(data);
+                builder.record_bounded(&rest, usize::MAX);
+            }
+        }
+        if let SMLMessageBuilder::Recording(recording) = &builder {
+            assert!(recording.data().len() <= limit);
+        }
+    }
+    bodies
+}
+
+fuzz_target!(|input: Input| {
+    let Input { data, chunk_sizes } = input;
+    let mut chunks = vec![];
+    let mut rest = &data[..];
+    for size in chunk_sizes.iter().cycle().take(data.len()) {
+        if rest.is_empty() {
+            break;
+        }
+        let (chunk, tail) = rest.split_at(usize::min(*size as usize, rest.len()));
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+
+    let chunked = decode(chunks.into_iter(), data.len());
+    let whole = decode(std::iter::once(&data[..]), data.len());
+
+    assert_eq!(chunked, whole);
+});
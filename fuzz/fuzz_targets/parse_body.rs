@@ -0,0 +1,9 @@
+#![no_main]
+
+use hackdose_sml_parser::application::parser::{parse_body, parse_body_with_headers};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_body(data);
+    let _ = parse_body_with_headers(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use hackdose_sml_parser::application::parser::parse_message;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_message(data);
+});
@@ -0,0 +1,26 @@
+#![no_main]
+
+use hackdose_sml_parser::{
+    application::parser::parse_body,
+    encode::{encode_body, encode_frame},
+    transport::SMLMessageBuilder,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(messages) = parse_body(data) else {
+        return;
+    };
+
+    let body = encode_body(&messages);
+    assert_eq!(parse_body(&body).unwrap(), messages);
+
+    let mut builder = SMLMessageBuilder::Empty;
+    builder.record(&encode_frame(&body));
+    match builder {
+        SMLMessageBuilder::Complete { data, .. } => {
+            assert_eq!(parse_body(&data).unwrap(), messages)
+        }
+        _ => panic!("encoded frame not complete"),
+    }
+});
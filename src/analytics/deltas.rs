@@ -0,0 +1,284 @@
+use alloc::vec::Vec;
+
+use super::Interval;
+use crate::{
+    application::{
+        domain::{SmlMessageEnvelope, SmlMessages},
+        obis::Obis,
+    },
+    export::timestamp,
+};
+
+/// Most intervals between two readings which are split into deltas, see [IntervalDeltas]
+pub const MAX_GAP_INTERVALS: u64 = 1000;
+
+/// Energy counted in an interval
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct IntervalDelta {
+    /// start of the interval in seconds since the unix epoch
+    pub start: u64,
+    /// end of the interval, the start of the next one
+    pub end: u64,
+    pub delta: f64,
+}
+
+/// Splits the increase of an energy counter into intervals aligned to the wall clock
+///
+/// The increase between two readings is spread evenly over the time between them, so
+/// intervals without readings of their own still get their share. A counter going
+/// backwards is taken as reset to zero. A reading of another meter pushed by
+/// [IntervalDeltas::push] is taken as replacement of the meter and starts counting
+/// anew; the energy between the last reading of the old and the first of the new meter
+/// is lost. So is the energy of a gap longer than [MAX_GAP_INTERVALS], e.g. after a
+/// wrong timestamp, which completes the interval in progress without the empty intervals
+/// of the gap.
+#[derive(Debug, Clone)]
+pub struct IntervalDeltas {
+    interval: Interval,
+    utc_offset: i32,
+    obis: &'static [u8],
+    server_id: Option<Vec<u8>>,
+    last: Option<Sample>,
+    /// start and delta of the interval in progress
+    current: Option<(u64, f64)>,
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    time: u64,
+    meter: Vec<u8>,
+    value: f64,
+}
+
+impl IntervalDeltas {
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            utc_offset: 0,
+            obis: Obis::PositiveActiveEnergyTotal.obis_number(),
+            server_id: None,
+            last: None,
+            current: None,
+        }
+    }
+
+    /// Align intervals to wall-clock time `seconds` ahead of UTC, defaults to 0
+    ///
+    /// Days in local time need the offset of the timezone; it is fixed, so changes to
+    /// or from daylight saving time move the boundaries by an hour.
+    pub fn utc_offset(mut self, seconds: i32) -> Self {
+        self.utc_offset = seconds;
+        self
+    }
+
+    /// Counter read by [IntervalDeltas::record], defaults to
+    /// [Obis::PositiveActiveEnergyTotal]
+    pub fn obis(mut self, obis: Obis) -> Self {
+        self.obis = obis.obis_number();
+        self
+    }
+
+    /// Meter read by [IntervalDeltas::record], defaults to the first meter it sees
+    pub fn server_id(mut self, server_id: impl Into<Vec<u8>>) -> Self {
+        self.server_id = Some(server_id.into());
+        self
+    }
+
+    /// Add the counter reading `value` of `meter` at `time`, returning the intervals
+    /// completed by it
+    ///
+    /// Readings not newer than the last one are ignored.
+    pub fn push(&mut self, time: u64, meter: &[u8], value: f64) -> Vec<IntervalDelta> {
+        let mut completed = Vec::new();
+        let Some(last) = &self.last else {
+            self.current = Some((self.interval.start(time, self.utc_offset), 0.0));
+            self.last = Some(Sample {
+                time,
+                meter: meter.to_vec(),
+                value,
+            });
+            return completed;
+        };
+        if time <= last.time {
+            return completed;
+        }
+
+        let increase = if last.meter != meter {
+            0.0
+        } else if value < last.value {
+            value
+        } else {
+            value - last.value
+        };
+        let elapsed = (time - last.time) as f64;
+        let mut from = last.time;
+        let (mut start, mut delta) = self.current.unwrap_or((from, 0.0));
+        if (time - start) / self.interval.seconds() > MAX_GAP_INTERVALS {
+            completed.push(IntervalDelta {
+                start,
+                end: start + self.interval.seconds(),
+                delta,
+            });
+            (start, delta, from) = (self.interval.start(time, self.utc_offset), 0.0, time);
+        }
+        loop {
+            let end = start + self.interval.seconds();
+            if time < end {
+                delta += increase * (time - from) as f64 / elapsed;
+                break;
+            }
+            delta += increase * (end - from) as f64 / elapsed;
+            completed.push(IntervalDelta { start, end, delta });
+            (start, delta, from) = (end, 0.0, end);
+        }
+        self.current = Some((start, delta));
+        self.last = Some(Sample {
+            time,
+            meter: meter.to_vec(),
+            value,
+        });
+        completed
+    }
+
+    /// Add the counter readings in the list responses of `messages`
+    ///
+    /// Only reads the meter of [IntervalDeltas::server_id], other meters on the same
+    /// bus are skipped. `received_at` is used for values without timestamp of their own.
+    pub fn record(&mut self, messages: &SmlMessages, received_at: u64) -> Vec<IntervalDelta> {
+        let mut completed = Vec::new();
+        for message in &messages.messages {
+            let SmlMessageEnvelope::GetListResponse(body) = message else {
+                continue;
+            };
+            if *self.server_id.get_or_insert_with(|| body.server_id.clone()) != body.server_id {
+                continue;
+            }
+            for entry in &body.value_list {
+                if entry.object_name != self.obis {
+                    continue;
+                }
+                if let Some(value) = entry.scaled_value() {
+                    let time = timestamp(entry, received_at);
+                    completed.extend(self.push(time, &body.server_id, value));
+                }
+            }
+        }
+        completed
+    }
+
+    /// The interval in progress with the energy counted so far
+    pub fn current(&self) -> Option<IntervalDelta> {
+        self.current.map(|(start, delta)| IntervalDelta {
+            start,
+            end: start + self.interval.seconds(),
+            delta,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue};
+
+    const MIDNIGHT: u64 = 1704067200;
+    const QUARTER: u64 = 900;
+
+    fn deltas(completed: &[IntervalDelta]) -> Vec<(u64, f64)> {
+        completed
+            .iter()
+            .map(|x| ((x.start - MIDNIGHT) / QUARTER, x.delta))
+            .collect()
+    }
+
+    #[test]
+    pub fn spreads_increase_over_intervals() {
+        let mut intervals = IntervalDeltas::new(Interval::QuarterHour);
+
+        assert_eq!(intervals.push(MIDNIGHT + 450, b"a", 100.0), vec![]);
+        assert_eq!(intervals.push(MIDNIGHT + 600, b"a", 110.0), vec![]);
+        assert_eq!(
+            deltas(&intervals.push(MIDNIGHT + 900, b"a", 130.0)),
+            vec![(0, 30.0)]
+        );
+        // no readings for the second quarter hour
+        assert_eq!(
+            deltas(&intervals.push(MIDNIGHT + 2250, b"a", 190.0)),
+            vec![(1, 40.0)]
+        );
+        assert_eq!(intervals.current().map(|x| x.delta), Some(20.0));
+        // late readings are ignored
+        assert_eq!(intervals.push(MIDNIGHT + 2000, b"a", 180.0), vec![]);
+    }
+
+    #[test]
+    pub fn continues_across_counter_reset_and_meter_replacement() {
+        let mut intervals = IntervalDeltas::new(Interval::QuarterHour);
+
+        intervals.push(MIDNIGHT, b"old", 5000.0);
+        intervals.push(MIDNIGHT + 300, b"old", 5010.0);
+        // reset to zero, then counted up to 4
+        intervals.push(MIDNIGHT + 450, b"old", 4.0);
+        // replaced by a meter starting at 200
+        intervals.push(MIDNIGHT + 600, b"new", 200.0);
+        let completed = intervals.push(MIDNIGHT + 900, b"new", 206.0);
+
+        assert_eq!(deltas(&completed), vec![(0, 20.0)]);
+    }
+
+    #[test]
+    pub fn restarts_after_gap_of_too_many_intervals() {
+        let mut intervals = IntervalDeltas::new(Interval::QuarterHour);
+
+        intervals.push(MIDNIGHT, b"a", 100.0);
+        intervals.push(MIDNIGHT + 300, b"a", 110.0);
+        // a wrong timestamp far in the future
+        let completed = intervals.push(u32::MAX as u64, b"a", 120.0);
+
+        assert_eq!(deltas(&completed), vec![(0, 10.0)]);
+        assert_eq!(intervals.current().map(|x| x.delta), Some(0.0));
+        let completed = intervals.push(u32::MAX as u64 + 900, b"a", 130.0);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].end - completed[0].start, QUARTER);
+
+        // gaps up to the limit are still split
+        let mut intervals = IntervalDeltas::new(Interval::QuarterHour);
+        intervals.push(MIDNIGHT, b"a", 0.0);
+        let gap = MAX_GAP_INTERVALS * QUARTER;
+        let completed = intervals.push(MIDNIGHT + gap, b"a", gap as f64);
+        assert_eq!(completed.len() as u64, MAX_GAP_INTERVALS);
+        assert!(completed.iter().all(|x| x.delta == QUARTER as f64));
+    }
+
+    #[test]
+    pub fn records_counter_from_messages() {
+        let messages = |server_id: u8, value: usize| {
+            ListResponse::new(&[0x0a, server_id])
+                .value(&[1, 0, 2, 8, 0, 255], AnyValue::Unsigned(value))
+                .unit(30)
+                .scaler(-1)
+                .messages()
+        };
+        let mut intervals = IntervalDeltas::new(Interval::Hour)
+            .obis(Obis::NegativeActiveEnergyTotal)
+            .server_id([0x0a, 0x01])
+            .utc_offset(3600);
+
+        intervals.record(&messages(2, 500), MIDNIGHT - 3600);
+        intervals.record(&messages(1, 1000), MIDNIGHT - 3600);
+        // another meter on the same bus
+        intervals.record(&messages(2, 9000), MIDNIGHT - 1800);
+        let completed = intervals.record(&messages(1, 1300), MIDNIGHT);
+
+        assert_eq!(
+            completed,
+            vec![IntervalDelta {
+                start: MIDNIGHT - 3600,
+                end: MIDNIGHT,
+                delta: 30.0
+            }]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Consumption per interval and energy from power readings
+//!
+//! [IntervalDeltas] turns the readings of an energy counter into the energy counted per
+//! quarter hour, hour or day. [PowerIntegrator] integrates power readings into energy
+//! for meters sending only power, and compares it with a counter if there is one.
+//! [NetPowerDeriver] works out signed net power for meters which send it without sign.
+//! [IntervalDeltas] and [PowerIntegrator] take the time of a reading in seconds since
+//! the unix epoch, e.g. from SML_Time or the time it was received; their `record` takes
+//! the timestamp sent by the meter if there is one. Values keep the unit sent by the
+//! meter, power in W is integrated to Wh. The `record` methods read one meter only,
+//! the first one seen unless set by `server_id`.
+//! ```
+//! use hackdose_sml_parser::analytics::{Interval, IntervalDeltas};
+//!
+//! let mut deltas = IntervalDeltas::new(Interval::Hour);
+//! deltas.push(1704067200, b"grid", 1000.0);
+//! deltas.push(1704069000, b"grid", 1200.0);
+//! let hours = deltas.push(1704072600, b"grid", 1500.0);
+//!
+//! assert_eq!(hours.len(), 1);
+//! assert_eq!((hours[0].start, hours[0].delta), (1704067200, 350.0));
+//! ```
+mod deltas;
+mod net_power;
+mod power;
+
+pub use deltas::{IntervalDelta, IntervalDeltas, MAX_GAP_INTERVALS};
+pub use net_power::{NetPower, NetPowerDeriver, SignSource, STATUS_EXPORT};
+pub use power::PowerIntegrator;
+
+/// Length of the intervals energy is summed up for
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Interval {
+    QuarterHour,
+    Hour,
+    Day,
+}
+
+impl Interval {
+    pub fn seconds(&self) -> u64 {
+        match self {
+            Interval::QuarterHour => 15 * 60,
+            Interval::Hour => 60 * 60,
+            Interval::Day => 24 * 60 * 60,
+        }
+    }
+
+    /// Start of the interval containing `time`, with wall-clock time `utc_offset`
+    /// seconds ahead of UTC
+    pub fn start(&self, time: u64, utc_offset: i32) -> u64 {
+        let local = time as i64 + utc_offset as i64;
+        let start = local - local.rem_euclid(self.seconds() as i64);
+        (start - utc_offset as i64) as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn aligns_intervals_to_wall_clock() {
+        // 2024-01-01 10:20:00 UTC
+        let time = 1704104400;
+
+        assert_eq!(Interval::QuarterHour.start(time, 0), 1704104100);
+        assert_eq!(Interval::Hour.start(time, 0), 1704103200);
+        assert_eq!(Interval::Day.start(time, 0), 1704067200);
+        // midnight in UTC+1 is 23:00 UTC the day before
+        assert_eq!(Interval::Day.start(time, 3600), 1704063600);
+        assert_eq!(Interval::Hour.start(time, 1800), 1704101400);
+    }
+}
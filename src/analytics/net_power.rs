@@ -0,0 +1,301 @@
+use alloc::vec::Vec;
+
+use crate::application::{
+    domain::{GetListResponseBody, SmlMessageEnvelope, SmlMessages},
+    obis::Obis,
+};
+
+/// Bit 5 of the FNN status word, set while energy is exported (-A)
+pub const STATUS_EXPORT: u32 = 1 << 5;
+
+/// Where the sign of a [NetPower] was taken from
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SignSource {
+    /// `SumActiveInstantaneousPower` as sent, the meter sent negative values before
+    /// or there was nothing else to go by
+    Reported,
+    /// import power minus export power
+    ImportExport,
+    /// the direction bit of the status word
+    StatusWord,
+    /// which energy counter increased between readings
+    CounterDirection,
+}
+
+/// Power drawn from the grid in W, negative while feeding in
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct NetPower {
+    pub watts: f64,
+    pub source: SignSource,
+}
+
+/// Works out signed net power for meters which do not send it
+///
+/// Meters without PIN often send `SumActiveInstantaneousPower` without sign, or only
+/// `PositiveActiveInstantaneousPower` and `NegativeActiveInstantaneousPower`. The sign
+/// is taken from, in this order:
+///
+/// 1. the import and export power if sent
+/// 2. the sum power if the meter ever sent a negative one
+/// 3. the energy counter which increased since the previous reading
+/// 4. the direction bit of the status word of the sum power or import counter
+/// 5. the energy counter which increased last
+///
+/// Without any of these the sum power is taken as sent. Use one deriver per meter.
+#[derive(Debug, Clone)]
+pub struct NetPowerDeriver {
+    status_export: u32,
+    server_id: Option<Vec<u8>>,
+    /// the meter sent negative power, so it sends signed power
+    signed: bool,
+    /// import and export counter of the previous reading
+    counters: Option<(f64, f64)>,
+    /// sign of the counter which increased last
+    direction: Option<f64>,
+}
+
+impl Default for NetPowerDeriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetPowerDeriver {
+    pub fn new() -> Self {
+        Self {
+            status_export: STATUS_EXPORT,
+            server_id: None,
+            signed: false,
+            counters: None,
+            direction: None,
+        }
+    }
+
+    /// Bits of the status word set while exporting, defaults to [STATUS_EXPORT]
+    ///
+    /// 0 ignores the status word, for meters using the bit otherwise.
+    pub fn status_export_mask(mut self, mask: u32) -> Self {
+        self.status_export = mask;
+        self
+    }
+
+    /// Meter read by [NetPowerDeriver::record], defaults to the first meter it sees
+    pub fn server_id(mut self, server_id: impl Into<Vec<u8>>) -> Self {
+        self.server_id = Some(server_id.into());
+        self
+    }
+
+    /// Net power of the last list response of the meter in `messages`
+    ///
+    /// List responses of other meters than [NetPowerDeriver::server_id] are skipped.
+    pub fn record(&mut self, messages: &SmlMessages) -> Option<NetPower> {
+        messages
+            .messages
+            .iter()
+            .filter_map(|message| match message {
+                SmlMessageEnvelope::GetListResponse(body)
+                    if *self.server_id.get_or_insert_with(|| body.server_id.clone())
+                        == body.server_id =>
+                {
+                    self.derive(body)
+                }
+                _ => None,
+            })
+            .last()
+    }
+
+    /// Net power of a list response, `None` if it contains no power
+    pub fn derive(&mut self, body: &GetListResponseBody) -> Option<NetPower> {
+        let entry = |obis: Obis| {
+            body.value_list
+                .iter()
+                .find(|entry| entry.object_name == obis.obis_number())
+        };
+        let value = |obis: Obis| entry(obis).and_then(|entry| entry.scaled_value());
+
+        let counted = self.counter_direction(
+            value(Obis::PositiveActiveEnergyTotal),
+            value(Obis::NegativeActiveEnergyTotal),
+        );
+        let import = value(Obis::PositiveActiveInstantaneousPower);
+        let export = value(Obis::NegativeActiveInstantaneousPower);
+        if import.is_some() || export.is_some() {
+            return Some(NetPower {
+                watts: import.unwrap_or(0.0) - export.unwrap_or(0.0),
+                source: SignSource::ImportExport,
+            });
+        }
+
+        let sum = value(Obis::SumActiveInstantaneousPower)?;
+        self.signed |= sum < 0.0;
+        let magnitude = sum.abs();
+        let status = [
+            Obis::SumActiveInstantaneousPower,
+            Obis::PositiveActiveEnergyTotal,
+        ]
+        .into_iter()
+        .find_map(|obis| entry(obis)?.status);
+        let (sign, source) = if self.signed {
+            (sum.signum(), SignSource::Reported)
+        } else if let Some(sign) = counted {
+            (sign, SignSource::CounterDirection)
+        } else if let Some(status) = status.filter(|_| self.status_export != 0) {
+            let export = status & self.status_export != 0;
+            (if export { -1.0 } else { 1.0 }, SignSource::StatusWord)
+        } else if let Some(sign) = self.direction {
+            (sign, SignSource::CounterDirection)
+        } else {
+            (1.0, SignSource::Reported)
+        };
+        Some(NetPower {
+            watts: sign * magnitude,
+            source,
+        })
+    }
+
+    /// Sign of the counter which increased since the previous reading, if only one did
+    fn counter_direction(&mut self, import: Option<f64>, export: Option<f64>) -> Option<f64> {
+        let counters = (import?, export.unwrap_or(0.0));
+        let previous = self.counters.replace(counters)?;
+        let sign = match (counters.0 > previous.0, counters.1 > previous.1) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => return None,
+        };
+        self.direction = Some(sign);
+        Some(sign)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue};
+
+    fn readings(entries: &[(Obis, Option<u32>, isize)]) -> GetListResponseBody {
+        meter_readings(0x01, entries)
+    }
+
+    fn meter_readings(
+        server_id: u8,
+        entries: &[(Obis, Option<u32>, isize)],
+    ) -> GetListResponseBody {
+        entries
+            .iter()
+            .fold(
+                ListResponse::new(&[0x0a, server_id]),
+                |list, (obis, status, value)| {
+                    list.value(obis.obis_number(), AnyValue::Signed(*value))
+                        .status(*status)
+                },
+            )
+            .body()
+    }
+
+    fn power(watts: isize) -> (Obis, Option<u32>, isize) {
+        (Obis::SumActiveInstantaneousPower, None, watts)
+    }
+
+    fn counters(import: isize, export: isize) -> [(Obis, Option<u32>, isize); 2] {
+        [
+            (Obis::PositiveActiveEnergyTotal, None, import),
+            (Obis::NegativeActiveEnergyTotal, None, export),
+        ]
+    }
+
+    fn derive_all(deriver: &mut NetPowerDeriver, bodies: &[GetListResponseBody]) -> Vec<NetPower> {
+        bodies.iter().filter_map(|x| deriver.derive(x)).collect()
+    }
+
+    fn net(watts: f64, source: SignSource) -> NetPower {
+        NetPower { watts, source }
+    }
+
+    #[test]
+    pub fn prefers_import_and_export_power() {
+        let body = readings(&[
+            power(300),
+            (Obis::PositiveActiveInstantaneousPower, None, 0),
+            (Obis::NegativeActiveInstantaneousPower, None, 300),
+        ]);
+
+        assert_eq!(
+            NetPowerDeriver::new().derive(&body),
+            Some(net(-300.0, SignSource::ImportExport))
+        );
+        assert_eq!(NetPowerDeriver::new().derive(&readings(&[])), None);
+    }
+
+    #[test]
+    pub fn takes_sign_from_counter_direction() {
+        let [import, export] = counters(1000, 500);
+        let first = readings(&[power(200), import, export]);
+        let [import, export] = counters(1000, 501);
+        let exporting = readings(&[power(200), import, export]);
+        let [import, export] = counters(1000, 501);
+        let unchanged = readings(&[power(100), import, export]);
+        let [import, export] = counters(1001, 501);
+        let importing = readings(&[power(100), import, export]);
+
+        assert_eq!(
+            derive_all(
+                &mut NetPowerDeriver::new(),
+                &[first, exporting, unchanged, importing]
+            ),
+            vec![
+                net(200.0, SignSource::Reported),
+                net(-200.0, SignSource::CounterDirection),
+                net(-100.0, SignSource::CounterDirection),
+                net(100.0, SignSource::CounterDirection),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn takes_sign_from_status_word_until_meter_sends_negative_power() {
+        let exporting = readings(&[(Obis::SumActiveInstantaneousPower, Some(0x1a2), 250)]);
+        let negative = readings(&[power(-250)]);
+        let positive = readings(&[(Obis::SumActiveInstantaneousPower, Some(0x1a2), 250)]);
+
+        assert_eq!(
+            derive_all(
+                &mut NetPowerDeriver::new(),
+                &[exporting.clone(), negative, positive]
+            ),
+            vec![
+                net(-250.0, SignSource::StatusWord),
+                net(-250.0, SignSource::Reported),
+                net(250.0, SignSource::Reported),
+            ]
+        );
+        assert_eq!(
+            NetPowerDeriver::new()
+                .status_export_mask(0)
+                .derive(&exporting),
+            Some(net(250.0, SignSource::Reported))
+        );
+    }
+
+    #[test]
+    pub fn records_only_one_meter() {
+        let messages = SmlMessages {
+            messages: vec![
+                SmlMessageEnvelope::GetListResponse(meter_readings(0x01, &[power(-100)])),
+                SmlMessageEnvelope::GetListResponse(meter_readings(0x02, &[power(400)])),
+            ],
+        };
+
+        assert_eq!(
+            NetPowerDeriver::new().record(&messages),
+            Some(net(-100.0, SignSource::Reported))
+        );
+        assert_eq!(
+            NetPowerDeriver::new()
+                .server_id([0x0a, 0x02])
+                .record(&messages),
+            Some(net(400.0, SignSource::Reported))
+        );
+    }
+}
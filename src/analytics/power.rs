@@ -0,0 +1,209 @@
+use alloc::vec::Vec;
+
+use crate::{
+    application::{
+        domain::{SmlMessageEnvelope, SmlMessages},
+        obis::Obis,
+        unit::{WATT, WATT_HOUR},
+    },
+    export::timestamp,
+};
+
+/// Longest time between power readings which is integrated unless configured
+const DEFAULT_MAX_GAP: u64 = 60;
+
+/// Integrates power in W into energy in Wh
+///
+/// Power is taken to change linearly between readings. Gaps longer than
+/// [PowerIntegrator::max_gap] are not integrated but added up as [PowerIntegrator::uncovered].
+/// If the meter also sends a counter, [PowerIntegrator::deviation] tells how far the
+/// integrated energy is off since the first counter reading.
+#[derive(Debug, Clone)]
+pub struct PowerIntegrator {
+    max_gap: u64,
+    server_id: Option<Vec<u8>>,
+    /// time and power of the last reading
+    last: Option<(u64, f64)>,
+    energy: f64,
+    uncovered: u64,
+    counted: Option<Counted>,
+}
+
+/// Counter readings to compare the integrated energy with
+#[derive(Debug, Clone, Copy)]
+struct Counted {
+    /// integrated energy at the first counter reading
+    integrated: f64,
+    first: f64,
+    last: f64,
+}
+
+impl Default for PowerIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PowerIntegrator {
+    pub fn new() -> Self {
+        Self {
+            max_gap: DEFAULT_MAX_GAP,
+            server_id: None,
+            last: None,
+            energy: 0.0,
+            uncovered: 0,
+            counted: None,
+        }
+    }
+
+    /// Longest time in seconds between readings which is integrated, defaults to 60
+    pub fn max_gap(mut self, seconds: u64) -> Self {
+        self.max_gap = seconds;
+        self
+    }
+
+    /// Meter read by [PowerIntegrator::record], defaults to the first meter it sees
+    pub fn server_id(mut self, server_id: impl Into<Vec<u8>>) -> Self {
+        self.server_id = Some(server_id.into());
+        self
+    }
+
+    /// Add the power reading `watts` at `time`
+    ///
+    /// Readings not newer than the last one are ignored.
+    pub fn push_power(&mut self, time: u64, watts: f64) {
+        match self.last {
+            Some((last, _)) if time <= last => return,
+            Some((last, power)) if time - last <= self.max_gap => {
+                self.energy += (power + watts) / 2.0 * (time - last) as f64 / 3600.0
+            }
+            Some((last, _)) => self.uncovered += time - last,
+            None => (),
+        }
+        self.last = Some((time, watts));
+    }
+
+    /// Add a reading of the counter the integrated energy should match, in Wh
+    pub fn push_counter(&mut self, value: f64) {
+        match &mut self.counted {
+            Some(counted) => counted.last = value,
+            None => {
+                self.counted = Some(Counted {
+                    integrated: self.energy,
+                    first: value,
+                    last: value,
+                })
+            }
+        }
+    }
+
+    /// Add the readings in the list responses of `messages`
+    ///
+    /// Integrates [Obis::SumActiveInstantaneousPower] in W and compares it with
+    /// [Obis::PositiveActiveEnergyTotal] minus [Obis::NegativeActiveEnergyTotal] in Wh if
+    /// sent. Values in other units and of other meters than [PowerIntegrator::server_id]
+    /// are skipped. `received_at` is used for values without timestamp of their own.
+    pub fn record(&mut self, messages: &SmlMessages, received_at: u64) {
+        for message in &messages.messages {
+            let SmlMessageEnvelope::GetListResponse(body) = message else {
+                continue;
+            };
+            if *self.server_id.get_or_insert_with(|| body.server_id.clone()) != body.server_id {
+                continue;
+            }
+            let value = |obis: Obis, unit: u8| {
+                body.value_list
+                    .iter()
+                    .find(|entry| entry.object_name == obis.obis_number())
+                    .filter(|entry| entry.unit == Some(unit))
+                    .and_then(|entry| Some((entry, entry.scaled_value()?)))
+            };
+            if let Some((entry, watts)) = value(Obis::SumActiveInstantaneousPower, WATT) {
+                self.push_power(timestamp(entry, received_at), watts);
+            }
+            if let Some((_, import)) = value(Obis::PositiveActiveEnergyTotal, WATT_HOUR) {
+                let export =
+                    value(Obis::NegativeActiveEnergyTotal, WATT_HOUR).map_or(0.0, |(_, x)| x);
+                self.push_counter(import - export);
+            }
+        }
+    }
+
+    /// Energy integrated so far in Wh
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+
+    /// Seconds between readings which were not integrated because they were too long
+    pub fn uncovered(&self) -> u64 {
+        self.uncovered
+    }
+
+    /// Increase of the counter since its first reading
+    pub fn counted(&self) -> Option<f64> {
+        self.counted.map(|counted| counted.last - counted.first)
+    }
+
+    /// Integrated minus counted energy since the first counter reading
+    pub fn deviation(&self) -> Option<f64> {
+        self.counted
+            .map(|counted| self.energy - counted.integrated - (counted.last - counted.first))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue};
+
+    #[test]
+    pub fn integrates_power_and_skips_gaps() {
+        let mut integrator = PowerIntegrator::new().max_gap(1800);
+
+        integrator.push_power(0, 1000.0);
+        integrator.push_power(1800, 3000.0);
+        integrator.push_power(1200, 5000.0);
+        integrator.push_power(3600, 3000.0);
+        integrator.push_power(7200, 0.0);
+
+        assert_eq!(integrator.energy(), 1000.0 + 1500.0);
+        assert_eq!(integrator.uncovered(), 3600);
+    }
+
+    #[test]
+    pub fn compares_with_net_counter() {
+        let messages = |server_id: u8, (unit, power): (u8, isize), import, export| {
+            ListResponse::new(&[0x0a, server_id])
+                .value(
+                    Obis::PositiveActiveEnergyTotal.obis_number(),
+                    AnyValue::Unsigned(import),
+                )
+                .unit(WATT_HOUR)
+                .value(
+                    Obis::NegativeActiveEnergyTotal.obis_number(),
+                    AnyValue::Unsigned(export),
+                )
+                .unit(WATT_HOUR)
+                .value(
+                    Obis::SumActiveInstantaneousPower.obis_number(),
+                    AnyValue::Signed(power),
+                )
+                .unit(unit)
+                .messages()
+        };
+        let mut integrator = PowerIntegrator::new();
+        assert_eq!(integrator.deviation(), None);
+
+        integrator.record(&messages(1, (WATT, 3600), 1000, 500), 0);
+        // another meter on the same bus
+        integrator.record(&messages(2, (WATT, 100), 0, 0), 15);
+        integrator.record(&messages(1, (WATT, -1800), 1010, 500), 30);
+        // apparent power in VA
+        integrator.record(&messages(1, (28, 2000), 1010, 510), 45);
+        integrator.record(&messages(1, (WATT, -1800), 1010, 510), 60);
+
+        assert_eq!(integrator.energy(), 7.5 - 15.0);
+        assert_eq!(integrator.counted(), Some(0.0));
+        assert_eq!(integrator.deviation(), Some(-7.5));
+    }
+}
@@ -0,0 +1,306 @@
+//! Annotated hex listings of SML frames
+//!
+//! [annotate] explains every byte of a frame: escape sequences, type-length fields,
+//! message boundaries, crcs and field names from the SML schema. Unlike the typed
+//! parsers it also describes frames which are corrupt or use unsupported messages,
+//! which helps when onboarding a new meter.
+//! ```
+//! use hackdose_sml_parser::{annotate::annotate, encode::encode_frame};
+//!
+//! let frame = encode_frame(&[
+//!     0x76, 0x05, 0x03, 0x2b, 0x18, 0x11, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01,
+//!     0x71, 0x01, 0x63, 0xfa, 0x36, 0x00,
+//! ]);
+//! let listing = annotate(&frame).to_string();
+//!
+//! assert!(listing.contains("transactionId: octet string, 4 bytes = 032b1811"));
+//! assert!(listing.contains("crc16: unsigned, 2 bytes = fa36, valid"));
+//! ```
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+use crate::{
+    hex::Hex,
+    transport::{
+        crc16, END_SEQUENCE_WITHOUT_CRC, ESCAPED_ESCAPE_SEQUENCE, ESCAPE_SEQUENCE, START_SEQUENCE,
+    },
+};
+
+mod schema;
+
+/// Number of bytes shown per line of a listing
+const BYTES_PER_LINE: usize = 8;
+
+/// Description of a range of annotated bytes
+#[derive(PartialEq, Debug, Clone)]
+pub struct Annotation {
+    /// the bytes described, as offsets into the annotated data
+    pub range: Range<usize>,
+    /// nesting level of the described element
+    pub depth: usize,
+    pub text: String,
+}
+
+/// Annotations of some data, displayed as hex listing
+#[derive(PartialEq, Debug, Clone)]
+pub struct Annotated<'a> {
+    pub data: &'a [u8],
+    /// annotations ordered by their start, escape sequences overlap the element they are in
+    pub annotations: Vec<Annotation>,
+}
+
+/// Annotate the frames in `data`, or `data` as message body if it contains no frame
+///
+/// Bytes outside of frames are annotated as such, truncated frames are annotated
+/// as far as they go.
+pub fn annotate(data: &[u8]) -> Annotated<'_> {
+    let mut annotations = if contains_start_sequence(data) {
+        annotate_frames(data)
+    } else {
+        schema::annotate_body(data, 0)
+    };
+    annotations.sort_by_key(|annotation| annotation.range.start);
+    Annotated { data, annotations }
+}
+
+fn contains_start_sequence(data: &[u8]) -> bool {
+    data.windows(START_SEQUENCE.len())
+        .any(|window| window == START_SEQUENCE)
+}
+
+fn annotate_frames(data: &[u8]) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    let mut outside = None;
+    let mut position = 0;
+    let mut number = 0;
+    while position < data.len() {
+        if data[position..].starts_with(START_SEQUENCE) {
+            if let Some(start) = outside.take() {
+                annotations.push(annotation(start..position, 0, "outside of frame"));
+            }
+            number += 1;
+            position = annotate_frame(data, position, number, &mut annotations);
+        } else {
+            outside.get_or_insert(position);
+            position += 1;
+        }
+    }
+    if let Some(start) = outside {
+        annotations.push(annotation(start..position, 0, "outside of frame"));
+    }
+    annotations
+}
+
+/// Annotate the frame starting at `start`, returning the position after it
+fn annotate_frame(
+    data: &[u8],
+    start: usize,
+    number: usize,
+    annotations: &mut Vec<Annotation>,
+) -> usize {
+    let text = format!("frame {}: escape sequence", number);
+    annotations.push(annotation(start..start + 4, 0, &text));
+    annotations.push(annotation(
+        start + 4..start + 8,
+        1,
+        "start of frame, version 1",
+    ));
+
+    // unescaped body and the offset of each of its bytes in data
+    let mut body = Vec::new();
+    let mut offsets = Vec::new();
+    let mut position = start + START_SEQUENCE.len();
+    let end = loop {
+        let rest = &data[position..];
+        // escape sequences are aligned to four bytes from the start of the frame
+        let aligned = (position - start) % ESCAPE_SEQUENCE.len() == 0;
+        if rest.is_empty() || rest.starts_with(START_SEQUENCE) {
+            break None;
+        } else if aligned && rest.starts_with(ESCAPED_ESCAPE_SEQUENCE) {
+            annotations.push(annotation(
+                position..position + 4,
+                1,
+                "escape sequence, the following 1b1b1b1b is data",
+            ));
+            body.extend_from_slice(ESCAPE_SEQUENCE);
+            offsets.extend(position + 4..position + 8);
+            position += ESCAPED_ESCAPE_SEQUENCE.len();
+        } else if aligned && rest.starts_with(END_SEQUENCE_WITHOUT_CRC) {
+            break Some(position);
+        } else if aligned && rest.starts_with(ESCAPE_SEQUENCE) && rest.len() >= 8 {
+            annotations.push(annotation(
+                position..position + 8,
+                1,
+                "escape sequence with unknown command",
+            ));
+            position += 8;
+        } else {
+            body.push(rest[0]);
+            offsets.push(position);
+            position += 1;
+        }
+    };
+
+    let Some(end) = end else {
+        annotate_body(&body, &offsets, annotations);
+        annotations.push(annotation(
+            position..position,
+            1,
+            "truncated, no end sequence",
+        ));
+        return position;
+    };
+
+    let padding = data.get(end + 5).copied().unwrap_or(0) as usize;
+    if padding > 0 && padding <= 3 && padding <= body.len() {
+        let padding_start = offsets[offsets.len() - padding];
+        body.truncate(body.len() - padding);
+        offsets.truncate(offsets.len() - padding);
+        annotations.push(annotation(padding_start..end, 1, "padding"));
+    }
+    annotate_body(&body, &offsets, annotations);
+
+    annotations.push(annotation(end..end + 4, 1, "escape sequence"));
+    let trailer = end + END_SEQUENCE_WITHOUT_CRC.len();
+    if trailer >= data.len() {
+        annotations.push(annotation(end + 4..data.len(), 1, "truncated end"));
+        return data.len();
+    }
+    annotations.push(annotation(
+        end + 4..trailer + 1,
+        1,
+        &format!("end of frame, {} padding bytes", data[trailer]),
+    ));
+    let crc = trailer + 1..trailer + 3;
+    if crc.end > data.len() {
+        annotations.push(annotation(crc.start..data.len(), 1, "truncated crc"));
+        return data.len();
+    }
+    let expected = crc16(&data[start..crc.start]).to_le_bytes();
+    let text = if data[crc.clone()] == expected {
+        String::from("frame crc, valid")
+    } else {
+        format!("frame crc, invalid, expected {}", Hex(&expected))
+    };
+    annotations.push(annotation(crc.clone(), 1, &text));
+    crc.end
+}
+
+/// Annotate an unescaped body, mapping its ranges back to the escaped data
+fn annotate_body(body: &[u8], offsets: &[usize], annotations: &mut Vec<Annotation>) {
+    for mut annotation in schema::annotate_body(body, 1) {
+        let Range { start, end } = annotation.range;
+        annotation.range = match (offsets.get(start), end.checked_sub(1)) {
+            (Some(first), Some(last)) if end > start => *first..offsets[last] + 1,
+            (Some(first), _) => *first..*first,
+            (None, _) => continue,
+        };
+        annotations.push(annotation);
+    }
+}
+
+fn annotation(range: Range<usize>, depth: usize, text: &str) -> Annotation {
+    Annotation {
+        range,
+        depth,
+        text: String::from(text),
+    }
+}
+
+impl Display for Annotated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for annotation in self.annotations.iter() {
+            let start = annotation.range.start;
+            let bytes = &self.data[annotation.range.clone()];
+            let mut lines = bytes.chunks(BYTES_PER_LINE);
+            writeln!(
+                f,
+                "{:06x}  {:width$}  {:indent$}{}",
+                start,
+                format!("{:#}", Hex(lines.next().unwrap_or_default())),
+                "",
+                annotation.text,
+                width = 3 * BYTES_PER_LINE - 1,
+                indent = 2 * annotation.depth
+            )?;
+            for (index, line) in lines.enumerate() {
+                writeln!(
+                    f,
+                    "{:06x}  {:#}",
+                    start + (index + 1) * BYTES_PER_LINE,
+                    Hex(line)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        application::domain::{GetOpenResponseBody, SmlMessageEnvelope, SmlMessages},
+        encode::encode,
+    };
+
+    fn texts<'a>(annotated: &'a Annotated) -> Vec<&'a str> {
+        annotated
+            .annotations
+            .iter()
+            .map(|annotation| annotation.text.as_str())
+            .collect()
+    }
+
+    fn assert_covered(annotated: &Annotated) {
+        let mut covered = vec![false; annotated.data.len()];
+        for annotation in annotated.annotations.iter() {
+            annotation.range.clone().for_each(|x| covered[x] = true);
+        }
+        assert!(covered.iter().all(|x| *x), "{}", annotated);
+    }
+
+    fn open_response() -> Vec<u8> {
+        encode(&SmlMessages {
+            messages: vec![SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                server_id: vec![0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x00, 0x00],
+                req_file_id: vec![0x01, 0x02, 0x03, 0x04, 0x05],
+            })],
+        })
+    }
+
+    #[test]
+    pub fn annotates_escaped_escape_sequence() {
+        let frame = open_response();
+
+        let annotated = annotate(&frame);
+
+        let texts = texts(&annotated);
+        assert!(texts.contains(&"escape sequence, the following 1b1b1b1b is data"));
+        assert!(texts.contains(&"serverId: octet string, 8 bytes = 1b1b1b1b1a000000"));
+        assert!(texts.contains(&"frame crc, valid"));
+        assert_covered(&annotated);
+    }
+
+    #[test]
+    pub fn annotates_corrupt_and_truncated_frames() {
+        let mut data = vec![0xaa, 0xbb];
+        let mut frame = open_response();
+        // unknown message body
+        frame[19] = 0x09;
+        data.extend_from_slice(&frame);
+        data.extend_from_slice(&open_response()[..30]);
+
+        let annotated = annotate(&data);
+
+        let texts = texts(&annotated);
+        assert_eq!(texts[0], "outside of frame");
+        assert!(texts.contains(&"tag: octet string, 8 bytes = 0101760101060102"));
+        assert!(texts.contains(&"frame crc, invalid, expected fe68"));
+        assert!(texts.contains(&"truncated, no end sequence"));
+        assert_covered(&annotated);
+    }
+}
@@ -0,0 +1,518 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::Range;
+
+use super::Annotation;
+use crate::{
+    application::{
+        obis::{Obis, ObisNotation},
+        tlv::{element_length, read_type_length, ElementType, TypeLength},
+        unit,
+    },
+    hex::Hex,
+    transport::crc16,
+};
+
+/// Deepest nesting of lists walked, deeper lists are annotated as a whole
+const MAX_DEPTH: usize = 64;
+
+/// Expected content of an SML element
+#[derive(Clone, Copy)]
+enum Schema {
+    OctetString,
+    ObisNumber,
+    Unsigned,
+    Signed,
+    Unit,
+    /// any primitive value
+    Value,
+    /// anything, without names
+    Any,
+    Tree,
+    /// list with a fixed number of named elements
+    Sequence(&'static [(&'static str, Schema)]),
+    /// list of any number of like elements
+    ListOf(&'static str, &'static Schema),
+    /// list of a tag and the element selected by it
+    Choice(&'static [(u16, &'static str, Schema)]),
+}
+
+use Schema::*;
+
+const TIME: Schema = Choice(&[
+    (1, "secIndex", Unsigned),
+    (2, "timestamp", Unsigned),
+    (
+        3,
+        "localTimestamp",
+        Sequence(&[
+            ("timestamp", Unsigned),
+            ("localOffset", Signed),
+            ("seasonTimeOffset", Signed),
+        ]),
+    ),
+]);
+
+const PROC_PAR_VALUE: Schema = Choice(&[
+    (1, "smlValue", Value),
+    (
+        2,
+        "smlPeriodEntry",
+        Sequence(&[
+            ("objName", ObisNumber),
+            ("unit", Unit),
+            ("scaler", Signed),
+            ("value", Value),
+            ("valueSignature", OctetString),
+        ]),
+    ),
+    (3, "smlTupelEntry", Any),
+    (4, "smlTime", TIME),
+]);
+
+const TREE: Schema = Sequence(&[
+    ("parameterName", ObisNumber),
+    ("parameterValue", PROC_PAR_VALUE),
+    ("childList", ListOf("child", &Tree)),
+]);
+
+const TREE_PATH: Schema = ListOf("pathEntry", &ObisNumber);
+
+const LIST_ENTRY: Schema = Sequence(&[
+    ("objName", ObisNumber),
+    ("status", Unsigned),
+    ("valTime", TIME),
+    ("unit", Unit),
+    ("scaler", Signed),
+    ("value", Value),
+    ("valueSignature", OctetString),
+]);
+
+const MESSAGE_BODY: Schema = Choice(&[
+    (
+        0x0100,
+        "OpenRequest",
+        Sequence(&[
+            ("codepage", OctetString),
+            ("clientId", OctetString),
+            ("reqFileId", OctetString),
+            ("serverId", OctetString),
+            ("username", OctetString),
+            ("password", OctetString),
+            ("smlVersion", Unsigned),
+        ]),
+    ),
+    (
+        0x0101,
+        "OpenResponse",
+        Sequence(&[
+            ("codepage", OctetString),
+            ("clientId", OctetString),
+            ("reqFileId", OctetString),
+            ("serverId", OctetString),
+            ("refTime", TIME),
+            ("smlVersion", Unsigned),
+        ]),
+    ),
+    (
+        0x0200,
+        "CloseRequest",
+        Sequence(&[("globalSignature", OctetString)]),
+    ),
+    (
+        0x0201,
+        "CloseResponse",
+        Sequence(&[("globalSignature", OctetString)]),
+    ),
+    (0x0300, "GetProfilePackRequest", Any),
+    (0x0301, "GetProfilePackResponse", Any),
+    (0x0400, "GetProfileListRequest", Any),
+    (0x0401, "GetProfileListResponse", Any),
+    (
+        0x0500,
+        "GetProcParameterRequest",
+        Sequence(&[
+            ("serverId", OctetString),
+            ("username", OctetString),
+            ("password", OctetString),
+            ("parameterTreePath", TREE_PATH),
+            ("attribute", OctetString),
+        ]),
+    ),
+    (
+        0x0501,
+        "GetProcParameterResponse",
+        Sequence(&[
+            ("serverId", OctetString),
+            ("parameterTreePath", TREE_PATH),
+            ("parameterTree", TREE),
+        ]),
+    ),
+    (
+        0x0600,
+        "SetProcParameterRequest",
+        Sequence(&[
+            ("serverId", OctetString),
+            ("username", OctetString),
+            ("password", OctetString),
+            ("parameterTreePath", TREE_PATH),
+            ("parameterTree", TREE),
+        ]),
+    ),
+    (
+        0x0700,
+        "GetListRequest",
+        Sequence(&[
+            ("clientId", OctetString),
+            ("serverId", OctetString),
+            ("username", OctetString),
+            ("password", OctetString),
+            ("listName", OctetString),
+        ]),
+    ),
+    (
+        0x0701,
+        "GetListResponse",
+        Sequence(&[
+            ("clientId", OctetString),
+            ("serverId", OctetString),
+            ("listName", OctetString),
+            ("actSensorTime", TIME),
+            ("valList", ListOf("valListEntry", &LIST_ENTRY)),
+            ("listSignature", OctetString),
+            ("actGatewayTime", TIME),
+        ]),
+    ),
+    (
+        0xff01,
+        "AttentionResponse",
+        Sequence(&[
+            ("serverId", OctetString),
+            ("attentionNo", OctetString),
+            ("attentionMsg", OctetString),
+            ("attentionDetails", TREE),
+        ]),
+    ),
+]);
+
+/// Annotate the messages of an unescaped message body
+///
+/// Ranges refer to `body`, `depth` is the nesting level of the messages.
+pub(super) fn annotate_body(body: &[u8], depth: usize) -> Vec<Annotation> {
+    let mut walker = Walker {
+        body,
+        position: 0,
+        annotations: Vec::new(),
+        failed: false,
+    };
+    let mut number = 0;
+    while !walker.failed && walker.position < body.len() {
+        let rest = walker.position..body.len();
+        if body[rest.clone()].iter().all(|x| *x == 0x00) {
+            walker.annotate(rest, depth, String::from("padding"));
+            break;
+        }
+        number += 1;
+        walker.message(number, depth);
+    }
+    walker.annotations
+}
+
+struct Walker<'a> {
+    body: &'a [u8],
+    position: usize,
+    annotations: Vec<Annotation>,
+    /// set once an element could not be read, the rest of the body is annotated then
+    failed: bool,
+}
+
+impl Walker<'_> {
+    fn annotate(&mut self, range: Range<usize>, depth: usize, text: String) {
+        self.annotations.push(Annotation { range, depth, text });
+    }
+
+    /// Type-length field at the current position, if it and its value can be read
+    fn read(&mut self, name: &str, depth: usize) -> Option<TypeLength> {
+        if self.failed {
+            return None;
+        }
+        let rest = &self.body[self.position..];
+        let type_length = read_type_length(rest).filter(|type_length| {
+            type_length.element_type == ElementType::List
+                || type_length.size + type_length.length <= rest.len()
+        });
+        if type_length.is_none() {
+            let text = if rest.is_empty() {
+                format!("{}: missing", name)
+            } else {
+                format!("{}: invalid or truncated element", name)
+            };
+            self.annotate(self.position..self.body.len(), depth, text);
+            self.position = self.body.len();
+            self.failed = true;
+        }
+        type_length
+    }
+
+    fn message(&mut self, number: usize, depth: usize) {
+        let start = self.position;
+        let name = format!("message {}", number);
+        let Some(type_length) = self.read(&name, depth) else {
+            return;
+        };
+        if type_length.element_type != ElementType::List || type_length.length != 6 {
+            return self.element(&name, &Any, depth);
+        }
+        self.list_header(&name, type_length, "", depth);
+        self.element("transactionId", &OctetString, depth + 1);
+        self.element("groupNo", &Unsigned, depth + 1);
+        self.element("abortOnError", &Unsigned, depth + 1);
+        self.element("messageBody", &MESSAGE_BODY, depth + 1);
+
+        let crc_start = self.position;
+        let Some(type_length) = self.read("crc16", depth + 1) else {
+            return;
+        };
+        if type_length.element_type != ElementType::Unsigned || type_length.length != 2 {
+            self.element("crc16", &Unsigned, depth + 1);
+        } else {
+            let value = crc_start + type_length.size..crc_start + type_length.size + 2;
+            let expected = crc16(&self.body[start..crc_start]).to_le_bytes();
+            let text = if self.body[value.clone()] == expected {
+                format!(
+                    "crc16: {} = {}, valid",
+                    describe(type_length),
+                    Hex(&expected)
+                )
+            } else {
+                format!(
+                    "crc16: {} = {}, invalid, expected {}",
+                    describe(type_length),
+                    Hex(&self.body[value.clone()]),
+                    Hex(&expected)
+                )
+            };
+            self.position = value.end;
+            self.annotate(crc_start..value.end, depth + 1, text);
+        }
+
+        match self.body.get(self.position) {
+            Some(0x00) => {
+                self.annotate(
+                    self.position..self.position + 1,
+                    depth + 1,
+                    String::from("endOfSmlMsg"),
+                );
+                self.position += 1;
+            }
+            _ => self.element("endOfSmlMsg", &Any, depth + 1),
+        }
+    }
+
+    fn element(&mut self, name: &str, schema: &Schema, depth: usize) {
+        let Some(type_length) = self.read(name, depth) else {
+            return;
+        };
+        let start = self.position;
+        if self.body[start] == 0x01 {
+            self.position += 1;
+            return self.annotate(start..start + 1, depth, format!("{}: not set", name));
+        }
+        if type_length.element_type != ElementType::List {
+            return self.primitive(name, schema, type_length, depth);
+        }
+        if depth >= MAX_DEPTH {
+            return self.nested_too_deeply(name, depth);
+        }
+        match schema {
+            Sequence(fields) if type_length.length == fields.len() => {
+                let suffix = match fields.first() {
+                    Some((_, ObisNumber)) => self.obis_suffix(start + type_length.size),
+                    _ => String::new(),
+                };
+                self.list_header(name, type_length, &suffix, depth);
+                for (name, schema) in fields.iter() {
+                    self.element(name, schema, depth + 1);
+                }
+            }
+            ListOf(item, schema) => {
+                self.list_header(name, type_length, "", depth);
+                for index in 0..type_length.length {
+                    self.element(&format!("{} {}", item, index + 1), schema, depth + 1);
+                }
+            }
+            Choice(choices) if type_length.length == 2 => {
+                self.choice(name, choices, type_length, depth)
+            }
+            Tree => self.element(name, &TREE, depth),
+            _ => {
+                self.list_header(name, type_length, "", depth);
+                for index in 0..type_length.length {
+                    self.element(&format!("element {}", index + 1), &Any, depth + 1);
+                }
+            }
+        }
+    }
+
+    fn list_header(&mut self, name: &str, type_length: TypeLength, suffix: &str, depth: usize) {
+        let start = self.position;
+        self.position += type_length.size;
+        let text = format!("{}: {}{}", name, describe(type_length), suffix);
+        self.annotate(start..self.position, depth, text);
+    }
+
+    /// Name of the OBIS number starting at `position`, to label list entries
+    fn obis_suffix(&self, position: usize) -> String {
+        let rest = &self.body[position.min(self.body.len())..];
+        match read_type_length(rest) {
+            Some(TypeLength {
+                element_type: ElementType::OctetString,
+                length: 6,
+                size: 1,
+            }) if rest.len() >= 7 => format!(", {}", display_obis(&rest[1..7])),
+            _ => String::new(),
+        }
+    }
+
+    fn choice(
+        &mut self,
+        name: &str,
+        choices: &[(u16, &'static str, Schema)],
+        type_length: TypeLength,
+        depth: usize,
+    ) {
+        self.list_header(name, type_length, " (choice)", depth);
+        let start = self.position;
+        let Some(type_length) = self.read("tag", depth + 1) else {
+            return;
+        };
+        if type_length.element_type != ElementType::Unsigned || type_length.length > 2 {
+            self.element("tag", &Value, depth + 1);
+            return self.element("value", &Any, depth + 1);
+        }
+        let value =
+            &self.body[start + type_length.size..start + type_length.size + type_length.length];
+        let tag = value.iter().fold(0u16, |tag, x| (tag << 8) | *x as u16);
+        self.position = start + type_length.size + type_length.length;
+        match choices.iter().find(|(choice, _, _)| *choice == tag) {
+            Some((_, choice, schema)) => {
+                let text = format!("tag: {} = {:#06x} {}", describe(type_length), tag, choice);
+                self.annotate(start..self.position, depth + 1, text);
+                self.element(choice, schema, depth + 1);
+            }
+            None => {
+                let text = format!("tag: {} = {:#06x}, unknown", describe(type_length), tag);
+                self.annotate(start..self.position, depth + 1, text);
+                self.element("value", &Any, depth + 1);
+            }
+        }
+    }
+
+    fn primitive(&mut self, name: &str, schema: &Schema, type_length: TypeLength, depth: usize) {
+        let start = self.position;
+        let value_start = start + type_length.size;
+        self.position = value_start + type_length.length;
+        let value = &self.body[value_start..self.position];
+        if self.body[start] == 0x00 {
+            let text = format!("{}: end of message marker", name);
+            return self.annotate(start..self.position, depth, text);
+        }
+        let shown = match (schema, type_length.element_type) {
+            (ObisNumber, ElementType::OctetString) => display_obis(value),
+            (_, ElementType::OctetString) => display_octets(value),
+            (Unit, ElementType::Unsigned) => {
+                let code = to_unsigned(value).and_then(|code| u8::try_from(code).ok());
+                match code.and_then(|code| Some((code, unit::symbol(code)?))) {
+                    Some((code, symbol)) => format!("{} ({})", code, symbol),
+                    None => display_unsigned(value),
+                }
+            }
+            (_, ElementType::Unsigned) => display_unsigned(value),
+            (_, ElementType::Signed) => match to_signed(value) {
+                Some(value) => format!("{}", value),
+                None => Hex(value).to_string(),
+            },
+            (_, ElementType::Boolean) => format!("{}", value.iter().any(|x| *x != 0)),
+            (_, ElementType::List) => unreachable!("lists are not primitive"),
+        };
+        self.annotate(
+            start..self.position,
+            depth,
+            format!("{}: {} = {}", name, describe(type_length), shown),
+        );
+    }
+
+    fn nested_too_deeply(&mut self, name: &str, depth: usize) {
+        match element_length(&self.body[self.position..]) {
+            Some(length) => {
+                let start = self.position;
+                self.position += length;
+                self.annotate(
+                    start..self.position,
+                    depth,
+                    format!("{}: list nested too deeply", name),
+                );
+            }
+            None => {
+                let text = format!("{}: list nested too deeply and truncated", name);
+                self.annotate(self.position..self.body.len(), depth, text);
+                self.position = self.body.len();
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// Decoded type and length of a type-length field
+fn describe(type_length: TypeLength) -> String {
+    let element_type = match type_length.element_type {
+        ElementType::List => return format!("list of {}", type_length.length),
+        ElementType::OctetString => "octet string",
+        ElementType::Boolean => "boolean",
+        ElementType::Signed => "signed",
+        ElementType::Unsigned => "unsigned",
+    };
+    match type_length.length {
+        1 => format!("{}, 1 byte", element_type),
+        length => format!("{}, {} bytes", element_type, length),
+    }
+}
+
+fn to_unsigned(value: &[u8]) -> Option<u64> {
+    (value.len() <= 8).then(|| value.iter().fold(0u64, |sum, x| (sum << 8) | *x as u64))
+}
+
+fn to_signed(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let negative = value[0] & 0x80 != 0;
+    let initial = if negative { -1i64 } else { 0 };
+    Some(value.iter().fold(initial, |sum, x| (sum << 8) | *x as i64))
+}
+
+fn display_unsigned(value: &[u8]) -> String {
+    match to_unsigned(value) {
+        Some(value) => format!("{}", value),
+        None => Hex(value).to_string(),
+    }
+}
+
+fn display_obis(value: &[u8]) -> String {
+    match Obis::from_number(value) {
+        Some(obis) => format!("{} {}", ObisNotation(value), obis.name()),
+        None => format!("{}", ObisNotation(value)),
+    }
+}
+
+/// Printable octet strings as hex followed by the text
+fn display_octets(value: &[u8]) -> String {
+    if value.iter().all(|x| x.is_ascii_graphic() || *x == b' ') {
+        format!("{} \"{}\"", Hex(value), String::from_utf8_lossy(value))
+    } else {
+        Hex(value).to_string()
+    }
+}
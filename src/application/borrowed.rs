@@ -0,0 +1,272 @@
+//! Domain types borrowing octet strings from the parsed buffer
+//!
+//! Mirrors [crate::application::domain], but byte fields are slices into the
+//! input, so parsing only allocates for lists. Use [SmlMessages::into_owned]
+//! to keep the messages beyond the lifetime of the buffer.
+//! ```
+//! use hackdose_sml_parser::application::parser::parse_body_borrowed;
+//!
+//! # let body: &[u8] = &[];
+//! let messages = parse_body_borrowed(body).unwrap();
+//! let owned = messages.into_owned();
+//! ```
+use alloc::vec::Vec;
+use serde::Serialize;
+
+use crate::application::domain::{self, Scale};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SmlMessages<'a> {
+    pub messages: Vec<SmlMessageEnvelope<'a>>,
+}
+
+/// A single SML message including its header
+#[derive(PartialEq, Debug, Clone)]
+pub struct SmlMessage<'a> {
+    pub transaction_id: &'a [u8],
+    pub group_no: u8,
+    pub body: SmlMessageEnvelope<'a>,
+}
+
+/// Body of an SML message
+///
+/// New message types may be added in minor releases.
+#[derive(PartialEq, Debug, Clone)]
+#[non_exhaustive]
+pub enum SmlMessageEnvelope<'a> {
+    GetOpenResponse(GetOpenResponseBody<'a>),
+    GetListResponse(GetListResponseBody<'a>),
+    GetProcParameterResponse(GetProcParameterResponseBody<'a>),
+    AttentionResponse(AttentionResponseBody<'a>),
+    GetCloseResponse,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct GetOpenResponseBody<'a> {
+    pub server_id: &'a [u8],
+    pub req_file_id: &'a [u8],
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct GetListResponseBody<'a> {
+    pub server_id: &'a [u8],
+    pub list_name: &'a [u8],
+    pub value_list: Vec<SmlListEntry<'a>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct GetProcParameterResponseBody<'a> {
+    pub server_id: &'a [u8],
+    pub parameter_tree_path: Vec<&'a [u8]>,
+    pub parameter_tree: SmlTree<'a>,
+}
+
+/// Error or acknowledgement sent in reply to a request
+#[derive(PartialEq, Debug, Clone)]
+pub struct AttentionResponseBody<'a> {
+    pub server_id: &'a [u8],
+    /// attention number, e.g. `81 81 C7 C7 FE 00` for "Error"
+    pub attention_no: &'a [u8],
+    pub attention_msg: &'a [u8],
+    pub attention_details: Option<SmlTree<'a>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SmlTree<'a> {
+    pub parameter_name: &'a [u8],
+    pub parameter_value: Option<ProcParValue<'a>>,
+    pub child_list: Vec<SmlTree<'a>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ProcParValue<'a> {
+    Value(AnyValue<'a>),
+    /// seconds, either as index or as unix timestamp
+    Time(u32),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SmlListEntry<'a> {
+    pub object_name: &'a [u8],
+    pub status: Option<u32>,
+    pub value_time: Option<ValueTime<'a>>,
+    pub unit: Option<u8>,
+    pub scaler: Option<i8>,
+    pub value: AnyValue<'a>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ValueTime<'a> {
+    OctetString(&'a [u8]),
+    Time(domain::SmlTime),
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub enum AnyValue<'a> {
+    Unsigned(usize),
+    Signed(isize),
+    String(&'a [u8]),
+}
+
+impl SmlMessages<'_> {
+    pub fn into_owned(self) -> domain::SmlMessages {
+        domain::SmlMessages {
+            messages: self
+                .messages
+                .into_iter()
+                .map(SmlMessageEnvelope::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl SmlMessage<'_> {
+    pub fn into_owned(self) -> domain::SmlMessage {
+        domain::SmlMessage {
+            transaction_id: self.transaction_id.to_vec(),
+            group_no: self.group_no,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+impl SmlMessageEnvelope<'_> {
+    pub fn into_owned(self) -> domain::SmlMessageEnvelope {
+        match self {
+            SmlMessageEnvelope::GetOpenResponse(body) => {
+                domain::SmlMessageEnvelope::GetOpenResponse(body.into_owned())
+            }
+            SmlMessageEnvelope::GetListResponse(body) => {
+                domain::SmlMessageEnvelope::GetListResponse(body.into_owned())
+            }
+            SmlMessageEnvelope::GetProcParameterResponse(body) => {
+                domain::SmlMessageEnvelope::GetProcParameterResponse(body.into_owned())
+            }
+            SmlMessageEnvelope::AttentionResponse(body) => {
+                domain::SmlMessageEnvelope::AttentionResponse(body.into_owned())
+            }
+            SmlMessageEnvelope::GetCloseResponse => domain::SmlMessageEnvelope::GetCloseResponse,
+        }
+    }
+}
+
+impl GetOpenResponseBody<'_> {
+    pub fn into_owned(self) -> domain::GetOpenResponseBody {
+        domain::GetOpenResponseBody {
+            server_id: self.server_id.to_vec(),
+            req_file_id: self.req_file_id.to_vec(),
+        }
+    }
+}
+
+impl GetListResponseBody<'_> {
+    pub fn into_owned(self) -> domain::GetListResponseBody {
+        domain::GetListResponseBody {
+            server_id: self.server_id.to_vec(),
+            list_name: self.list_name.to_vec(),
+            value_list: self
+                .value_list
+                .into_iter()
+                .map(SmlListEntry::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl GetProcParameterResponseBody<'_> {
+    pub fn into_owned(self) -> domain::GetProcParameterResponseBody {
+        domain::GetProcParameterResponseBody {
+            server_id: self.server_id.to_vec(),
+            parameter_tree_path: self
+                .parameter_tree_path
+                .into_iter()
+                .map(<[u8]>::to_vec)
+                .collect(),
+            parameter_tree: self.parameter_tree.into_owned(),
+        }
+    }
+}
+
+impl AttentionResponseBody<'_> {
+    pub fn into_owned(self) -> domain::AttentionResponseBody {
+        domain::AttentionResponseBody {
+            server_id: self.server_id.to_vec(),
+            attention_no: self.attention_no.to_vec(),
+            attention_msg: self.attention_msg.to_vec(),
+            attention_details: self.attention_details.map(SmlTree::into_owned),
+        }
+    }
+}
+
+impl SmlTree<'_> {
+    pub fn into_owned(self) -> domain::SmlTree {
+        domain::SmlTree {
+            parameter_name: self.parameter_name.to_vec(),
+            parameter_value: self.parameter_value.map(ProcParValue::into_owned),
+            child_list: self
+                .child_list
+                .into_iter()
+                .map(SmlTree::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl ProcParValue<'_> {
+    pub fn into_owned(self) -> domain::ProcParValue {
+        match self {
+            ProcParValue::Value(value) => domain::ProcParValue::Value(value.into_owned()),
+            ProcParValue::Time(time) => domain::ProcParValue::Time(time),
+        }
+    }
+}
+
+impl SmlListEntry<'_> {
+    /// The numeric value with its scaler applied, `None` for octet strings
+    pub fn scaled_value(&self) -> Option<f64> {
+        let value = match self.value {
+            AnyValue::Unsigned(v) => v as f64,
+            AnyValue::Signed(v) => v as f64,
+            AnyValue::String(_) => return None,
+        };
+        Some(value.scale(self.scaler.unwrap_or(0)))
+    }
+
+    /// The time of the value if the meter sent one
+    pub fn time(&self) -> Option<domain::SmlTime> {
+        match self.value_time {
+            Some(ValueTime::Time(time)) => Some(time),
+            _ => None,
+        }
+    }
+
+    pub fn into_owned(self) -> domain::SmlListEntry {
+        domain::SmlListEntry {
+            object_name: self.object_name.to_vec(),
+            status: self.status,
+            value_time: self.value_time.map(ValueTime::into_owned),
+            unit: self.unit,
+            scaler: self.scaler,
+            value: self.value.into_owned(),
+        }
+    }
+}
+
+impl ValueTime<'_> {
+    pub fn into_owned(self) -> domain::ValueTime {
+        match self {
+            ValueTime::OctetString(value) => domain::ValueTime::OctetString(value.to_vec()),
+            ValueTime::Time(time) => domain::ValueTime::Time(time),
+        }
+    }
+}
+
+impl AnyValue<'_> {
+    pub fn into_owned(self) -> domain::AnyValue {
+        match self {
+            AnyValue::Unsigned(value) => domain::AnyValue::Unsigned(value),
+            AnyValue::Signed(value) => domain::AnyValue::Signed(value),
+            AnyValue::String(value) => domain::AnyValue::String(value.to_vec()),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use serde::Serialize;
 
 #[derive(PartialEq, Debug)]
@@ -5,10 +6,24 @@ pub struct SmlMessages {
     pub messages: Vec<SmlMessageEnvelope>,
 }
 
+/// A single SML message including its header
 #[derive(PartialEq, Debug, Clone)]
+pub struct SmlMessage {
+    pub transaction_id: Vec<u8>,
+    pub group_no: u8,
+    pub body: SmlMessageEnvelope,
+}
+
+/// Body of an SML message
+///
+/// New message types may be added in minor releases.
+#[derive(PartialEq, Debug, Clone)]
+#[non_exhaustive]
 pub enum SmlMessageEnvelope {
     GetOpenResponse(GetOpenResponseBody),
     GetListResponse(GetListResponseBody),
+    GetProcParameterResponse(GetProcParameterResponseBody),
+    AttentionResponse(AttentionResponseBody),
     GetCloseResponse,
 }
 
@@ -25,16 +40,84 @@ pub struct GetListResponseBody {
     pub value_list: Vec<SmlListEntry>,
 }
 
+#[derive(PartialEq, Debug, Clone)]
+pub struct GetProcParameterResponseBody {
+    pub server_id: Vec<u8>,
+    pub parameter_tree_path: Vec<Vec<u8>>,
+    pub parameter_tree: SmlTree,
+}
+
+/// Error or acknowledgement sent in reply to a request
+#[derive(PartialEq, Debug, Clone)]
+pub struct AttentionResponseBody {
+    pub server_id: Vec<u8>,
+    /// attention number, e.g. `81 81 C7 C7 FE 00` for "Error"
+    pub attention_no: Vec<u8>,
+    pub attention_msg: Vec<u8>,
+    pub attention_details: Option<SmlTree>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SmlTree {
+    pub parameter_name: Vec<u8>,
+    pub parameter_value: Option<ProcParValue>,
+    pub child_list: Vec<SmlTree>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ProcParValue {
+    Value(AnyValue),
+    /// seconds, either as index or as unix timestamp
+    Time(u32),
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct SmlListEntry {
     pub object_name: Vec<u8>,
     pub status: Option<u32>,
-    pub value_time: Vec<u8>,
+    pub value_time: Option<ValueTime>,
     pub unit: Option<u8>,
     pub scaler: Option<i8>,
     pub value: AnyValue,
 }
 
+impl SmlListEntry {
+    /// The numeric value with its scaler applied, `None` for octet strings
+    pub fn scaled_value(&self) -> Option<f64> {
+        let value = match self.value {
+            AnyValue::Unsigned(v) => v as f64,
+            AnyValue::Signed(v) => v as f64,
+            AnyValue::String(_) => return None,
+        };
+        Some(value.scale(self.scaler.unwrap_or(0)))
+    }
+
+    /// The time of the value if the meter sent one
+    pub fn time(&self) -> Option<SmlTime> {
+        match self.value_time {
+            Some(ValueTime::Time(time)) => Some(time),
+            _ => None,
+        }
+    }
+}
+
+/// valTime of a list entry
+#[derive(PartialEq, Debug, Clone)]
+pub enum ValueTime {
+    /// octet string as sent by older meters
+    OctetString(Vec<u8>),
+    Time(SmlTime),
+}
+
+/// Time as sent in SML_Time
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SmlTime {
+    /// seconds since an arbitrary point, e.g. the power-up of the meter
+    SecIndex(u32),
+    /// seconds since the unix epoch
+    Timestamp(u32),
+}
+
 #[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum AnyValue {
     Unsigned(usize),
@@ -49,16 +132,132 @@ pub trait Scale {
 
 impl Scale for AnyValue {
     fn scale(&self, scaler: i8) -> Self {
+        let factor = 10usize.saturating_pow(scaler.unsigned_abs() as u32);
         match self {
-            AnyValue::Unsigned(v) => {
-                let scaler = scaler;
-                AnyValue::Unsigned((10f64.powf(scaler as f64) * *v as f64) as usize)
-            }
+            AnyValue::Unsigned(v) if scaler >= 0 => AnyValue::Unsigned(v.saturating_mul(factor)),
+            AnyValue::Unsigned(v) => AnyValue::Unsigned(v / factor),
             AnyValue::Signed(v) => {
-                let scaler = scaler;
-                AnyValue::Signed((10f64.powf(scaler as f64) * *v as f64) as isize)
+                let factor = isize::try_from(factor).unwrap_or(isize::MAX);
+                if scaler >= 0 {
+                    AnyValue::Signed(v.saturating_mul(factor))
+                } else {
+                    AnyValue::Signed(v / factor)
+                }
             }
             AnyValue::String(v) => AnyValue::String(v.clone()),
         }
     }
 }
+
+impl Scale for f64 {
+    fn scale(&self, scaler: i8) -> Self {
+        let factor = (0..scaler.unsigned_abs()).fold(1.0, |factor, _| factor * 10.0);
+        if scaler >= 0 {
+            self * factor
+        } else {
+            self / factor
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Builder of a list response for tests, see [ListResponse::value]
+    pub(crate) struct ListResponse(GetListResponseBody);
+
+    impl ListResponse {
+        pub(crate) fn new(server_id: &[u8]) -> Self {
+            Self(GetListResponseBody {
+                server_id: server_id.to_vec(),
+                list_name: vec![],
+                value_list: vec![],
+            })
+        }
+
+        /// Add an entry without status, time, unit and scaler
+        ///
+        /// The other methods set the fields of the entry added last.
+        pub(crate) fn value(mut self, object_name: &[u8], value: AnyValue) -> Self {
+            self.0.value_list.push(SmlListEntry {
+                object_name: object_name.to_vec(),
+                status: None,
+                value_time: None,
+                unit: None,
+                scaler: None,
+                value,
+            });
+            self
+        }
+
+        pub(crate) fn unit(mut self, unit: u8) -> Self {
+            self.last().unit = Some(unit);
+            self
+        }
+
+        pub(crate) fn scaler(mut self, scaler: i8) -> Self {
+            self.last().scaler = Some(scaler);
+            self
+        }
+
+        pub(crate) fn status(mut self, status: Option<u32>) -> Self {
+            self.last().status = status;
+            self
+        }
+
+        pub(crate) fn time(mut self, time: ValueTime) -> Self {
+            self.last().value_time = Some(time);
+            self
+        }
+
+        fn last(&mut self) -> &mut SmlListEntry {
+            self.0.value_list.last_mut().expect("an entry to modify")
+        }
+
+        pub(crate) fn body(self) -> GetListResponseBody {
+            self.0
+        }
+
+        pub(crate) fn envelope(self) -> SmlMessageEnvelope {
+            SmlMessageEnvelope::GetListResponse(self.0)
+        }
+
+        /// Messages consisting of the list response only
+        pub(crate) fn messages(self) -> SmlMessages {
+            SmlMessages {
+                messages: vec![self.envelope()],
+            }
+        }
+    }
+
+    fn entry(scaler: Option<i8>, value: AnyValue) -> SmlListEntry {
+        SmlListEntry {
+            object_name: vec![1, 0, 1, 8, 0, 255],
+            status: None,
+            value_time: None,
+            unit: Some(30),
+            scaler,
+            value,
+        }
+    }
+
+    #[test]
+    pub fn applies_scaler() {
+        assert_eq!(
+            entry(Some(-1), AnyValue::Unsigned(12345)).scaled_value(),
+            Some(1234.5)
+        );
+        assert_eq!(
+            entry(Some(2), AnyValue::Signed(-3)).scaled_value(),
+            Some(-300.0)
+        );
+        assert_eq!(entry(None, AnyValue::Signed(7)).scaled_value(), Some(7.0));
+        assert_eq!(
+            entry(Some(0), AnyValue::String(vec![0x42])).scaled_value(),
+            None
+        );
+        assert_eq!(12.5.scale(-1), 1.25);
+        assert_eq!(AnyValue::Unsigned(125).scale(-1), AnyValue::Unsigned(12));
+    }
+}
@@ -1,3 +1,7 @@
+pub mod borrowed;
 pub mod domain;
 pub mod obis;
 pub mod parser;
+pub mod profile;
+pub mod tlv;
+pub mod unit;
@@ -1,22 +1,9 @@
-use std::collections::HashMap;
-
 // cf. https://www.promotic.eu/en/pmdoc/Subsystems/Comm/PmDrivers/IEC62056_OBIS.htm
-use enum_iterator::all;
-use lazy_static::lazy_static;
-
-// HashMap<[u8;6], Obis>
-lazy_static! {
-    static ref SOURCE: HashMap<&'static [u8], Obis> = {
-        let mut a = HashMap::new();
-        for obis in all::<Obis>() {
-            a.insert(obis.clone().obis_number(), obis);
-        }
-        a
-    };
-}
+use core::fmt::{self, Display};
+
 macro_rules! generate_obis {
 
-     ($( ($x:ident, $y:expr, $l:literal) ),*) => {
+     ($( ($x:ident, &[$($y:literal),+], $l:literal) ),*) => {
         #[derive(serde::Serialize, serde::Deserialize, enum_iterator::Sequence, Eq, PartialEq, Hash, Clone)]
         #[non_exhaustive]
         pub enum Obis {
@@ -30,14 +17,47 @@ macro_rules! generate_obis {
              pub fn obis_number(&self) -> &'static [u8] {
                  match self {
                     $(
-                        Self:: $x => $y,
+                        Self:: $x => &[$($y),+],
                     )*
                  }
              }
 
              /// Find matching Obis number from six-digit number
              pub fn from_number(number: &[u8]) -> Option<Self> {
-                SOURCE.get(number).map(|x| x.clone())
+                 match number {
+                    $(
+                        [$($y),+] => Some(Self:: $x),
+                    )*
+                    _ => None,
+                 }
+             }
+
+             /// Name of the variant, e.g. `SumActiveInstantaneousPower`
+             pub fn name(&self) -> &'static str {
+                 match self {
+                    $(
+                        Self:: $x => stringify!($x),
+                    )*
+                 }
+             }
+
+             /// Find the Obis number by the name of its variant
+             pub fn from_name(name: &str) -> Option<Self> {
+                 match name {
+                    $(
+                        stringify!($x) => Some(Self:: $x),
+                    )*
+                    _ => None,
+                 }
+             }
+
+             /// Human readable description including the unit
+             pub fn description(&self) -> &'static str {
+                 match self {
+                    $(
+                        Self:: $x => $l,
+                    )*
+                 }
              }
          }
     };
@@ -220,3 +240,200 @@ generate_obis! {
     (InstantaneousPowerFactorPhaseL3, &[1, 0, 73, 7, 0,255],"Instantaneous power factor in phase L3"),
     (Frequency, &[1, 0, 14, 7, 0,255],"Frequency [Hz]")
 }
+
+/// Phase of a three-phase supply
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Phase {
+    L1,
+    L2,
+    L3,
+    Neutral,
+}
+
+/// Direction of the energy flow a value refers to
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Direction {
+    /// drawn from the grid, A+ or Q+
+    Import,
+    /// fed into the grid, A- or Q-
+    Export,
+    /// both directions added up, |A|
+    Absolute,
+    /// imported minus exported, A+ - A-
+    Net,
+    /// reactive energy in quadrant 1 to 4
+    Quadrant(u8),
+}
+
+/// Displays the phase as `L1`, `L2`, `L3` or `N`
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::L1 => "L1",
+            Phase::L2 => "L2",
+            Phase::L3 => "L3",
+            Phase::Neutral => "N",
+        })
+    }
+}
+
+/// Displays the direction in lower case, quadrants as `Q1` to `Q4`
+impl Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Import => f.write_str("import"),
+            Direction::Export => f.write_str("export"),
+            Direction::Absolute => f.write_str("absolute"),
+            Direction::Net => f.write_str("net"),
+            Direction::Quadrant(quadrant) => write!(f, "Q{}", quadrant),
+        }
+    }
+}
+
+impl Obis {
+    /// Phase the value is measured in, `None` for values of all phases
+    pub fn phase(&self) -> Option<Phase> {
+        match self.obis_number()[2] {
+            21..=40 => Some(Phase::L1),
+            41..=60 => Some(Phase::L2),
+            61..=80 => Some(Phase::L3),
+            91 => Some(Phase::Neutral),
+            _ => None,
+        }
+    }
+
+    /// Tariff of registers counting per tariff, `None` for totals
+    pub fn tariff(&self) -> Option<u8> {
+        let tariff = self.obis_number()[4];
+        (1..=9).contains(&tariff).then_some(tariff)
+    }
+
+    /// Direction of the energy flow, `None` for e.g. voltage or apparent power
+    pub fn direction(&self) -> Option<Direction> {
+        match quantity(self.obis_number()) {
+            1 | 3 => Some(Direction::Import),
+            2 | 4 => Some(Direction::Export),
+            15 => Some(Direction::Absolute),
+            16 => Some(Direction::Net),
+            quadrant @ 5..=8 => Some(Direction::Quadrant(quadrant - 4)),
+            _ => None,
+        }
+    }
+}
+
+/// Value group C of an electricity OBIS number without the phase, e.g. 1 for A+ in L2
+pub(crate) fn quantity(number: &[u8]) -> u8 {
+    match number[2] {
+        c @ 21..=80 => (c - 1) % 20 + 1,
+        91 => 11,
+        c => c,
+    }
+}
+
+/// Displays an OBIS number in the usual `A-B:C.D.E*F` notation
+///
+/// Numbers not consisting of six bytes are displayed as hex.
+/// ```
+/// use hackdose_sml_parser::application::obis::ObisNotation;
+///
+/// assert_eq!(ObisNotation(&[1, 0, 1, 8, 0, 255]).to_string(), "1-0:1.8.0*255");
+/// ```
+pub struct ObisNotation<'a>(pub &'a [u8]);
+
+impl Display for ObisNotation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            [a, b, c, d, e, g] => write!(f, "{}-{}:{}.{}.{}*{}", a, b, c, d, e, g),
+            number => number.iter().try_for_each(|x| write!(f, "{:02x}", x)),
+        }
+    }
+}
+
+/// Parse an OBIS number in `A-B:C.D.E*F` notation, `*F` defaults to `*255`
+pub fn parse_notation(notation: &str) -> Option<[u8; 6]> {
+    let (rest, f) = match notation.split_once('*') {
+        Some((rest, f)) => (rest, f.parse().ok()?),
+        None => (notation, 255),
+    };
+    let (a, rest) = rest.split_once('-')?;
+    let (b, rest) = rest.split_once(':')?;
+    let mut cde = rest.split('.');
+    let mut next = || cde.next()?.parse::<u8>().ok();
+    let number = [
+        a.parse().ok()?,
+        b.parse().ok()?,
+        next()?,
+        next()?,
+        next()?,
+        f,
+    ];
+    cde.next().is_none().then_some(number)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn parses_notation() {
+        assert_eq!(parse_notation("1-0:16.7.0"), Some([1, 0, 16, 7, 0, 255]));
+        assert_eq!(
+            parse_notation("129-129:199.130.3*255"),
+            Some([129, 129, 199, 130, 3, 255])
+        );
+        assert_eq!(parse_notation("1-0:16.7"), None);
+        assert_eq!(parse_notation("1-0:16.7.0.1"), None);
+        assert_eq!(parse_notation("SumActiveInstantaneousPower"), None);
+    }
+
+    #[test]
+    pub fn derives_phase_tariff_and_direction() {
+        let obis = Obis::NegativeActiveInstantaneousPowerPhaseL2;
+        assert_eq!(obis.phase(), Some(Phase::L2));
+        assert_eq!(obis.tariff(), None);
+        assert_eq!(obis.direction(), Some(Direction::Export));
+
+        let obis = Obis::PositiveActiveEnergyTarif2;
+        assert_eq!(obis.phase(), None);
+        assert_eq!(obis.tariff(), Some(2));
+        assert_eq!(obis.direction(), Some(Direction::Import));
+
+        assert_eq!(
+            Obis::ImportedCapacitiveReactiveEnergyQ2Total.direction(),
+            Some(Direction::Quadrant(2))
+        );
+        assert_eq!(
+            Obis::InstantaneousCurrentNeutral.phase(),
+            Some(Phase::Neutral)
+        );
+        assert_eq!(Obis::InstantaneousVoltagePhaseL3.direction(), None);
+    }
+
+    #[test]
+    pub fn displays_phase_and_direction() {
+        assert_eq!(Phase::Neutral.to_string(), "N");
+        assert_eq!(Phase::L2.to_string(), "L2");
+        assert_eq!(Direction::Export.to_string(), "export");
+        assert_eq!(Direction::Quadrant(3).to_string(), "Q3");
+    }
+
+    #[test]
+    pub fn finds_obis_by_name() {
+        let obis = Obis::from_name("SumActiveInstantaneousPower").unwrap();
+
+        assert_eq!(
+            ObisNotation(obis.obis_number()).to_string(),
+            "1-0:16.7.0*255"
+        );
+    }
+
+    #[test]
+    pub fn finds_every_obis_by_number_and_name() {
+        for obis in enum_iterator::all::<Obis>() {
+            assert!(Obis::from_number(obis.obis_number()) == Some(obis.clone()));
+            assert!(Obis::from_name(obis.name()) == Some(obis.clone()));
+        }
+        assert!(Obis::from_number(&[1, 0, 1, 8, 0]).is_none());
+        assert!(Obis::from_name("Unknown").is_none());
+    }
+}
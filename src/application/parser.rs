@@ -1,9 +1,14 @@
-use byteorder::{BigEndian, ReadBytesExt};
-use std::io::Cursor;
-
-use crate::application::domain::{
-    AnyValue, GetListResponseBody, GetOpenResponseBody, SmlListEntry, SmlMessageEnvelope,
-    SmlMessages,
+use alloc::vec;
+use alloc::vec::Vec;
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::application::{
+    borrowed::{
+        AnyValue, AttentionResponseBody, GetListResponseBody, GetOpenResponseBody,
+        GetProcParameterResponseBody, ProcParValue, SmlListEntry, SmlMessage, SmlMessageEnvelope,
+        SmlMessages, SmlTree, ValueTime,
+    },
+    domain::{self, SmlTime},
 };
 
 #[non_exhaustive]
@@ -14,24 +19,49 @@ pub enum ParseError {
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// Maximum nesting of parameter trees, deeper trees are rejected
+pub const MAX_TREE_DEPTH: usize = 32;
+
 /// Parse the body of an SML message (omitting header and footer)
-pub fn parse_body(input: &[u8]) -> ParseResult<SmlMessages> {
+pub fn parse_body(input: &[u8]) -> ParseResult<domain::SmlMessages> {
+    parse_body_borrowed(input).map(SmlMessages::into_owned)
+}
+
+/// Parse the body of an SML message, borrowing octet strings from `input`
+pub fn parse_body_borrowed(input: &[u8]) -> ParseResult<SmlMessages<'_>> {
     sml_parser::sml_body(input).map_err(|_| ParseError::Unknown)
 }
 
+/// Parse the body of an SML message keeping the message headers
+///
+/// Use this to match replies to requests by their transaction id.
+pub fn parse_body_with_headers(input: &[u8]) -> ParseResult<Vec<domain::SmlMessage>> {
+    sml_parser::sml_body_with_headers(input)
+        .map(|messages| messages.into_iter().map(SmlMessage::into_owned).collect())
+        .map_err(|_| ParseError::Unknown)
+}
+
 /// Parse the whole SML message
-pub fn parse_message(input: &[u8]) -> ParseResult<SmlMessages> {
+pub fn parse_message(input: &[u8]) -> ParseResult<domain::SmlMessages> {
+    parse_message_borrowed(input).map(SmlMessages::into_owned)
+}
+
+/// Parse the whole SML message, borrowing octet strings from `input`
+pub fn parse_message_borrowed(input: &[u8]) -> ParseResult<SmlMessages<'_>> {
     sml_parser::sml_messages(input).map_err(|_| ParseError::Unknown)
 }
 
 peg::parser! {
-    grammar sml_parser<'a>() for [u8] {
+    grammar sml_parser() for [u8] {
+
+        pub (crate) rule sml_body() -> SmlMessages<'input>
+            = a:(sml_message_envelope())* padding() { SmlMessages { messages: a } }
 
-        pub (crate) rule sml_body() -> SmlMessages
-            = a:(sml_message_envelope())* { SmlMessages { messages: a } }
+        pub (crate) rule sml_messages() -> SmlMessages<'input>
+            = header() a:(sml_message_envelope())* padding() footer() { SmlMessages { messages: a } }
 
-        pub (crate) rule sml_messages() -> SmlMessages
-            = header() a:(sml_message_envelope())* footer() { SmlMessages { messages: a } }
+        rule padding()
+            = [0x00]*<0,3>
 
         rule header() -> ()
             = ([0x1b] [0x1b] [0x1b] [0x1b] [0x01] [0x01] [0x01] [0x01])
@@ -39,28 +69,65 @@ peg::parser! {
         rule footer() -> ()
             = ([0x1b] [0x1b] [0x1b] [0x1b] [0x1a] [0..=255]*<3,3>)
 
-        rule sml_message_envelope() -> SmlMessageEnvelope
-            = [0x76] transaction_id() group_no() abort_on_error() a:sml_message_body() crc() end_of_message() { a }
+        pub (crate) rule sml_body_with_headers() -> Vec<SmlMessage<'input>>
+            = a:(sml_message())* padding() { a }
+
+        rule sml_message_envelope() -> SmlMessageEnvelope<'input>
+            = m:sml_message() { m.body }
+
+        rule sml_message() -> SmlMessage<'input>
+            = [0x76] transaction_id:transaction_id() group_no:group_no() abort_on_error() body:sml_message_body() crc() end_of_message() { SmlMessage { transaction_id, group_no, body } }
 
         rule end_of_message() = [0x00]
         rule crc() = [0x63] any_number() any_number()
 
-        rule sml_message_body() -> SmlMessageEnvelope
-            = get_open_response() / get_list_response() / get_close_response() // and more types
+        rule sml_message_body() -> SmlMessageEnvelope<'input>
+            = get_open_response() / get_list_response() / get_proc_parameter_response() / attention_response() / get_close_response() // and more types
 
-        rule get_open_response() -> SmlMessageEnvelope
+        rule get_open_response() -> SmlMessageEnvelope<'input>
             = ([0x72] [0x63] [0x01] [0x01]) [0x76] a: get_open_response_content() { SmlMessageEnvelope::GetOpenResponse(a)}
 
-        rule get_open_response_content() -> GetOpenResponseBody
-            = [0x01] [0x01] req_file_id:string() server_id:string() [0x01] [0x01] { GetOpenResponseBody { server_id: server_id, req_file_id: req_file_id }}
+        rule get_open_response_content() -> GetOpenResponseBody<'input>
+            = [0x01] [0x01] req_file_id:string() server_id:string() [0x01] [0x01] { GetOpenResponseBody { server_id, req_file_id }}
 
-        rule get_close_response() -> SmlMessageEnvelope
+        rule get_close_response() -> SmlMessageEnvelope<'input>
             = ([0x72] [0x63] [0x02] [0x01]) [0x71] get_close_response_content() { SmlMessageEnvelope::GetCloseResponse}
 
         rule get_close_response_content()
             = [0x01]
 
-        rule get_list_response() -> SmlMessageEnvelope
+        rule get_proc_parameter_response() -> SmlMessageEnvelope<'input>
+            = ([0x72] [0x63] [0x05] [0x01]) [0x73] server_id:string() parameter_tree_path:tree_path() parameter_tree:tree(0) { SmlMessageEnvelope::GetProcParameterResponse(GetProcParameterResponseBody { server_id, parameter_tree_path, parameter_tree })}
+
+        rule attention_response() -> SmlMessageEnvelope<'input>
+            = ([0x72] [0x63] [0xff] [0x01]) [0x74] server_id:string() attention_no:string() attention_msg:string() attention_details:optional_tree() { SmlMessageEnvelope::AttentionResponse(AttentionResponseBody { server_id, attention_no, attention_msg, attention_details })}
+
+        rule list_length() -> usize
+            = n:[0x70..=0x7f] { (n - 0x70) as usize }
+
+        rule tree_path() -> Vec<&'input [u8]>
+            = l:list_length() p:(string())*<{l}> { p }
+
+        rule tree(depth: usize) -> SmlTree<'input>
+            = [0x73] parameter_name:string() parameter_value:proc_par_value() child_list:child_list(depth + 1) { SmlTree { parameter_name, parameter_value, child_list }}
+
+        rule optional_tree() -> Option<SmlTree<'input>>
+            = (t:tree(0) { Some(t) }) / ([0x01] { None })
+
+        rule child_list(depth: usize) -> Vec<SmlTree<'input>>
+            = ([0x01] { vec![] }) / (tree_depth(depth) l:list_length() c:(tree(depth))*<{l}> { c })
+
+        // bound the recursion on untrusted input
+        rule tree_depth(depth: usize)
+            = {? if depth < MAX_TREE_DEPTH { Ok(()) } else { Err("tree nested too deeply") } }
+
+        rule proc_par_value() -> Option<ProcParValue<'input>>
+            = ([0x72] [0x62] [0x01] v:value() { Some(ProcParValue::Value(v)) }) / ([0x72] [0x62] [0x04] t:sml_time() { Some(ProcParValue::Time(t)) }) / ([0x01] { None })
+
+        rule sml_time() -> u32
+            = [0x72] [0x62] [0x01..=0x02] t:unsigned_32() { t }
+
+        rule get_list_response() -> SmlMessageEnvelope<'input>
             = ([0x72] [0x63] [0x07] [0x01]) [0x77] a: get_list_response_content() { SmlMessageEnvelope::GetListResponse(a)}
 
         rule list_signature()
@@ -69,49 +136,59 @@ peg::parser! {
         rule act_gateway_time()
             = ([0x01]*<0,1>)
 
-        rule get_list_response_content() -> GetListResponseBody
-            = [0x01] server_id:string() list_name:string() obscure_prefix_in_get_list_response() value_list:list_sml_value() list_signature() act_gateway_time() { GetListResponseBody { server_id: server_id, list_name: list_name, value_list: value_list }}
+        rule get_list_response_content() -> GetListResponseBody<'input>
+            = [0x01] server_id:string() list_name:string() obscure_prefix_in_get_list_response() value_list:list_sml_value() list_signature() act_gateway_time() { GetListResponseBody { server_id, list_name, value_list }}
 
         rule obscure_prefix_in_get_list_response()
             = [0x72] [0x62] [0..=255] [0x65] [0..=255] [0..=255] [0..=255] [0..=255]
 
-        rule list_sml_value1() -> Vec<SmlListEntry> = [0x71] n:(single_sml_value())*<1,1> { n }
-        rule list_sml_value2() -> Vec<SmlListEntry> = [0x72] n:(single_sml_value())*<2,2> { n }
-        rule list_sml_value3() -> Vec<SmlListEntry> = [0x73] n:(single_sml_value())*<3,3> { n }
-        rule list_sml_value4() -> Vec<SmlListEntry> = [0x74] n:(single_sml_value())*<4,4> { n }
-        rule list_sml_value5() -> Vec<SmlListEntry> = [0x75] n:(single_sml_value())*<5,5> { n }
-        rule list_sml_value6() -> Vec<SmlListEntry> = [0x76] n:(single_sml_value())*<6,6> { n }
-        rule list_sml_value7() -> Vec<SmlListEntry> = [0x77] n:(single_sml_value())*<7,7> { n }
-        rule list_sml_value8() -> Vec<SmlListEntry> = [0x78] n:(single_sml_value())*<8,8> { n }
-        rule list_sml_value9() -> Vec<SmlListEntry> = [0x79] n:(single_sml_value())*<9,9> { n }
-        rule list_sml_value10() -> Vec<SmlListEntry> = [0x7a] n:(single_sml_value())*<10,10> { n }
-        rule list_sml_value11() -> Vec<SmlListEntry> = [0x7b] n:(single_sml_value())*<11,11> { n }
-        rule list_sml_value12() -> Vec<SmlListEntry> = [0x7c] n:(single_sml_value())*<12,12> { n }
-        rule list_sml_value13() -> Vec<SmlListEntry> = [0x7d] n:(single_sml_value())*<13,13> { n }
-        rule list_sml_value14() -> Vec<SmlListEntry> = [0x7e] n:(single_sml_value())*<14,14> { n }
-        rule list_sml_value15() -> Vec<SmlListEntry> = [0x7f] n:(single_sml_value())*<15,15> { n }
-        rule list_sml_value() -> Vec<SmlListEntry> = list_sml_value1()/list_sml_value1()/list_sml_value2()/list_sml_value3()/list_sml_value4()/list_sml_value5()/list_sml_value6()/list_sml_value7()/list_sml_value8()/list_sml_value9()/list_sml_value10()/list_sml_value11()/list_sml_value12()/list_sml_value13()/list_sml_value14()/list_sml_value15()
-        rule single_sml_value() -> SmlListEntry
-            = [0x77] obj_name: string() status: optional_unsigned_32() val_time: string() unit: (optional_unsigned_8()) scaler: scaler() value: value() sml_value_signature() { SmlListEntry { object_name: obj_name, status: status, value_time: val_time, unit, scaler: scaler, value: value }}
+        rule list_sml_value1() -> Vec<SmlListEntry<'input>> = [0x71] n:(single_sml_value())*<1,1> { n }
+        rule list_sml_value2() -> Vec<SmlListEntry<'input>> = [0x72] n:(single_sml_value())*<2,2> { n }
+        rule list_sml_value3() -> Vec<SmlListEntry<'input>> = [0x73] n:(single_sml_value())*<3,3> { n }
+        rule list_sml_value4() -> Vec<SmlListEntry<'input>> = [0x74] n:(single_sml_value())*<4,4> { n }
+        rule list_sml_value5() -> Vec<SmlListEntry<'input>> = [0x75] n:(single_sml_value())*<5,5> { n }
+        rule list_sml_value6() -> Vec<SmlListEntry<'input>> = [0x76] n:(single_sml_value())*<6,6> { n }
+        rule list_sml_value7() -> Vec<SmlListEntry<'input>> = [0x77] n:(single_sml_value())*<7,7> { n }
+        rule list_sml_value8() -> Vec<SmlListEntry<'input>> = [0x78] n:(single_sml_value())*<8,8> { n }
+        rule list_sml_value9() -> Vec<SmlListEntry<'input>> = [0x79] n:(single_sml_value())*<9,9> { n }
+        rule list_sml_value10() -> Vec<SmlListEntry<'input>> = [0x7a] n:(single_sml_value())*<10,10> { n }
+        rule list_sml_value11() -> Vec<SmlListEntry<'input>> = [0x7b] n:(single_sml_value())*<11,11> { n }
+        rule list_sml_value12() -> Vec<SmlListEntry<'input>> = [0x7c] n:(single_sml_value())*<12,12> { n }
+        rule list_sml_value13() -> Vec<SmlListEntry<'input>> = [0x7d] n:(single_sml_value())*<13,13> { n }
+        rule list_sml_value14() -> Vec<SmlListEntry<'input>> = [0x7e] n:(single_sml_value())*<14,14> { n }
+        rule list_sml_value15() -> Vec<SmlListEntry<'input>> = [0x7f] n:(single_sml_value())*<15,15> { n }
+        rule list_sml_value() -> Vec<SmlListEntry<'input>> = list_sml_value1()/list_sml_value1()/list_sml_value2()/list_sml_value3()/list_sml_value4()/list_sml_value5()/list_sml_value6()/list_sml_value7()/list_sml_value8()/list_sml_value9()/list_sml_value10()/list_sml_value11()/list_sml_value12()/list_sml_value13()/list_sml_value14()/list_sml_value15()
+        rule single_sml_value() -> SmlListEntry<'input>
+            = [0x77] obj_name: string() status: status() val_time: value_time() unit: (optional_unsigned_8()) scaler: scaler() value: value() sml_value_signature() { SmlListEntry { object_name: obj_name, status, value_time: val_time, unit, scaler, value }}
+
+        rule value_time() -> Option<ValueTime<'input>>
+            = (t:typed_sml_time() { Some(ValueTime::Time(t)) }) / (v:string() { (!v.is_empty()).then_some(ValueTime::OctetString(v)) })
+
+        rule typed_sml_time() -> SmlTime
+            = [0x72] [0x62] [0x01] t:unsigned_32() { SmlTime::SecIndex(t) } / [0x72] [0x62] [0x02] t:unsigned_32() { SmlTime::Timestamp(t) }
+
+        rule status() -> Option<u32>
+            = (v:unsigned_8() { Some(v as u32) }) / (v:unsigned_16() { Some(v as u32) }) / optional_unsigned_32()
 
         rule scaler() -> Option<i8>
             = optional_signed_8()
 
-        rule value() -> AnyValue
+        rule value() -> AnyValue<'input>
             = arbitrary()
 
         rule sml_value_signature()
             = [0x01]
 
-        rule arbitrary() -> AnyValue =
+        rule arbitrary() -> AnyValue<'input> =
             (v:string() { AnyValue::String(v)}) / (v:unsigned_16() { AnyValue::Unsigned(v as usize)}) / (v:signed_16() { AnyValue::Signed(v as isize)}) /
-            (v:signed_64() { AnyValue::Signed(v as isize)}) / (v:signed_32() { AnyValue::Signed(v as isize)}) / (v:unsigned_32() { AnyValue::Unsigned(v as usize)})
+            (v:signed_64() {? isize::try_from(v).map(AnyValue::Signed).or(Err("value fitting isize")) }) / (v:signed_32() { AnyValue::Signed(v as isize)}) / (v:unsigned_32() { AnyValue::Unsigned(v as usize)}) /
+            (v:unsigned_8() { AnyValue::Unsigned(v as usize)}) / (v:signed_8() { AnyValue::Signed(v as isize)}) / (v:unsigned_64() {? usize::try_from(v).map(AnyValue::Unsigned).or(Err("value fitting usize")) })
 
-        rule transaction_id()
-            =(string4())
+        rule transaction_id() -> &'input [u8]
+            = string()
 
-        rule group_no()
-            =([0x62] any_number())
+        rule group_no() -> u8
+            = [0x62] n:any_number() { n }
 
         rule abort_on_error()
             =([0x62] [0x00])
@@ -123,69 +200,29 @@ peg::parser! {
             = ([0..=255])
 
         rule unsigned_16() -> u16
-            = [0x63] n:$([0..=255]*<2,2>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_u16::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x63] n:$([0..=255]*<2,2>) { BigEndian::read_u16(n) }
 
         rule unsigned_32() -> u32
-            = [0x65] n:$([0..=255]*<4,4>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_u32::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x65] n:$([0..=255]*<4,4>) { BigEndian::read_u32(n) }
 
         rule signed_32() -> i32
-            = [0x55] n:$([0..=255]*<4,4>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_i32::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x55] n:$([0..=255]*<4,4>) { BigEndian::read_i32(n) }
 
         rule unsigned_64() -> u64
-            = [0x69] n:$([0..=255]*<8,8>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_u64::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x69] n:$([0..=255]*<8,8>) { BigEndian::read_u64(n) }
 
 
         rule signed_64() -> i64
-            = [0x59] n:$([0..=255]*<8,8>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_i64::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x59] n:$([0..=255]*<8,8>) { BigEndian::read_i64(n) }
 
         pub rule signed_16() -> i16
-            = [0x53] n:$([0..=255]*<2,2>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_i16::<BigEndian>().unwrap();
-                    res
-                }
-            }
+            = [0x53] n:$([0..=255]*<2,2>) { BigEndian::read_i16(n) }
 
         rule signed_8() -> i8
-            = [0x52] n:$([0..=255]*<1,1>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_i8().unwrap();
-                    res
-                }
-            }
+            = [0x52] n:$([0..=255]*<1,1>) { n[0] as i8 }
 
         rule unsigned_8() -> u8
-            = [0x62] n:$([0..=255]*<1,1>) { {
-                    let mut rdr = Cursor::new(n);
-                    let res = rdr.read_u8().unwrap();
-                    res
-                }
-            }
+            = [0x62] n:$([0..=255]*<1,1>) { n[0] }
 
         rule optional_signed_16() -> Option<i16>
             = (v:signed_16() { Some(v) }) / ( [0x01] { None })
@@ -202,56 +239,56 @@ peg::parser! {
         rule optional_unsigned_32() -> Option<u32>
             = (v:unsigned_32() { Some(v) }) / ( [0x01] { None })
 
-        rule string0() -> Vec<u8> = [0x01] n:(any_number())*<0,0> { n }
-        rule string1() -> Vec<u8> = [0x02] n:(any_number())*<1,1> { n }
-        rule string2() -> Vec<u8> = [0x03] n:(any_number())*<2,2> { n }
-        rule string3() -> Vec<u8> = [0x04] n:(any_number())*<3,3> { n }
-        rule string4() -> Vec<u8> = [0x05] n:(any_number())*<4,4> { n }
-        rule string5() -> Vec<u8> = [0x06] n:(any_number())*<5,5> { n }
-        rule string6() -> Vec<u8> = [0x07] n:(any_number())*<6,6> { n }
-        rule string7() -> Vec<u8> = [0x08] n:(any_number())*<7,7> { n }
-        rule string8() -> Vec<u8> = [0x09] n:(any_number())*<8,8> { n }
-        rule string9() -> Vec<u8> = [0x0a] n:(any_number())*<9,9> { n }
-        rule string10() -> Vec<u8> = [0x0b] n:(any_number())*<10,10> { n }
-        rule string11() -> Vec<u8> = [0x0c] n:(any_number())*<11,11> { n }
-        rule string12() -> Vec<u8> = [0x0d] n:(any_number())*<12,12> { n }
-        rule string13() -> Vec<u8> = [0x0e] n:(any_number())*<13,13> { n }
-        rule string14() -> Vec<u8> = [0x0f] n:(any_number())*<14,14> { n }
-        rule string15() -> Vec<u8> = [0x10] n:(any_number())*<15,15> { n }
-        rule string16() -> Vec<u8> = [0x11] n:(any_number())*<16,16> { n }
-        rule string17() -> Vec<u8> = [0x81] [0x03] n:(any_number())*<17,17> { n }
-        rule string18() -> Vec<u8> = [0x81] [0x04] n:(any_number())*<18,18> { n }
-        rule string19() -> Vec<u8> = [0x81] [0x05] n:(any_number())*<19,19> { n }
-        rule string20() -> Vec<u8> = [0x81] [0x06] n:(any_number())*<20,20> { n }
-        rule string21() -> Vec<u8> = [0x81] [0x07] n:(any_number())*<21,21> { n }
-        rule string22() -> Vec<u8> = [0x81] [0x08] n:(any_number())*<22,22> { n }
-        rule string23() -> Vec<u8> = [0x81] [0x09] n:(any_number())*<23,23> { n }
-        rule string24() -> Vec<u8> = [0x81] [0x0a] n:(any_number())*<24,24> { n }
-        rule string25() -> Vec<u8> = [0x81] [0x0b] n:(any_number())*<25,25> { n }
-        rule string26() -> Vec<u8> = [0x81] [0x0c] n:(any_number())*<26,26> { n }
-        rule string27() -> Vec<u8> = [0x81] [0x0d] n:(any_number())*<27,27> { n }
-        rule string28() -> Vec<u8> = [0x81] [0x0e] n:(any_number())*<28,28> { n }
-        rule string29() -> Vec<u8> = [0x81] [0x0f] n:(any_number())*<29,29> { n }
-        rule string30() -> Vec<u8> = [0x82] [0x00] n:(any_number())*<30,30> { n }
-        rule string31() -> Vec<u8> = [0x82] [0x01] n:(any_number())*<31,31> { n }
-        rule string32() -> Vec<u8> = [0x82] [0x02] n:(any_number())*<32,32> { n }
-        rule string33() -> Vec<u8> = [0x82] [0x03] n:(any_number())*<33,33> { n }
-        rule string34() -> Vec<u8> = [0x82] [0x04] n:(any_number())*<34,34> { n }
-        rule string35() -> Vec<u8> = [0x82] [0x05] n:(any_number())*<35,35> { n }
-        rule string36() -> Vec<u8> = [0x82] [0x06] n:(any_number())*<36,36> { n }
-        rule string37() -> Vec<u8> = [0x82] [0x07] n:(any_number())*<37,37> { n }
-        rule string38() -> Vec<u8> = [0x82] [0x08] n:(any_number())*<38,38> { n }
-        rule string39() -> Vec<u8> = [0x82] [0x09] n:(any_number())*<39,39> { n }
-        rule string40() -> Vec<u8> = [0x82] [0x0a] n:(any_number())*<40,40> { n }
-        rule string41() -> Vec<u8> = [0x82] [0x0b] n:(any_number())*<41,41> { n }
-        rule string42() -> Vec<u8> = [0x82] [0x0c] n:(any_number())*<42,42> { n }
-        rule string43() -> Vec<u8> = [0x82] [0x0d] n:(any_number())*<43,43> { n }
-        rule string44() -> Vec<u8> = [0x82] [0x0e] n:(any_number())*<44,44> { n }
-        rule string45() -> Vec<u8> = [0x82] [0x0f] n:(any_number())*<45,45> { n }
-        rule string46() -> Vec<u8> = [0x83] [0x00] n:(any_number())*<46,46> { n }
-        rule string47() -> Vec<u8> = [0x83] [0x01] n:(any_number())*<47,47> { n }
-        rule string48() -> Vec<u8> = [0x83] [0x02] n:(any_number())*<48,48> { n }
-        rule string() -> Vec<u8> = string0()/string1()/string2()/string3()/string4()/string5()/string6()/string7()/string8()/string9()/string10()/string11()/string12()/string13()/string14()/string15()/string16()/string17()/string18()/string19()/string20()/string21()/string22()/string23()/string24()/string25()/string26()/string27()/string28()/string29()/string30()/string31()/string32()/string33()/string34()/string35()/string36()/string37()/string38()/string39()/string40()/string41()/string42()/string43()/string44()/string45()/string46()/string47()/string48()
+        rule string0() -> &'input [u8] = [0x01] n:$([_]*<0,0>) { n }
+        rule string1() -> &'input [u8] = [0x02] n:$([_]*<1,1>) { n }
+        rule string2() -> &'input [u8] = [0x03] n:$([_]*<2,2>) { n }
+        rule string3() -> &'input [u8] = [0x04] n:$([_]*<3,3>) { n }
+        rule string4() -> &'input [u8] = [0x05] n:$([_]*<4,4>) { n }
+        rule string5() -> &'input [u8] = [0x06] n:$([_]*<5,5>) { n }
+        rule string6() -> &'input [u8] = [0x07] n:$([_]*<6,6>) { n }
+        rule string7() -> &'input [u8] = [0x08] n:$([_]*<7,7>) { n }
+        rule string8() -> &'input [u8] = [0x09] n:$([_]*<8,8>) { n }
+        rule string9() -> &'input [u8] = [0x0a] n:$([_]*<9,9>) { n }
+        rule string10() -> &'input [u8] = [0x0b] n:$([_]*<10,10>) { n }
+        rule string11() -> &'input [u8] = [0x0c] n:$([_]*<11,11>) { n }
+        rule string12() -> &'input [u8] = [0x0d] n:$([_]*<12,12>) { n }
+        rule string13() -> &'input [u8] = [0x0e] n:$([_]*<13,13>) { n }
+        rule string14() -> &'input [u8] = [0x0f] n:$([_]*<14,14>) { n }
+        rule string15() -> &'input [u8] = [0x81] [0x01] n:$([_]*<15,15>) { n }
+        rule string16() -> &'input [u8] = [0x81] [0x02] n:$([_]*<16,16>) { n }
+        rule string17() -> &'input [u8] = [0x81] [0x03] n:$([_]*<17,17>) { n }
+        rule string18() -> &'input [u8] = [0x81] [0x04] n:$([_]*<18,18>) { n }
+        rule string19() -> &'input [u8] = [0x81] [0x05] n:$([_]*<19,19>) { n }
+        rule string20() -> &'input [u8] = [0x81] [0x06] n:$([_]*<20,20>) { n }
+        rule string21() -> &'input [u8] = [0x81] [0x07] n:$([_]*<21,21>) { n }
+        rule string22() -> &'input [u8] = [0x81] [0x08] n:$([_]*<22,22>) { n }
+        rule string23() -> &'input [u8] = [0x81] [0x09] n:$([_]*<23,23>) { n }
+        rule string24() -> &'input [u8] = [0x81] [0x0a] n:$([_]*<24,24>) { n }
+        rule string25() -> &'input [u8] = [0x81] [0x0b] n:$([_]*<25,25>) { n }
+        rule string26() -> &'input [u8] = [0x81] [0x0c] n:$([_]*<26,26>) { n }
+        rule string27() -> &'input [u8] = [0x81] [0x0d] n:$([_]*<27,27>) { n }
+        rule string28() -> &'input [u8] = [0x81] [0x0e] n:$([_]*<28,28>) { n }
+        rule string29() -> &'input [u8] = [0x81] [0x0f] n:$([_]*<29,29>) { n }
+        rule string30() -> &'input [u8] = [0x82] [0x00] n:$([_]*<30,30>) { n }
+        rule string31() -> &'input [u8] = [0x82] [0x01] n:$([_]*<31,31>) { n }
+        rule string32() -> &'input [u8] = [0x82] [0x02] n:$([_]*<32,32>) { n }
+        rule string33() -> &'input [u8] = [0x82] [0x03] n:$([_]*<33,33>) { n }
+        rule string34() -> &'input [u8] = [0x82] [0x04] n:$([_]*<34,34>) { n }
+        rule string35() -> &'input [u8] = [0x82] [0x05] n:$([_]*<35,35>) { n }
+        rule string36() -> &'input [u8] = [0x82] [0x06] n:$([_]*<36,36>) { n }
+        rule string37() -> &'input [u8] = [0x82] [0x07] n:$([_]*<37,37>) { n }
+        rule string38() -> &'input [u8] = [0x82] [0x08] n:$([_]*<38,38>) { n }
+        rule string39() -> &'input [u8] = [0x82] [0x09] n:$([_]*<39,39>) { n }
+        rule string40() -> &'input [u8] = [0x82] [0x0a] n:$([_]*<40,40>) { n }
+        rule string41() -> &'input [u8] = [0x82] [0x0b] n:$([_]*<41,41>) { n }
+        rule string42() -> &'input [u8] = [0x82] [0x0c] n:$([_]*<42,42>) { n }
+        rule string43() -> &'input [u8] = [0x82] [0x0d] n:$([_]*<43,43>) { n }
+        rule string44() -> &'input [u8] = [0x82] [0x0e] n:$([_]*<44,44>) { n }
+        rule string45() -> &'input [u8] = [0x82] [0x0f] n:$([_]*<45,45>) { n }
+        rule string46() -> &'input [u8] = [0x83] [0x00] n:$([_]*<46,46>) { n }
+        rule string47() -> &'input [u8] = [0x83] [0x01] n:$([_]*<47,47>) { n }
+        rule string48() -> &'input [u8] = [0x83] [0x02] n:$([_]*<48,48>) { n }
+        rule string() -> &'input [u8] = string0()/string1()/string2()/string3()/string4()/string5()/string6()/string7()/string8()/string9()/string10()/string11()/string12()/string13()/string14()/string15()/string16()/string17()/string18()/string19()/string20()/string21()/string22()/string23()/string24()/string25()/string26()/string27()/string28()/string29()/string30()/string31()/string32()/string33()/string34()/string35()/string36()/string37()/string38()/string39()/string40()/string41()/string42()/string43()/string44()/string45()/string46()/string47()/string48()
 
     }
 }
@@ -259,6 +296,12 @@ peg::parser! {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::application::borrowed;
+    use crate::application::domain::{
+        AnyValue, GetListResponseBody, GetOpenResponseBody, SmlListEntry, SmlMessageEnvelope,
+        SmlMessages, SmlTime, ValueTime,
+    };
+    use crate::encode::Encode;
     #[test]
     pub fn open() {
         //
@@ -285,7 +328,7 @@ mod test {
             /* */ 0x1a, 0x00, 0x70, 0xb2, // 1a + padding + CRC (2 bytes)
         ];
 
-        let result = sml_parser::sml_messages(&example_open);
+        let result = sml_parser::sml_messages(&example_open).map(|m| m.into_owned());
 
         assert_eq!(
             result,
@@ -343,7 +386,7 @@ mod test {
             /*      */ 0x00, // end of message
         ];
 
-        let result = sml_parser::sml_body(&example_list);
+        let result = sml_parser::sml_body(&example_list).map(|m| m.into_owned());
 
         assert_eq!(
             result,
@@ -355,7 +398,7 @@ mod test {
                         SmlListEntry {
                             object_name: vec![129, 129, 199, 130, 3, 255],
                             status: None,
-                            value_time: vec![],
+                            value_time: None,
                             unit: None,
                             scaler: None,
                             value: AnyValue::String(vec![73, 83, 75])
@@ -363,7 +406,7 @@ mod test {
                         SmlListEntry {
                             object_name: vec![1, 0, 1, 8, 0, 255],
                             status: Some(386),
-                            value_time: vec![],
+                            value_time: None,
                             unit: Some(30),
                             scaler: Some(-1),
                             value: AnyValue::Signed(0)
@@ -374,6 +417,107 @@ mod test {
         )
     }
 
+    #[test]
+    pub fn borrows_octet_strings_from_input() {
+        let example_list = [
+            0x76, 0x05, 0x01, 0xd3, 0xd7, 0xbb, 0x62, 0x00, 0x62, 0x00, // header
+            0x72, 0x63, 0x07, 0x01, 0x77, 0x01, // getListResponse
+            0x03, 0x01, 0x02, // serverId
+            0x07, 0x01, 0x00, 0x62, 0x0a, 0xff, 0xff, // listName
+            0x72, 0x62, 0x01, 0x65, 0x01, 0x8a, 0x4d, 0x15, // actSensorTime
+            0x71, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, // objName
+            0x01, 0x01, 0x62, 0x1e, 0x52, 0xff, 0x04, 0x49, 0x53, 0x4b, 0x01, // entry
+            0x01, 0x01, 0x63, 0x00, 0x00, 0x00, // signatures, crc
+        ];
+
+        let result = parse_body_borrowed(&example_list).unwrap();
+
+        let input = example_list.as_ptr_range();
+        match &result.messages[0] {
+            borrowed::SmlMessageEnvelope::GetListResponse(body) => {
+                assert_eq!(body.value_list[0].value, borrowed::AnyValue::String(b"ISK"));
+                assert!(input.contains(&body.value_list[0].object_name.as_ptr()));
+            }
+            _ => panic!("not a list response"),
+        }
+        assert_eq!(result.into_owned(), parse_body(&example_list).unwrap());
+    }
+
+    #[test]
+    pub fn parses_value_time() {
+        let example_list = [
+            0x76, 0x05, 0x01, 0xd3, 0xd7, 0xbb, 0x62, 0x00, 0x62, 0x00, // header
+            0x72, 0x63, 0x07, 0x01, 0x77, 0x01, // getListResponse
+            0x03, 0x01, 0x02, // serverId
+            0x07, 0x01, 0x00, 0x62, 0x0a, 0xff, 0xff, // listName
+            0x72, 0x62, 0x01, 0x65, 0x01, 0x8a, 0x4d, 0x15, // actSensorTime
+            0x71, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, 0x01, // objName, status
+            0x72, 0x62, 0x02, 0x65, 0x65, 0x92, 0x00, 0x80, // valTime: timestamp
+            0x62, 0x1e, 0x52, 0xff, 0x62, 0x2a, 0x01, // entry
+            0x01, 0x01, 0x63, 0x00, 0x00, 0x00, // signatures, crc
+        ];
+
+        let result = parse_body(&example_list).unwrap();
+
+        match &result.messages[0] {
+            SmlMessageEnvelope::GetListResponse(body) => {
+                let entry = &body.value_list[0];
+                assert_eq!(
+                    entry.value_time,
+                    Some(ValueTime::Time(SmlTime::Timestamp(1704067200)))
+                );
+                assert_eq!(entry.value, AnyValue::Unsigned(42));
+                let mut encoded = vec![];
+                entry.encode(&mut encoded);
+                assert_eq!(encoded, example_list[35..59]);
+            }
+            _ => panic!("not a list response"),
+        }
+    }
+
+    fn list_with_value(value: &[u8]) -> Vec<u8> {
+        let mut list = vec![
+            0x76, 0x05, 0x01, 0xd3, 0xd7, 0xbb, 0x62, 0x00, 0x62, 0x00, // header
+            0x72, 0x63, 0x07, 0x01, 0x77, 0x01, // getListResponse
+            0x03, 0x01, 0x02, // serverId
+            0x07, 0x01, 0x00, 0x62, 0x0a, 0xff, 0xff, // listName
+            0x72, 0x62, 0x01, 0x65, 0x01, 0x8a, 0x4d, 0x15, // actSensorTime
+            0x71, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, 0x01, // objName, status
+            0x01, 0x62, 0x1e, 0x52, 0xff, // valTime, unit, scaler
+        ];
+        list.extend_from_slice(value);
+        list.extend_from_slice(&[0x01, 0x01, 0x01, 0x63, 0x00, 0x00, 0x00]);
+        list
+    }
+
+    fn first_value(result: &domain::SmlMessages) -> &AnyValue {
+        match &result.messages[0] {
+            SmlMessageEnvelope::GetListResponse(body) => &body.value_list[0].value,
+            _ => panic!("not a list response"),
+        }
+    }
+
+    #[test]
+    pub fn rejects_64_bit_values_not_fitting_usize_or_isize() {
+        let unsigned = u64::MAX - 1;
+        let mut value = vec![0x69];
+        value.extend_from_slice(&unsigned.to_be_bytes());
+        let result = parse_body(&list_with_value(&value));
+        match usize::try_from(unsigned) {
+            Ok(v) => assert_eq!(first_value(&result.unwrap()), &AnyValue::Unsigned(v)),
+            Err(_) => assert!(result.is_err()),
+        }
+
+        let signed = i64::MIN + 1;
+        let mut value = vec![0x59];
+        value.extend_from_slice(&signed.to_be_bytes());
+        let result = parse_body(&list_with_value(&value));
+        match isize::try_from(signed) {
+            Ok(v) => assert_eq!(first_value(&result.unwrap()), &AnyValue::Signed(v)),
+            Err(_) => assert!(result.is_err()),
+        }
+    }
+
     #[test]
     pub fn get_close_response() {
         let example_close = vec![
@@ -392,7 +536,7 @@ mod test {
             /* */ 0x1b, 0x1b, 0x1b, 0x1b, // escape sequence
             /* */ 0x1a, 0x00, 0x70, 0xb2, // 1a + padding + CRC (2 bytes)
         ];
-        let result = sml_parser::sml_messages(&example_close);
+        let result = sml_parser::sml_messages(&example_close).map(|m| m.into_owned());
 
         assert_eq!(
             result,
@@ -402,13 +546,48 @@ mod test {
         )
     }
 
+    #[test]
+    pub fn rejects_deeply_nested_trees() {
+        // found by fuzzing: nested child lists overflowed the stack
+        let mut example_deep = vec![
+            0x76, 0x05, 0x01, 0x02, 0x03, 0x04, 0x62, 0x00, 0x62, 0x00, // header
+            0x72, 0x63, 0x05, 0x01, // getProcParameterResponse
+            0x73, 0x01, 0x71, 0x01, // serverId, parameterTreePath
+        ];
+        for _ in 0..100_000 {
+            example_deep.extend_from_slice(&[0x73, 0x01, 0x01, 0x71]);
+        }
+        example_deep.extend_from_slice(&[0x73, 0x01, 0x01, 0x01, 0x63, 0x00, 0x00, 0x00]);
+
+        let result = parse_body(&example_deep);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn accepts_nested_trees_up_to_limit() {
+        let mut example_nested = vec![
+            0x76, 0x05, 0x01, 0x02, 0x03, 0x04, 0x62, 0x00, 0x62, 0x00, // header
+            0x72, 0x63, 0x05, 0x01, // getProcParameterResponse
+            0x73, 0x01, 0x71, 0x01, // serverId, parameterTreePath
+        ];
+        for _ in 1..MAX_TREE_DEPTH {
+            example_nested.extend_from_slice(&[0x73, 0x01, 0x01, 0x71]);
+        }
+        example_nested.extend_from_slice(&[0x73, 0x01, 0x01, 0x01, 0x63, 0x00, 0x00, 0x00]);
+
+        let result = parse_body(&example_nested);
+
+        assert!(result.is_ok());
+    }
+
     // From here on: Generate "generic types", should be solved by build scripts in the future
     #[test]
     pub fn generate_strings() {
         for i in 0..=14 {
             let length = format!("{:#04x}", i + 1);
             println!(
-                "rule string{}() -> Vec<u8> = [{}] n:(any_number())*<{},{}> {{ n }}",
+                "rule string{}() -> &'input [u8] = [{}] n:$([_]*<{},{}>) {{ n }}",
                 i, length, i, i
             );
         }
@@ -418,7 +597,7 @@ mod test {
             let part_1 = format!("{:#04x}", part_1);
             let part_2 = format!("{:#04x}", part_2);
             println!(
-                "rule string{}() -> Vec<u8> = [{}] [{}] n:(any_number())*<{},{}> {{ n }}",
+                "rule string{}() -> &'input [u8] = [{}] [{}] n:$([_]*<{},{}>) {{ n }}",
                 i - 2,
                 part_1,
                 part_2,
@@ -441,7 +620,7 @@ mod test {
         for i in 1..=15 {
             let length = format!("{:#04x}", i + 0x70);
             println!(
-                "rule list_sml_value{}() -> Vec<SmlListEntry> = [{}] n:(single_sml_value())*<{},{}> {{ n }}",
+                "rule list_sml_value{}() -> Vec<SmlListEntry<'input>> = [{}] n:(single_sml_value())*<{},{}> {{ n }}",
                 i, length, i, i
             );
         }
@@ -452,6 +631,9 @@ mod test {
                 format!("{}/{}", a, b)
             });
 
-        println!("rule list_sml_value() -> Vec<SmlListEntry> = {}", strings);
+        println!(
+            "rule list_sml_value() -> Vec<SmlListEntry<'input>> = {}",
+            strings
+        );
     }
 }
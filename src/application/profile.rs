@@ -0,0 +1,491 @@
+//! Per-model corrections for meters deviating from the SML specification
+//!
+//! The grammar follows the specification (and the ISKRA meters it was developed against).
+//! Some device families emit values that parse fine but mean something else.
+//! A [MeterProfile] repairs those values, detect it once and parse with it.
+//!
+//! ```
+//! use hackdose_sml_parser::application::{parser::parse_body, profile::MeterProfile};
+//!
+//! # let body: &[u8] = &[];
+//! let profile = MeterProfile::detect(&parse_body(body).unwrap());
+//! let messages = profile.parse(body).unwrap();
+//! ```
+use crate::application::{
+    domain::{AnyValue, SmlListEntry, SmlMessageEnvelope, SmlMessages},
+    obis::Obis,
+    parser::{parse_body, ParseResult},
+    tlv::{element_length, read_type_length, ElementType},
+};
+
+/// Object name of the manufacturer identification (FLAG id, e.g. `ISK`)
+static MANUFACTURER_ID: &[u8] = &[129, 129, 199, 130, 3, 255];
+
+/// Meter device family
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum MeterProfile {
+    /// No corrections applied
+    Generic,
+    /// Iskraemeco (`ISK`): follows the specification, no corrections applied
+    Iskra,
+    /// EMH metering (`EMH`): follows the specification, no corrections applied
+    Emh,
+    /// eBZ (`EBZ`): follows the specification, no corrections applied
+    Ebz,
+    /// EasyMeter (`ESY`): some models omit the scaler of energy registers
+    EasyMeter,
+    /// Holley (`HLY`): DTZ541 emits the value list in non-standard order
+    Holley,
+    /// DZG (`DZG`): instantaneous power is sent unsigned with the sign in the top bit
+    Dzg,
+}
+
+impl MeterProfile {
+    /// Find the profile from the three-letter FLAG manufacturer id
+    pub fn from_manufacturer_id(id: &[u8]) -> Self {
+        match id {
+            b"ISK" => MeterProfile::Iskra,
+            b"EMH" => MeterProfile::Emh,
+            b"EBZ" => MeterProfile::Ebz,
+            b"ESY" => MeterProfile::EasyMeter,
+            b"HLY" => MeterProfile::Holley,
+            b"DZG" => MeterProfile::Dzg,
+            _ => MeterProfile::Generic,
+        }
+    }
+
+    /// Find the profile from a server id
+    ///
+    /// Server ids of electricity meters (DIN 43863-5) carry the FLAG id in bytes 2 to 4,
+    /// e.g. `0a 01 44 5a 47 00 ...` for DZG.
+    pub fn from_server_id(server_id: &[u8]) -> Self {
+        match server_id.get(2..5) {
+            Some(id) => Self::from_manufacturer_id(id),
+            None => MeterProfile::Generic,
+        }
+    }
+
+    /// Detect the profile from parsed messages
+    ///
+    /// Uses the server id first and falls back to the manufacturer list entry.
+    pub fn detect(messages: &SmlMessages) -> Self {
+        for message in messages.messages.iter() {
+            let (server_id, value_list) = match message {
+                SmlMessageEnvelope::GetOpenResponse(body) => (&body.server_id, None),
+                SmlMessageEnvelope::GetListResponse(body) => {
+                    (&body.server_id, Some(&body.value_list))
+                }
+                _ => continue,
+            };
+            let profile = Self::from_server_id(server_id);
+            if profile != MeterProfile::Generic {
+                return profile;
+            }
+            let manufacturer = value_list.and_then(|list| {
+                list.iter()
+                    .find(|entry| entry.object_name == MANUFACTURER_ID)
+            });
+            if let Some(SmlListEntry {
+                value: AnyValue::String(id),
+                ..
+            }) = manufacturer
+            {
+                return Self::from_manufacturer_id(id);
+            }
+        }
+        MeterProfile::Generic
+    }
+
+    /// Parse the body of an SML message and apply the corrections of this profile
+    pub fn parse(&self, body: &[u8]) -> ParseResult<SmlMessages> {
+        let mut messages = match self {
+            MeterProfile::Dzg => {
+                let mut body = body.to_vec();
+                fix_dzg_sign(&mut body);
+                parse_body(&body)?
+            }
+            _ => parse_body(body)?,
+        };
+        self.apply(&mut messages);
+        Ok(messages)
+    }
+
+    /// Apply the corrections of this profile to `messages` parsed from `body`
+    ///
+    /// Parses `body` again only for profiles whose corrections depend on the encoding.
+    pub fn correct(&self, body: &[u8], messages: &mut SmlMessages) -> ParseResult<()> {
+        match self {
+            MeterProfile::Dzg => *messages = self.parse(body)?,
+            _ => self.apply(messages),
+        }
+        Ok(())
+    }
+
+    /// Apply the corrections of this profile to all list responses
+    ///
+    /// The sign of DZG power depends on the width it was encoded with, which parsed
+    /// values do not tell; use [MeterProfile::parse] for DZG meters.
+    pub fn apply(&self, messages: &mut SmlMessages) {
+        for message in messages.messages.iter_mut() {
+            if let SmlMessageEnvelope::GetListResponse(body) = message {
+                match self {
+                    MeterProfile::EasyMeter => {
+                        body.value_list.iter_mut().for_each(fix_easymeter_scaler)
+                    }
+                    MeterProfile::Holley => fix_holley_order(&mut body.value_list),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+fn is_sum_active_power(object_name: &[u8]) -> bool {
+    matches!(
+        Obis::from_number(object_name),
+        Some(Obis::SumActiveInstantaneousPower)
+            | Some(Obis::SumActiveInstantaneousPowerPhaseL1)
+            | Some(Obis::SumActiveInstantaneousPowerPhaseL2)
+            | Some(Obis::SumActiveInstantaneousPowerPhaseL3)
+    )
+}
+
+/// DZG sends signed power as unsigned integer of the same width: retype the values
+/// of power list entries in `body` as signed
+fn fix_dzg_sign(body: &mut [u8]) {
+    let mut position = 0;
+    while let Some(offset) = body[position..]
+        .windows(8)
+        .position(|x| x[..2] == [0x77, 0x07] && is_sum_active_power(&x[2..]))
+    {
+        position += offset + 8;
+        // status, valTime, unit and scaler
+        for _ in 0..4 {
+            match element_length(&body[position..]) {
+                Some(length) => position += length,
+                None => return,
+            }
+        }
+        if let Some(type_length) = read_type_length(&body[position..]) {
+            if type_length.element_type == ElementType::Unsigned && type_length.size == 1 {
+                body[position] = body[position] & 0x0f | 0x50;
+            }
+        }
+    }
+}
+
+/// EasyMeter omits the scaler of energy registers which are sent in 0.1 Wh
+fn fix_easymeter_scaler(entry: &mut SmlListEntry) {
+    let is_energy =
+        entry.object_name.len() == 6 && entry.object_name[0] == 1 && entry.object_name[3] == 8;
+    if is_energy && entry.scaler.is_none() {
+        entry.scaler = Some(-1);
+    }
+}
+
+/// Holley sends the list entries in arbitrary order: restore manufacturer and
+/// server id first, followed by the OBIS registers in ascending order
+fn fix_holley_order(value_list: &mut [SmlListEntry]) {
+    value_list.sort_by(|a, b| {
+        let a_is_register = a.object_name.first() == Some(&1);
+        let b_is_register = b.object_name.first() == Some(&1);
+        a_is_register
+            .cmp(&b_is_register)
+            .then_with(|| a.object_name.cmp(&b.object_name))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{application::domain::GetListResponseBody, transport::SMLMessageBuilder};
+
+    /// DZG DWS7412: -200 W sent as Unsigned16 `ff 38`
+    static DZG: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x0d, 0x7b, 0x2e, 0x00, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x06, 0x7b, 0x2e, 0x00, 0x10, 0x11, 0x0b, 0x0a, 0x01, 0x44, 0x5a, 0x47, 0x00,
+        0x03, 0x85, 0x1e, 0x2c, 0x01, 0x01, 0x63, 0xb2, 0x95, 0x00, // getOpenResponse
+        0x76, 0x05, 0x0d, 0x7b, 0x2e, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x0a, 0x01, 0x44, 0x5a, 0x47, 0x00, 0x03, 0x85, 0x1e, 0x2c, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x00, 0x2f, 0x3a, 0x11, 0x75, 0x77, 0x07,
+        0x01, 0x00, 0x60, 0x32, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x04, 0x44, 0x5a, 0x47, 0x01,
+        0x77, 0x07, 0x01, 0x00, 0x00, 0x00, 0x09, 0xff, 0x01, 0x01, 0x01, 0x01, 0x0b, 0x0a, 0x01,
+        0x44, 0x5a, 0x47, 0x00, 0x03, 0x85, 0x1e, 0x2c, 0x01, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08,
+        0x00, 0xff, 0x65, 0x00, 0x1c, 0x01, 0x04, 0x01, 0x62, 0x1e, 0x52, 0xff, 0x65, 0x00, 0x36,
+        0xee, 0x80, 0x01, 0x77, 0x07, 0x01, 0x00, 0x02, 0x08, 0x00, 0xff, 0x65, 0x00, 0x1c, 0x01,
+        0x04, 0x01, 0x62, 0x1e, 0x52, 0xff, 0x65, 0x00, 0x01, 0x86, 0xa0, 0x01, 0x77, 0x07, 0x01,
+        0x00, 0x10, 0x07, 0x00, 0xff, 0x01, 0x01, 0x62, 0x1b, 0x52, 0x00, 0x63, 0xff, 0x38, 0x01,
+        0x01, 0x01, 0x63, 0xf3, 0xd7, 0x00, // getListResponse
+        0x76, 0x05, 0x0d, 0x7b, 0x2e, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0x26, 0x58, 0x00, // getCloseResponse
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x9b, 0x8e, // end
+    ];
+
+    /// EasyMeter Q3A: energy in 0.1 Wh without scaler
+    static EASYMETER: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x00, 0xa1, 0xb2, 0x00, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x06, 0xa1, 0xb2, 0x00, 0x10, 0x11, 0x0b, 0x0a, 0x01, 0x45, 0x53, 0x59, 0x11,
+        0x03, 0xa2, 0x7c, 0x11, 0x01, 0x01, 0x63, 0x9b, 0xbf, 0x00, // getOpenResponse
+        0x76, 0x05, 0x00, 0xa1, 0xb2, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x0a, 0x01, 0x45, 0x53, 0x59, 0x11, 0x03, 0xa2, 0x7c, 0x11, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x01, 0x12, 0x6c, 0x40, 0x74, 0x77, 0x07,
+        0x81, 0x81, 0xc7, 0x82, 0x03, 0xff, 0x01, 0x01, 0x01, 0x01, 0x04, 0x45, 0x53, 0x59, 0x01,
+        0x77, 0x07, 0x01, 0x00, 0x00, 0x00, 0x09, 0xff, 0x01, 0x01, 0x01, 0x01, 0x0b, 0x0a, 0x01,
+        0x45, 0x53, 0x59, 0x11, 0x03, 0xa2, 0x7c, 0x11, 0x01, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08,
+        0x00, 0xff, 0x63, 0x01, 0x82, 0x01, 0x62, 0x1e, 0x01, 0x69, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0xe2, 0x40, 0x01, 0x77, 0x07, 0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x63, 0x01, 0x82,
+        0x01, 0x62, 0x1b, 0x01, 0x55, 0x00, 0x00, 0x01, 0x2c, 0x01, 0x01, 0x01, 0x63, 0xae, 0xdb,
+        0x00, // getListResponse
+        0x76, 0x05, 0x00, 0xa1, 0xb2, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0x85, 0x57, 0x00, // getCloseResponse
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0xa2, 0x41, // end
+    ];
+
+    /// Holley DTZ541: power before energy and manufacturer last
+    static HOLLEY: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x03, 0xe8, 0x1c, 0x00, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x06, 0xe8, 0x1c, 0x00, 0x10, 0x11, 0x0b, 0x0a, 0x01, 0x48, 0x4c, 0x59, 0x02,
+        0x00, 0x01, 0x2c, 0x4e, 0x01, 0x01, 0x63, 0xc6, 0xf4, 0x00, // getOpenResponse
+        0x76, 0x05, 0x03, 0xe8, 0x1c, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x0a, 0x01, 0x48, 0x4c, 0x59, 0x02, 0x00, 0x01, 0x2c, 0x4e, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x00, 0x4a, 0x11, 0x07, 0x74, 0x77, 0x07,
+        0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x01, 0x01, 0x62, 0x1b, 0x52, 0x00, 0x53, 0x01, 0x2c,
+        0x01, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, 0x65, 0x00, 0x1c, 0x01, 0x04, 0x01,
+        0x62, 0x1e, 0x52, 0xff, 0x65, 0x00, 0x01, 0xe2, 0x40, 0x01, 0x77, 0x07, 0x01, 0x00, 0x00,
+        0x00, 0x09, 0xff, 0x01, 0x01, 0x01, 0x01, 0x0b, 0x0a, 0x01, 0x48, 0x4c, 0x59, 0x02, 0x00,
+        0x01, 0x2c, 0x4e, 0x01, 0x77, 0x07, 0x81, 0x81, 0xc7, 0x82, 0x03, 0xff, 0x01, 0x01, 0x01,
+        0x01, 0x04, 0x48, 0x4c, 0x59, 0x01, 0x01, 0x01, 0x63, 0x0f, 0x26,
+        0x00, // getListResponse
+        0x76, 0x05, 0x03, 0xe8, 0x1c, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0x7e, 0x17, 0x00, // getCloseResponse
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x7e, 0x3b, // end
+    ];
+
+    /// EMH eHZ: follows the specification, -150 W sent as Integer16
+    static EMH: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x02, 0x11, 0x0b, 0x0a, 0x01, 0x45, 0x4d, 0x48, 0x00, 0x00, 0x7a, 0xc3, 0x51,
+        0x01, 0x01, 0x63, 0x5b, 0xc6, 0x00, // getOpenResponse
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x0a, 0x01, 0x45, 0x4d, 0x48, 0x00, 0x00, 0x7a, 0xc3, 0x51, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x00, 0x00, 0x00, 0x00, 0x74, 0x77, 0x07,
+        0x81, 0x81, 0xc7, 0x82, 0x03, 0xff, 0x01, 0x01, 0x01, 0x01, 0x04, 0x45, 0x4d, 0x48, 0x01,
+        0x77, 0x07, 0x01, 0x00, 0x00, 0x00, 0x09, 0xff, 0x01, 0x01, 0x01, 0x01, 0x0b, 0x0a, 0x01,
+        0x45, 0x4d, 0x48, 0x00, 0x00, 0x7a, 0xc3, 0x51, 0x01, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08,
+        0x00, 0xff, 0x01, 0x01, 0x62, 0x1e, 0x52, 0xff, 0x65, 0x00, 0x01, 0xe2, 0x40, 0x01, 0x77,
+        0x07, 0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x01, 0x01, 0x62, 0x1b, 0x52, 0x00, 0x53, 0xff,
+        0x6a, 0x01, 0x01, 0x01, 0x63, 0x61, 0x70, 0x00, // getListResponse
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x03, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0xa8, 0xe6, 0x00, // getCloseResponse
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x77, 0xf8, // end
+    ];
+
+    /// eBZ DD3: follows the specification, -150 W sent as Integer16
+    static EBZ: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x02, 0x11, 0x0b, 0x0a, 0x01, 0x45, 0x42, 0x5a, 0x01, 0x00, 0x0d, 0x54, 0x2a,
+        0x01, 0x01, 0x63, 0x0e, 0xaa, 0x00, // getOpenResponse
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x0a, 0x01, 0x45, 0x42, 0x5a, 0x01, 0x00, 0x0d, 0x54, 0x2a, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x00, 0x00, 0x00, 0x00, 0x74, 0x77, 0x07,
+        0x81, 0x81, 0xc7, 0x82, 0x03, 0xff, 0x01, 0x01, 0x01, 0x01, 0x04, 0x45, 0x42, 0x5a, 0x01,
+        0x77, 0x07, 0x01, 0x00, 0x00, 0x00, 0x09, 0xff, 0x01, 0x01, 0x01, 0x01, 0x0b, 0x0a, 0x01,
+        0x45, 0x42, 0x5a, 0x01, 0x00, 0x0d, 0x54, 0x2a, 0x01, 0x77, 0x07, 0x01, 0x00, 0x01, 0x08,
+        0x00, 0xff, 0x01, 0x01, 0x62, 0x1e, 0x52, 0xff, 0x65, 0x00, 0x01, 0xe2, 0x40, 0x01, 0x77,
+        0x07, 0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x01, 0x01, 0x62, 0x1b, 0x52, 0x00, 0x53, 0xff,
+        0x6a, 0x01, 0x01, 0x01, 0x63, 0x78, 0xc3, 0x00, // getListResponse
+        0x76, 0x05, 0x00, 0x00, 0x00, 0x03, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0xa8, 0xe6, 0x00, // getCloseResponse
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0xd0, 0x5f, // end
+    ];
+
+    /// ISKRA MT631: server id without FLAG id, manufacturer in the list
+    static ISKRA: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, // start
+        0x76, 0x05, 0x01, 0xd3, 0xd7, 0x00, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x01, 0x01, 0x76,
+        0x01, 0x01, 0x06, 0xd3, 0xd7, 0x00, 0x10, 0x11, 0x0b, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+        0x07, 0x08, 0x09, 0x0a, 0x01, 0x01, 0x63, 0x27, 0xbb, 0x00, // getOpenResponse
+        0x76, 0x05, 0x01, 0xd3, 0xd7, 0x01, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x07, 0x01, 0x77,
+        0x01, 0x0b, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x07, 0x01, 0x00,
+        0x62, 0x0a, 0xff, 0xff, 0x72, 0x62, 0x01, 0x65, 0x01, 0x8a, 0x4d, 0x15, 0x73, 0x77, 0x07,
+        0x81, 0x81, 0xc7, 0x82, 0x03, 0xff, 0x01, 0x01, 0x01, 0x01, 0x04, 0x49, 0x53, 0x4b, 0x01,
+        0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, 0x65, 0x00, 0x00, 0x01, 0x82, 0x01, 0x62,
+        0x1e, 0x52, 0xff, 0x59, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xe2, 0x40, 0x01, 0x77, 0x07,
+        0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x65, 0x00, 0x00, 0x01, 0x82, 0x01, 0x62, 0x1b, 0x52,
+        0x00, 0x55, 0xff, 0xff, 0xff, 0x38, 0x01, 0x01, 0x01, 0x63, 0x4f, 0x92,
+        0x00, // getListResponse
+        0x76, 0x05, 0x01, 0xd3, 0xd7, 0x02, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71,
+        0x01, 0x63, 0xe7, 0x52, 0x00, // getCloseResponse
+        0x00, 0x00, // padding
+        0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x02, 0xf5, 0x00, // end
+    ];
+
+    /// Body of a complete frame
+    fn body(frame: &[u8]) -> Vec<u8> {
+        let mut builder = SMLMessageBuilder::Empty;
+        builder.record(frame);
+        match builder {
+            SMLMessageBuilder::Complete { data, .. } => data,
+            _ => panic!("incomplete frame"),
+        }
+    }
+
+    fn detect_and_parse(frame: &[u8]) -> (MeterProfile, SmlMessages) {
+        let body = body(frame);
+        let profile = MeterProfile::detect(&parse_body(&body).unwrap());
+        (profile, profile.parse(&body).unwrap())
+    }
+
+    fn value_list(messages: &SmlMessages) -> &[SmlListEntry] {
+        match &messages.messages[1] {
+            SmlMessageEnvelope::GetListResponse(GetListResponseBody { value_list, .. }) => {
+                value_list
+            }
+            _ => panic!("expected list response"),
+        }
+    }
+
+    fn entry(messages: &SmlMessages, obis: Obis) -> &SmlListEntry {
+        value_list(messages)
+            .iter()
+            .find(|entry| entry.object_name == obis.obis_number())
+            .unwrap()
+    }
+
+    #[test]
+    pub fn detects_profile_from_server_id() {
+        assert_eq!(
+            MeterProfile::from_server_id(&[0x0a, 0x01, 0x44, 0x5a, 0x47, 0x00, 0x03, 0x85]),
+            MeterProfile::Dzg
+        );
+        assert_eq!(
+            MeterProfile::from_server_id(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+            MeterProfile::Generic
+        );
+        assert_eq!(MeterProfile::from_server_id(&[0x0a]), MeterProfile::Generic);
+    }
+
+    #[test]
+    pub fn dzg_negative_power_is_signed() {
+        let (profile, messages) = detect_and_parse(DZG);
+
+        assert_eq!(profile, MeterProfile::Dzg);
+        assert_eq!(
+            entry(&messages, Obis::SumActiveInstantaneousPower).value,
+            AnyValue::Signed(-200)
+        );
+        assert_eq!(
+            entry(&messages, Obis::PositiveActiveEnergyTotal).value,
+            AnyValue::Unsigned(3600000)
+        );
+    }
+
+    #[test]
+    pub fn dzg_sign_follows_encoded_width() {
+        let power = |value: &[u8]| {
+            let mut body = vec![
+                0x77, 0x07, 0x01, 0x00, 0x10, 0x07, 0x00, 0xff, 0x65, 0x00, 0x1c, 0x01, 0x04, 0x01,
+                0x62, 0x1b, 0x52, 0x00,
+            ];
+            body.extend_from_slice(value);
+            fix_dzg_sign(&mut body);
+            body[18]
+        };
+
+        assert_eq!(power(&[0x62, 0x9c]), 0x52);
+        assert_eq!(power(&[0x63, 0xff, 0x38]), 0x53);
+        assert_eq!(power(&[0x65, 0xff, 0xff, 0xff, 0x38]), 0x55);
+        assert_eq!(power(&[0x55, 0xff, 0xff, 0xff, 0x38]), 0x55);
+    }
+
+    #[test]
+    pub fn easymeter_energy_gets_scaler() {
+        let (profile, messages) = detect_and_parse(EASYMETER);
+
+        assert_eq!(profile, MeterProfile::EasyMeter);
+        assert_eq!(
+            entry(&messages, Obis::PositiveActiveEnergyTotal).scaled_value(),
+            Some(12345.6)
+        );
+        assert_eq!(
+            entry(&messages, Obis::SumActiveInstantaneousPower).scaler,
+            None
+        );
+    }
+
+    #[test]
+    pub fn holley_list_is_reordered() {
+        let (profile, messages) = detect_and_parse(HOLLEY);
+
+        assert_eq!(profile, MeterProfile::Holley);
+        let names: Vec<_> = value_list(&messages)
+            .iter()
+            .map(|entry| entry.object_name.clone())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                vec![129, 129, 199, 130, 3, 255],
+                vec![1, 0, 0, 0, 9, 255],
+                vec![1, 0, 1, 8, 0, 255],
+                vec![1, 0, 16, 7, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    pub fn iskra_is_detected_from_manufacturer_entry_and_left_untouched() {
+        let body = body(ISKRA);
+        let (profile, messages) = detect_and_parse(ISKRA);
+
+        assert_eq!(profile, MeterProfile::Iskra);
+        assert_eq!(messages, parse_body(&body).unwrap());
+    }
+
+    #[test]
+    pub fn emh_and_ebz_are_detected_from_server_id_and_left_untouched() {
+        for (frame, expected) in [(EMH, MeterProfile::Emh), (EBZ, MeterProfile::Ebz)] {
+            let body = body(frame);
+            let (profile, messages) = detect_and_parse(frame);
+
+            assert_eq!(profile, expected);
+            assert_eq!(messages, parse_body(&body).unwrap());
+            assert_eq!(
+                entry(&messages, Obis::SumActiveInstantaneousPower).value,
+                AnyValue::Signed(-150)
+            );
+        }
+    }
+
+    #[test]
+    pub fn corrects_parsed_messages() {
+        for frame in [DZG, EASYMETER, HOLLEY, ISKRA, EMH, EBZ] {
+            let body = body(frame);
+            let mut messages = parse_body(&body).unwrap();
+            let profile = MeterProfile::detect(&messages);
+
+            profile.correct(&body, &mut messages).unwrap();
+
+            assert_eq!(messages, profile.parse(&body).unwrap());
+        }
+    }
+
+    #[test]
+    pub fn generic_profile_leaves_messages_untouched() {
+        let body = body(DZG);
+
+        let messages = MeterProfile::Generic.parse(&body).unwrap();
+
+        assert_eq!(messages, parse_body(&body).unwrap());
+        assert_eq!(
+            entry(&messages, Obis::SumActiveInstantaneousPower).value,
+            AnyValue::Unsigned(0xff38)
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Type-length fields of SML elements
+//!
+//! Allows walking SML data without knowing its schema, e.g. to find the bytes
+//! of a parsed element or to explain data the typed parser rejects.
+
+/// Type of an SML element
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ElementType {
+    OctetString,
+    Boolean,
+    Signed,
+    Unsigned,
+    List,
+}
+
+/// The type-length field at the start of an SML element
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct TypeLength {
+    pub element_type: ElementType,
+    /// number of elements for lists, otherwise bytes of the value
+    pub length: usize,
+    /// bytes of the type-length field itself
+    pub size: usize,
+}
+
+/// Read the type-length field at the start of `input`
+///
+/// Returns `None` for reserved types, truncated fields and lengths not covering
+/// the field itself. The end of message marker `00` reads as empty octet string.
+pub fn read_type_length(input: &[u8]) -> Option<TypeLength> {
+    let first = *input.first()?;
+    let element_type = match first & 0x70 {
+        0x00 => ElementType::OctetString,
+        0x40 => ElementType::Boolean,
+        0x50 => ElementType::Signed,
+        0x60 => ElementType::Unsigned,
+        0x70 => ElementType::List,
+        _ => return None,
+    };
+    let mut total = (first & 0x0f) as usize;
+    let mut size = 1;
+    let mut more = first & 0x80 != 0;
+    while more {
+        let byte = *input.get(size)?;
+        if byte & 0x70 != 0 || size >= 4 {
+            return None;
+        }
+        total = (total << 4) | (byte & 0x0f) as usize;
+        more = byte & 0x80 != 0;
+        size += 1;
+    }
+    let length = match element_type {
+        ElementType::List => total,
+        _ if first == 0x00 => 0,
+        _ => total.checked_sub(size)?,
+    };
+    Some(TypeLength {
+        element_type,
+        length,
+        size,
+    })
+}
+
+/// Number of bytes of the element at the start of `input`, including nested elements
+pub fn element_length(input: &[u8]) -> Option<usize> {
+    let mut position = 0;
+    let mut pending = 1usize;
+    while pending > 0 {
+        let type_length = read_type_length(&input[position..])?;
+        pending -= 1;
+        position += type_length.size;
+        match type_length.element_type {
+            ElementType::List => pending = pending.checked_add(type_length.length)?,
+            _ => position += type_length.length,
+        }
+        if position > input.len() {
+            return None;
+        }
+    }
+    Some(position)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn reads_multi_byte_type_length() {
+        assert_eq!(
+            read_type_length(&[0x83, 0x02]),
+            Some(TypeLength {
+                element_type: ElementType::OctetString,
+                length: 48,
+                size: 2
+            })
+        );
+        assert_eq!(
+            read_type_length(&[0x77]),
+            Some(TypeLength {
+                element_type: ElementType::List,
+                length: 7,
+                size: 1
+            })
+        );
+        assert_eq!(read_type_length(&[0x20]), None);
+        assert_eq!(read_type_length(&[0x81]), None);
+    }
+
+    #[test]
+    pub fn measures_nested_elements() {
+        let entry = [
+            0x77, 0x07, 0x01, 0x00, 0x01, 0x08, 0x00, 0xff, 0x65, 0x00, 0x00, 0x01, 0x82, 0x01,
+            0x62, 0x1e, 0x52, 0xff, 0x59, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x01,
+        ];
+
+        assert_eq!(element_length(&entry), Some(entry.len() - 1));
+        assert_eq!(element_length(&entry[..20]), None);
+    }
+}
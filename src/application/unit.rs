@@ -0,0 +1,58 @@
+//! Units of SML values
+//!
+//! SML uses the DLMS unit codes of IEC 62056-62.
+
+/// Unit code of active power
+pub const WATT: u8 = 27;
+/// Unit code of active energy
+pub const WATT_HOUR: u8 = 30;
+
+/// Symbol of a DLMS unit code, e.g. `W` for 27
+pub fn symbol(unit: u8) -> Option<&'static str> {
+    let symbol = match unit {
+        1 => "a",
+        2 => "mo",
+        3 => "wk",
+        4 => "d",
+        5 => "h",
+        6 => "min",
+        7 => "s",
+        8 => "°",
+        9 => "°C",
+        11 => "m",
+        12 => "m/s",
+        13 | 14 => "m³",
+        15 | 16 => "m³/h",
+        17 | 18 => "m³/d",
+        19 => "l",
+        20 => "kg",
+        21 => "N",
+        22 => "Nm",
+        23 => "Pa",
+        24 => "bar",
+        25 => "J",
+        26 => "J/h",
+        27 => "W",
+        28 => "VA",
+        29 => "var",
+        30 => "Wh",
+        31 => "VAh",
+        32 => "varh",
+        33 => "A",
+        34 => "C",
+        35 => "V",
+        36 => "V/m",
+        37 => "F",
+        38 => "Ω",
+        40 => "Wb",
+        41 => "T",
+        42 => "A/m",
+        43 => "H",
+        44 => "Hz",
+        52 => "K",
+        56 => "%",
+        57 => "Ah",
+        _ => return None,
+    };
+    Some(symbol)
+}
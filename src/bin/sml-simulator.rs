@@ -0,0 +1,147 @@
+//! Emit SML telegrams of a virtual meter
+//!
+//! ```text
+//! sml-simulator [--server-id HEX] [--interval SECONDS] [--count N] [--fast]
+//!               [--profile constant:W | sine:MEAN:AMPLITUDE:PERIOD | csv:PATH]
+//!               [--output - | PATH | tcp:ADDRESS]
+//! ```
+//!
+//! `PATH` may also be a serial device or the slave side of a PTY, e.g. one created by
+//! `socat -d -d pty,raw,echo=0 pty,raw,echo=0`. With `tcp:ADDRESS` the simulator listens
+//! and serves each client its own meter.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader},
+    net::TcpListener,
+    process::exit,
+    thread,
+    time::Duration,
+};
+
+use hackdose_sml_parser::{
+    hex::parse_hex,
+    simulator::{PowerProfile, VirtualMeter},
+};
+
+struct Options {
+    server_id: Vec<u8>,
+    interval: Duration,
+    count: Option<usize>,
+    realtime: bool,
+    profile: PowerProfile,
+    output: String,
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(options) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+fn meter(options: &Options) -> VirtualMeter {
+    VirtualMeter::new(&options.server_id)
+        .profile(options.profile.clone())
+        .interval(options.interval)
+}
+
+fn run(options: Options) -> io::Result<()> {
+    match options.output.as_str() {
+        "-" => meter(&options).run(&mut io::stdout().lock(), options.count, options.realtime),
+        output => match output.strip_prefix("tcp:") {
+            Some(address) => {
+                let listener = TcpListener::bind(address)?;
+                for stream in listener.incoming() {
+                    let mut stream = stream?;
+                    let mut meter = meter(&options);
+                    let (count, realtime) = (options.count, options.realtime);
+                    thread::spawn(move || meter.run(&mut stream, count, realtime));
+                }
+                Ok(())
+            }
+            None => {
+                let mut file = OpenOptions::new().create(true).append(true).open(output)?;
+                meter(&options).run(&mut file, options.count, options.realtime)
+            }
+        },
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        server_id: vec![0x0a, 0x01, 0x53, 0x49, 0x4d, 0x00, 0x00, 0x00, 0x00, 0x01],
+        interval: Duration::from_secs(1),
+        count: None,
+        realtime: true,
+        profile: PowerProfile::Constant(0.0),
+        output: "-".to_string(),
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--fast" {
+            options.realtime = false;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--server-id" => {
+                options.server_id =
+                    parse_hex(&value).ok_or_else(|| format!("invalid hex {}", value))?
+            }
+            "--interval" => options.interval = parse_duration(&value)?,
+            "--count" => {
+                options.count = Some(value.parse().map_err(|_| "invalid count".to_string())?)
+            }
+            "--profile" => options.profile = parse_profile(&value)?,
+            "--output" => options.output = value,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_profile(value: &str) -> Result<PowerProfile, String> {
+    let parts: Vec<_> = value.splitn(2, ':').collect();
+    match parts[..] {
+        ["constant", power] => Ok(PowerProfile::Constant(parse_number(power)?)),
+        ["sine", parameters] => {
+            let parameters = parameters
+                .split(':')
+                .map(parse_number)
+                .collect::<Result<Vec<_>, _>>()?;
+            match parameters[..] {
+                [mean, amplitude, period] => Ok(PowerProfile::Sine {
+                    mean,
+                    amplitude,
+                    period: Duration::try_from_secs_f64(period)
+                        .map_err(|_| format!("invalid period {}", period))?,
+                }),
+                _ => Err("expected sine:MEAN:AMPLITUDE:PERIOD".to_string()),
+            }
+        }
+        ["csv", path] => File::open(path)
+            .and_then(|file| PowerProfile::from_csv(BufReader::new(file)))
+            .map_err(|e| format!("{}: {}", path, e)),
+        _ => Err(format!("unknown profile {}", value)),
+    }
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_number(value)?)
+        .map_err(|_| format!("invalid duration {}", value))
+}
@@ -0,0 +1,391 @@
+//! Decode SML frames from a file, serial device, stdin or TCP
+//!
+//! ```text
+//! sml [--format tree | json | csv] [--obis CODE]... [--profile PROFILE] [--hex] [--keep-going]
+//!     [--record FILE] [INPUT]
+//! sml annotate [INPUT]
+//! ```
+//!
+//! `INPUT` is `-` for stdin (the default), `tcp:ADDRESS` to connect to e.g. an IR
+//! reader with network interface, `capture:PATH` to replay a capture or a path. Paths
+//! may be serial devices configured beforehand, e.g. with `stty -F /dev/ttyUSB0 9600 raw`.
+//! With the `serial` feature `serial:PATH[:BAUD]` opens a serial device with 8N1 and
+//! 9600 baud unless given, locks it and reopens it when it is unplugged.
+//! With `--record` everything read from `INPUT` is also written to a capture `FILE`.
+//!
+//! `CODE` is an OBIS number like `1-0:16.7.0`, a name like `SumActiveInstantaneousPower`
+//! or twelve hex digits; only matching list entries are printed. With `--hex` each list
+//! entry is followed by its bytes in the frame.
+//! `PROFILE` selects the corrections for a meter family (`generic`, `iskra`, `emh`, `easymeter`,
+//! `holley`, `dzg`, `ebz`); by default (`auto`) it is detected from each frame. Frames which cannot be parsed end decoding unless
+//! `--keep-going` is given, in which case they are counted.
+//!
+//! `annotate` reads `INPUT` to its end and prints it as hex listing explaining each
+//! byte, including frames which cannot be parsed.
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    net::TcpStream,
+    process::exit,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hackdose_sml_parser::{
+    annotate::annotate,
+    application::{
+        obis::{parse_notation, Obis},
+        profile::MeterProfile,
+    },
+    capture::{CaptureMetadata, CaptureReader, CaptureTap, CaptureWriter, Replay, ReplaySpeed},
+    hex::parse_hex,
+    reader::{SmlError, SmlReader},
+};
+
+mod output;
+
+use output::{Format, Output};
+
+struct Options {
+    format: Format,
+    obis: Vec<Vec<u8>>,
+    /// `None` to detect the profile of each frame
+    profile: Option<MeterProfile>,
+    hex: bool,
+    keep_going: bool,
+    record: Option<String>,
+    input: String,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let result = if args.peek().map(String::as_str) == Some("annotate") {
+        args.next();
+        parse_input(args).map(|input| run_annotate(&input))
+    } else {
+        parse_options(args).map(run)
+    };
+    let result = match result {
+        Ok(result) => result,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(2);
+        }
+    };
+
+    match result {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+fn open(input: &str) -> io::Result<Box<dyn Read>> {
+    match input {
+        "-" => Ok(Box::new(io::stdin())),
+        input => {
+            if let Some(address) = input.strip_prefix("tcp:") {
+                Ok(Box::new(TcpStream::connect(address)?))
+            } else if let Some(device) = input.strip_prefix("serial:") {
+                open_serial(device)
+            } else if let Some(path) = input.strip_prefix("capture:") {
+                let capture = CaptureReader::new(BufReader::new(File::open(path)?))?;
+                Ok(Box::new(Replay::new(
+                    capture,
+                    ReplaySpeed::AsFastAsPossible,
+                )))
+            } else {
+                Ok(Box::new(File::open(input)?))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+fn open_serial(device: &str) -> io::Result<Box<dyn Read>> {
+    use hackdose_sml_parser::serial::SerialSmlSource;
+
+    let (path, baud_rate) = parse_serial(device);
+    let source = SerialSmlSource::new(path).baud_rate(baud_rate.unwrap_or(9600));
+    Ok(Box::new(source.open()?))
+}
+
+#[cfg(not(feature = "serial"))]
+fn open_serial(_device: &str) -> io::Result<Box<dyn Read>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "serial: input needs the serial feature",
+    ))
+}
+
+/// Path and baud rate of a `serial:` input
+///
+/// The part after the last colon is a baud rate only if it is a number, so that paths
+/// containing colons like `/dev/serial/by-path/...` can be given without one.
+fn parse_serial(device: &str) -> (&str, Option<u32>) {
+    match device.rsplit_once(':') {
+        Some((path, baud_rate)) => match baud_rate.parse() {
+            Ok(baud_rate) => (path, Some(baud_rate)),
+            Err(_) => (device, None),
+        },
+        None => (device, None),
+    }
+}
+
+/// Record everything read from `input` to a capture at `path`
+fn record(input: Box<dyn Read>, device: &str, path: &str) -> io::Result<Box<dyn Read>> {
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .ok();
+    let baud = match device.strip_prefix("serial:") {
+        Some(device) => parse_serial(device).1.or(Some(9600)),
+        None => None,
+    };
+    let metadata = CaptureMetadata {
+        device: Some(device.to_string()),
+        baud,
+        started_at,
+        ..Default::default()
+    };
+    // unbuffered, so the capture is complete when interrupted
+    let writer = CaptureWriter::new(File::create(path)?, &metadata)?;
+    Ok(Box::new(CaptureTap::new(input, writer)))
+}
+
+fn run(options: Options) -> io::Result<()> {
+    let mut input = open(&options.input)?;
+    if let Some(path) = &options.record {
+        input = record(input, &options.input, path)?;
+    }
+    let reader = SmlReader::new(input);
+    let mut output = Output::new(
+        io::stdout().lock(),
+        options.format,
+        options.hex,
+        options.obis,
+    );
+    let mut frames = 0;
+    let mut corrupt = 0;
+    output.begin()?;
+    for frame in reader {
+        frames += 1;
+        match frame {
+            Ok(mut frame) => {
+                let profile = options
+                    .profile
+                    .unwrap_or_else(|| MeterProfile::detect(&frame.messages));
+                profile
+                    .correct(&frame.body, &mut frame.messages)
+                    .map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("frame {}: cannot apply profile {:?}", frames, profile),
+                        )
+                    })?;
+                output.frame(frames, &frame.body, &frame.messages)?
+            }
+            Err(SmlError::Io(e)) => return Err(e),
+            Err(SmlError::Parse { body }) => {
+                corrupt += 1;
+                if !options.keep_going {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("frame {}: cannot parse {} bytes", frames, body.len()),
+                    ));
+                }
+                eprintln!("frame {}: cannot parse {} bytes", frames, body.len());
+            }
+        }
+    }
+    output.flush()?;
+    if options.keep_going {
+        eprintln!("{} frames, {} corrupt", frames, corrupt);
+    }
+    Ok(())
+}
+
+fn run_annotate(input: &str) -> io::Result<()> {
+    let mut data = vec![];
+    open(input)?.read_to_end(&mut data)?;
+    let mut out = io::stdout().lock();
+    write!(out, "{}", annotate(&data))?;
+    out.flush()
+}
+
+fn parse_input(mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    let input = args.next().unwrap_or_else(|| "-".to_string());
+    match args.next() {
+        _ if input.starts_with("--") => Err(format!("unknown option {}", input)),
+        Some(arg) => Err(format!("unexpected argument {}", arg)),
+        None => Ok(input),
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        format: Format::Tree,
+        obis: vec![],
+        profile: None,
+        hex: false,
+        keep_going: false,
+        record: None,
+        input: "-".to_string(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hex" => options.hex = true,
+            "--keep-going" => options.keep_going = true,
+            "--format" | "--obis" | "--profile" | "--record" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for {}", arg))?;
+                match arg.as_str() {
+                    "--format" => options.format = parse_format(&value)?,
+                    "--obis" => options.obis.push(parse_obis(&value)?),
+                    "--profile" => options.profile = parse_profile(&value)?,
+                    _ => options.record = Some(value),
+                }
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            _ => options.input = arg,
+        }
+    }
+    Ok(options)
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    match value {
+        "tree" => Ok(Format::Tree),
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Csv),
+        _ => Err(format!("unknown format {}", value)),
+    }
+}
+
+fn parse_profile(value: &str) -> Result<Option<MeterProfile>, String> {
+    match value {
+        "auto" => Ok(None),
+        "generic" => Ok(Some(MeterProfile::Generic)),
+        "iskra" => Ok(Some(MeterProfile::Iskra)),
+        "emh" => Ok(Some(MeterProfile::Emh)),
+        "easymeter" => Ok(Some(MeterProfile::EasyMeter)),
+        "holley" => Ok(Some(MeterProfile::Holley)),
+        "dzg" => Ok(Some(MeterProfile::Dzg)),
+        "ebz" => Ok(Some(MeterProfile::Ebz)),
+        _ => Err(format!("unknown profile {}", value)),
+    }
+}
+
+fn parse_obis(value: &str) -> Result<Vec<u8>, String> {
+    if let Some(number) = parse_notation(value) {
+        return Ok(number.to_vec());
+    }
+    if let Some(obis) = Obis::from_name(value) {
+        return Ok(obis.obis_number().to_vec());
+    }
+    match parse_hex(value) {
+        Some(number) if number.len() == 6 => Ok(number),
+        _ => Err(format!("invalid OBIS code {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    pub fn parses_options() {
+        let defaults = options(&[]).unwrap();
+        assert_eq!(defaults.format, Format::Tree);
+        assert!(defaults.obis.is_empty());
+        assert_eq!(defaults.profile, None);
+        assert!(!defaults.hex && !defaults.keep_going);
+        assert_eq!(defaults.record, None);
+        assert_eq!(defaults.input, "-");
+
+        let options = options(&[
+            "--format",
+            "csv",
+            "--obis",
+            "1-0:1.8.0",
+            "--obis",
+            "SumActiveInstantaneousPower",
+            "--obis",
+            "0100020800ff",
+            "--profile",
+            "dzg",
+            "--hex",
+            "--keep-going",
+            "--record",
+            "meter.capture",
+            "tcp:reader:8000",
+        ])
+        .unwrap();
+        assert_eq!(options.format, Format::Csv);
+        assert_eq!(
+            options.obis,
+            vec![
+                vec![1, 0, 1, 8, 0, 255],
+                vec![1, 0, 16, 7, 0, 255],
+                vec![1, 0, 2, 8, 0, 255],
+            ]
+        );
+        assert_eq!(options.profile, Some(MeterProfile::Dzg));
+        assert!(options.hex && options.keep_going);
+        assert_eq!(options.record.as_deref(), Some("meter.capture"));
+        assert_eq!(options.input, "tcp:reader:8000");
+    }
+
+    #[test]
+    pub fn rejects_invalid_options() {
+        let error = |args: &[&str]| options(args).err();
+        assert_eq!(
+            error(&["--format", "xml"]),
+            Some("unknown format xml".to_string())
+        );
+        assert_eq!(
+            error(&["--profile", "landis"]),
+            Some("unknown profile landis".to_string())
+        );
+        assert_eq!(
+            error(&["--obis", "1-0"]),
+            Some("invalid OBIS code 1-0".to_string())
+        );
+        assert_eq!(
+            error(&["--obis"]),
+            Some("missing value for --obis".to_string())
+        );
+        assert_eq!(
+            error(&["--verbose"]),
+            Some("unknown option --verbose".to_string())
+        );
+    }
+
+    #[test]
+    pub fn parses_annotate_input_and_serial_device() {
+        let input = |args: &[&str]| parse_input(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(input(&[]), Ok("-".to_string()));
+        assert_eq!(input(&["capture:x"]), Ok("capture:x".to_string()));
+        assert!(input(&["a", "b"]).is_err());
+        assert!(input(&["--hex"]).is_err());
+
+        assert_eq!(parse_serial("/dev/ttyUSB0"), ("/dev/ttyUSB0", None));
+        assert_eq!(
+            parse_serial("/dev/ttyUSB0:300"),
+            ("/dev/ttyUSB0", Some(300))
+        );
+        let by_path = "/dev/serial/by-path/pci-0000:00:14.0-usb-0:1:1.0-port0";
+        assert_eq!(parse_serial(by_path), (by_path, None));
+        assert_eq!(
+            parse_serial(&format!("{}:9600", by_path)),
+            (by_path, Some(9600))
+        );
+    }
+}
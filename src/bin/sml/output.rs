@@ -0,0 +1,480 @@
+//! Printing decoded frames as tree, JSON lines or CSV
+use std::io::{self, Write};
+
+use hackdose_sml_parser::{
+    application::{
+        domain::{AnyValue, ProcParValue, SmlListEntry, SmlMessageEnvelope, SmlMessages, SmlTree},
+        obis::{Obis, ObisNotation},
+        tlv::{element_length, read_type_length, ElementType},
+        unit,
+    },
+    hex::Hex,
+};
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Tree,
+    Json,
+    Csv,
+}
+
+pub struct Output<W> {
+    out: W,
+    format: Format,
+    hex: bool,
+    obis: Vec<Vec<u8>>,
+}
+
+impl<W: Write> Output<W> {
+    /// Print to `out`, only list entries matching `obis` unless it is empty
+    pub fn new(out: W, format: Format, hex: bool, obis: Vec<Vec<u8>>) -> Self {
+        Self {
+            out,
+            format,
+            hex,
+            obis,
+        }
+    }
+
+    pub fn begin(&mut self) -> io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                write!(self.out, "frame,server_id,obis,name,value,unit")?;
+                if self.hex {
+                    write!(self.out, ",hex")?;
+                }
+                writeln!(self.out)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Print the messages parsed from `body`
+    pub fn frame(&mut self, index: usize, body: &[u8], messages: &SmlMessages) -> io::Result<()> {
+        let mut raw = RawLists::new(if self.hex { body } else { &[] });
+        match self.format {
+            Format::Tree => self.tree(index, &mut raw, messages),
+            Format::Json => self.json(index, &mut raw, messages),
+            Format::Csv => self.csv(index, &mut raw, messages),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn selected<'a>(&self, entries: &'a [SmlListEntry]) -> impl Iterator<Item = &'a SmlListEntry> {
+        let obis = self.obis.clone();
+        entries
+            .iter()
+            .filter(move |entry| obis.is_empty() || obis.contains(&entry.object_name))
+    }
+
+    fn tree(&mut self, index: usize, raw: &mut RawLists, messages: &SmlMessages) -> io::Result<()> {
+        writeln!(self.out, "frame {}", index)?;
+        for message in messages.messages.iter() {
+            match message {
+                SmlMessageEnvelope::GetOpenResponse(response) => {
+                    writeln!(self.out, "  GetOpenResponse")?;
+                    self.tree_field(2, "serverId", &response.server_id)?;
+                    self.tree_field(2, "reqFileId", &response.req_file_id)?;
+                }
+                SmlMessageEnvelope::GetListResponse(response) => {
+                    writeln!(self.out, "  GetListResponse")?;
+                    self.tree_field(2, "serverId", &response.server_id)?;
+                    self.tree_field(2, "listName", &response.list_name)?;
+                    writeln!(self.out, "    valList")?;
+                    for entry in self.selected(&response.value_list) {
+                        let line = format!(
+                            "      {}{}: {}",
+                            ObisNotation(&entry.object_name),
+                            Obis::from_number(&entry.object_name)
+                                .map(|obis| format!(" {}", obis.name()))
+                                .unwrap_or_default(),
+                            display_value(entry)
+                        );
+                        self.tree_line(&line, raw.next(&entry.object_name))?;
+                    }
+                }
+                SmlMessageEnvelope::GetProcParameterResponse(response) => {
+                    writeln!(self.out, "  GetProcParameterResponse")?;
+                    self.tree_field(2, "serverId", &response.server_id)?;
+                    let path: Vec<_> = response
+                        .parameter_tree_path
+                        .iter()
+                        .map(|x| Hex(x).to_string())
+                        .collect();
+                    writeln!(self.out, "    parameterTreePath: {}", path.join(" "))?;
+                    self.tree_parameters(3, raw, &response.parameter_tree)?;
+                }
+                SmlMessageEnvelope::AttentionResponse(response) => {
+                    writeln!(self.out, "  AttentionResponse")?;
+                    self.tree_field(2, "serverId", &response.server_id)?;
+                    self.tree_field(2, "attentionNo", &response.attention_no)?;
+                    self.tree_field(2, "attentionMsg", &response.attention_msg)?;
+                    if let Some(details) = &response.attention_details {
+                        self.tree_parameters(2, raw, details)?;
+                    }
+                }
+                SmlMessageEnvelope::GetCloseResponse => writeln!(self.out, "  GetCloseResponse")?,
+                _ => writeln!(self.out, "  unknown message")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn tree_field(&mut self, level: usize, name: &str, value: &[u8]) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{:indent$}{}: {}",
+            "",
+            name,
+            Hex(value),
+            indent = 2 * level
+        )
+    }
+
+    fn tree_parameters(
+        &mut self,
+        level: usize,
+        raw: &mut RawLists,
+        tree: &SmlTree,
+    ) -> io::Result<()> {
+        let value = match &tree.parameter_value {
+            Some(ProcParValue::Value(value)) => format!(": {}", display_any_value(value)),
+            Some(ProcParValue::Time(time)) => format!(": time {}", time),
+            None => String::new(),
+        };
+        let line = format!(
+            "{:indent$}{}{}",
+            "",
+            ObisNotation(&tree.parameter_name),
+            value,
+            indent = 2 * level
+        );
+        self.tree_line(&line, raw.next(&tree.parameter_name))?;
+        for child in tree.child_list.iter() {
+            self.tree_parameters(level + 1, raw, child)?;
+        }
+        Ok(())
+    }
+
+    fn tree_line(&mut self, line: &str, encoded: Option<String>) -> io::Result<()> {
+        match encoded {
+            Some(encoded) => writeln!(self.out, "{}  [{}]", line, encoded),
+            None => writeln!(self.out, "{}", line),
+        }
+    }
+
+    fn json(&mut self, index: usize, raw: &mut RawLists, messages: &SmlMessages) -> io::Result<()> {
+        let messages: Vec<_> = messages
+            .messages
+            .iter()
+            .map(|message| match message {
+                SmlMessageEnvelope::GetOpenResponse(response) => json!({
+                    "type": "GetOpenResponse",
+                    "serverId": Hex(&response.server_id).to_string(),
+                    "reqFileId": Hex(&response.req_file_id).to_string(),
+                }),
+                SmlMessageEnvelope::GetListResponse(response) => json!({
+                    "type": "GetListResponse",
+                    "serverId": Hex(&response.server_id).to_string(),
+                    "listName": Hex(&response.list_name).to_string(),
+                    "valList": self
+                        .selected(&response.value_list)
+                        .map(|entry| self.json_entry(raw, entry))
+                        .collect::<Vec<_>>(),
+                }),
+                SmlMessageEnvelope::GetProcParameterResponse(response) => json!({
+                    "type": "GetProcParameterResponse",
+                    "serverId": Hex(&response.server_id).to_string(),
+                    "parameterTreePath": response.parameter_tree_path.iter().map(|x| Hex(x).to_string()).collect::<Vec<_>>(),
+                    "parameterTree": json_tree(&response.parameter_tree),
+                }),
+                SmlMessageEnvelope::AttentionResponse(response) => json!({
+                    "type": "AttentionResponse",
+                    "serverId": Hex(&response.server_id).to_string(),
+                    "attentionNo": Hex(&response.attention_no).to_string(),
+                    "attentionMsg": Hex(&response.attention_msg).to_string(),
+                    "attentionDetails": response.attention_details.as_ref().map(json_tree),
+                }),
+                SmlMessageEnvelope::GetCloseResponse => json!({ "type": "GetCloseResponse" }),
+                _ => json!({ "type": "Unknown" }),
+            })
+            .collect();
+        let frame = json!({ "frame": index, "messages": messages });
+        writeln!(self.out, "{}", frame)
+    }
+
+    fn json_entry(&self, raw: &mut RawLists, entry: &SmlListEntry) -> Value {
+        let mut object = Map::new();
+        object.insert(
+            "obis".into(),
+            ObisNotation(&entry.object_name).to_string().into(),
+        );
+        if let Some(obis) = Obis::from_number(&entry.object_name) {
+            object.insert("name".into(), obis.name().into());
+        }
+        object.insert(
+            "value".into(),
+            match (&entry.value, entry.scaled_value()) {
+                (AnyValue::String(value), _) => display_octets(value).into(),
+                (_, value) => value.into(),
+            },
+        );
+        if let Some(symbol) = entry.unit.and_then(unit::symbol) {
+            object.insert("unit".into(), symbol.into());
+        }
+        if let Some(status) = entry.status {
+            object.insert("status".into(), status.into());
+        }
+        if let Some(raw) = raw.next(&entry.object_name) {
+            object.insert("hex".into(), raw.into());
+        }
+        Value::Object(object)
+    }
+
+    fn csv(&mut self, index: usize, raw: &mut RawLists, messages: &SmlMessages) -> io::Result<()> {
+        for message in messages.messages.iter() {
+            if let SmlMessageEnvelope::GetListResponse(response) = message {
+                for entry in self.selected(&response.value_list) {
+                    let value = match (&entry.value, entry.scaled_value()) {
+                        (AnyValue::String(value), _) => display_octets(value),
+                        (_, value) => value.map(|x| x.to_string()).unwrap_or_default(),
+                    };
+                    let mut fields = vec![
+                        index.to_string(),
+                        Hex(&response.server_id).to_string(),
+                        ObisNotation(&entry.object_name).to_string(),
+                        Obis::from_number(&entry.object_name)
+                            .map(|obis| obis.name().to_string())
+                            .unwrap_or_default(),
+                        value,
+                        entry
+                            .unit
+                            .and_then(unit::symbol)
+                            .unwrap_or_default()
+                            .to_string(),
+                    ];
+                    if self.hex {
+                        fields.push(raw.next(&entry.object_name).unwrap_or_default());
+                    }
+                    let fields: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+                    writeln!(self.out, "{}", fields.join(","))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_tree(tree: &SmlTree) -> Value {
+    let value = match &tree.parameter_value {
+        Some(ProcParValue::Value(value)) => display_any_value(value).into(),
+        Some(ProcParValue::Time(time)) => (*time).into(),
+        None => Value::Null,
+    };
+    json!({
+        "parameterName": ObisNotation(&tree.parameter_name).to_string(),
+        "parameterValue": value,
+        "childList": tree.child_list.iter().map(json_tree).collect::<Vec<_>>(),
+    })
+}
+
+fn display_value(entry: &SmlListEntry) -> String {
+    match (&entry.value, entry.scaled_value()) {
+        (AnyValue::String(value), _) => display_octets(value),
+        (_, Some(value)) => match entry.unit.and_then(unit::symbol) {
+            Some(symbol) => format!("{} {}", value, symbol),
+            None => value.to_string(),
+        },
+        (_, None) => String::new(),
+    }
+}
+
+fn display_any_value(value: &AnyValue) -> String {
+    match value {
+        AnyValue::Unsigned(value) => value.to_string(),
+        AnyValue::Signed(value) => value.to_string(),
+        AnyValue::String(value) => display_octets(value),
+    }
+}
+
+/// Printable octet strings as text in quotes, others as hex
+fn display_octets(value: &[u8]) -> String {
+    if !value.is_empty() && value.iter().all(|x| x.is_ascii_graphic() || *x == b' ') {
+        format!("\"{}\"", String::from_utf8_lossy(value))
+    } else {
+        Hex(value).to_string()
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Lists of a body starting with an octet string, to print the bytes of list entries
+///
+/// Entries and parameters are printed in the order of the body, so each lookup
+/// continues after the list found last.
+struct RawLists<'a> {
+    /// first octet string and bytes of each list, in the order of the body
+    lists: Vec<(&'a [u8], &'a [u8])>,
+    next: usize,
+}
+
+impl<'a> RawLists<'a> {
+    fn new(body: &'a [u8]) -> Self {
+        let mut lists = vec![];
+        let mut position = 0;
+        while let Some(type_length) = body.get(position..).and_then(read_type_length) {
+            if type_length.element_type != ElementType::List {
+                position += type_length.size + type_length.length;
+                continue;
+            }
+            let list = &body[position..];
+            let first = &list[type_length.size..];
+            if let (Some(length), Some(first_type_length)) =
+                (element_length(list), read_type_length(first))
+            {
+                let end = first_type_length.size + first_type_length.length;
+                if first_type_length.element_type == ElementType::OctetString && end <= first.len()
+                {
+                    lists.push((&first[first_type_length.size..end], &list[..length]));
+                }
+            }
+            // continue with the elements of the list
+            position += type_length.size;
+        }
+        Self { lists, next: 0 }
+    }
+
+    /// Bytes of the next list starting with the octet string `first` as hex
+    fn next(&mut self, first: &[u8]) -> Option<String> {
+        let found = self.lists[self.next..]
+            .iter()
+            .position(|(name, _)| *name == first)?;
+        self.next += found + 1;
+        Some(format!("{:#}", Hex(self.lists[self.next - 1].1)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hackdose_sml_parser::{
+        application::domain::{GetListResponseBody, GetOpenResponseBody},
+        encode::encode_body,
+    };
+
+    use super::*;
+
+    fn messages() -> SmlMessages {
+        let entry = |object_name: &[u8], unit, scaler, value| SmlListEntry {
+            object_name: object_name.to_vec(),
+            status: None,
+            value_time: None,
+            unit,
+            scaler,
+            value,
+        };
+        SmlMessages {
+            messages: vec![
+                SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                    server_id: vec![0x0a, 0x01],
+                    req_file_id: vec![0x02],
+                }),
+                SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+                    server_id: vec![0x0a, 0x01],
+                    list_name: vec![],
+                    value_list: vec![
+                        entry(
+                            &[1, 0, 96, 1, 0, 255],
+                            None,
+                            None,
+                            AnyValue::String(b"ISK, 1".to_vec()),
+                        ),
+                        entry(
+                            &[1, 0, 1, 8, 0, 255],
+                            Some(30),
+                            Some(-1),
+                            AnyValue::Unsigned(12345),
+                        ),
+                    ],
+                }),
+                SmlMessageEnvelope::GetCloseResponse,
+            ],
+        }
+    }
+
+    fn print(format: Format, hex: bool, obis: Vec<Vec<u8>>) -> String {
+        let messages = messages();
+        let body = encode_body(&messages);
+        let mut output = Output::new(vec![], format, hex, obis);
+        output.begin().unwrap();
+        output.frame(1, &body, &messages).unwrap();
+        String::from_utf8(output.out).unwrap()
+    }
+
+    #[test]
+    pub fn prints_tree() {
+        assert_eq!(
+            print(Format::Tree, false, vec![]),
+            "frame 1\n\
+             \x20 GetOpenResponse\n\
+             \x20   serverId: 0a01\n\
+             \x20   reqFileId: 02\n\
+             \x20 GetListResponse\n\
+             \x20   serverId: 0a01\n\
+             \x20   listName: \n\
+             \x20   valList\n\
+             \x20     1-0:96.1.0*255: \"ISK, 1\"\n\
+             \x20     1-0:1.8.0*255 PositiveActiveEnergyTotal: 1234.5 Wh\n\
+             \x20 GetCloseResponse\n"
+        );
+    }
+
+    #[test]
+    pub fn prints_json_lines() {
+        let line = print(Format::Json, false, vec![vec![1, 0, 1, 8, 0, 255]]);
+        let frame: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(frame["frame"], 1);
+        assert_eq!(frame["messages"][0]["type"], "GetOpenResponse");
+        assert_eq!(
+            frame["messages"][1]["valList"],
+            json!([{
+                "obis": "1-0:1.8.0*255",
+                "name": "PositiveActiveEnergyTotal",
+                "value": 1234.5,
+                "unit": "Wh",
+            }])
+        );
+        assert_eq!(frame["messages"][2]["type"], "GetCloseResponse");
+    }
+
+    #[test]
+    pub fn prints_csv_with_quoted_fields() {
+        assert_eq!(
+            print(Format::Csv, false, vec![]),
+            "frame,server_id,obis,name,value,unit\n\
+             1,0a01,1-0:96.1.0*255,,\"\"\"ISK, 1\"\"\",\n\
+             1,0a01,1-0:1.8.0*255,PositiveActiveEnergyTotal,1234.5,Wh\n"
+        );
+    }
+
+    #[test]
+    pub fn prints_bytes_of_list_entries() {
+        let csv = print(Format::Csv, true, vec![vec![1, 0, 1, 8, 0, 255]]);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some(
+                "1,0a01,1-0:1.8.0*255,PositiveActiveEnergyTotal,1234.5,Wh,\
+                 77 07 01 00 01 08 00 ff 01 01 62 1e 52 ff 63 30 39 01"
+            )
+        );
+    }
+}
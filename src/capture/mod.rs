@@ -0,0 +1,368 @@
+//! File format for raw captures of SML traffic
+//!
+//! A capture stores the chunks of bytes as read from a device together with the
+//! time they were read, so field problems can be reproduced exactly. Record one by
+//! wrapping the device in a [CaptureTap], play it back with [Replay]:
+//! ```
+//! use std::io::Cursor;
+//! use hackdose_sml_parser::{
+//!     capture::{CaptureMetadata, CaptureReader, CaptureTap, CaptureWriter, Replay, ReplaySpeed},
+//!     reader::SmlReader,
+//! };
+//!
+//! let metadata = CaptureMetadata {
+//!     device: Some("/dev/ttyUSB0".to_string()),
+//!     baud: Some(9600),
+//!     ..Default::default()
+//! };
+//! # let device = Cursor::new(vec![]);
+//! let writer = CaptureWriter::new(vec![], &metadata).unwrap();
+//! let mut tap = CaptureTap::new(device, writer);
+//! for frame in SmlReader::new(&mut tap) {
+//!     // ...
+//! }
+//! let capture = tap.into_inner().1.into_inner();
+//!
+//! let replay = Replay::new(CaptureReader::new(Cursor::new(capture)).unwrap(), ReplaySpeed::Original);
+//! for frame in SmlReader::new(replay) {
+//!     // the same frames again
+//! }
+//! ```
+//!
+//! # Format
+//!
+//! A capture starts with the magic bytes `SMLCAP` followed by the version `00 01`.
+//! Records follow, each consisting of a kind byte, the time since the start of the
+//! capture in microseconds (`u64`), the length of the payload (`u32`) and the payload.
+//! Integers are little endian. Kind `01` is a chunk of data, kind `02` metadata as
+//! UTF-8 lines `key=value`; unknown kinds and keys are skipped.
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    time::{Duration, Instant},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::hex::{parse_hex, Hex};
+
+mod replay;
+mod tap;
+
+pub use replay::{Replay, ReplaySpeed};
+pub use tap::CaptureTap;
+
+static MAGIC: &[u8] = b"SMLCAP\x00\x01";
+
+const KIND_CHUNK: u8 = 0x01;
+const KIND_METADATA: u8 = 0x02;
+
+/// Records larger than this are rejected when reading
+const MAX_RECORD_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Information about a capture
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct CaptureMetadata {
+    /// path or address of the device the capture was read from
+    pub device: Option<String>,
+    pub baud: Option<u32>,
+    /// server id of the meter
+    pub server_id: Option<Vec<u8>>,
+    /// start of the capture in milliseconds since the unix epoch
+    pub started_at: Option<u64>,
+}
+
+/// Bytes read from the device at once
+#[derive(PartialEq, Debug, Clone)]
+pub struct Chunk {
+    /// time since the start of the capture
+    pub offset: Duration,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// The data does not start like a capture of a supported version
+    InvalidHeader,
+    /// The capture ends within a record
+    Truncated,
+    /// A record is larger than [MAX_RECORD_SIZE]
+    RecordTooLarge(u32),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(e) => write!(f, "{}", e),
+            CaptureError::InvalidHeader => write!(f, "not an SML capture"),
+            CaptureError::Truncated => write!(f, "capture truncated"),
+            CaptureError::RecordTooLarge(size) => write!(f, "capture record of {} bytes", size),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+impl From<CaptureError> for io::Error {
+    fn from(e: CaptureError) -> Self {
+        match e {
+            CaptureError::Io(e) => e,
+            e => io::Error::new(ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl CaptureMetadata {
+    fn encode(&self) -> String {
+        let mut lines = String::new();
+        if let Some(device) = &self.device {
+            lines.push_str(&format!("device={}\n", device));
+        }
+        if let Some(baud) = self.baud {
+            lines.push_str(&format!("baud={}\n", baud));
+        }
+        if let Some(server_id) = &self.server_id {
+            lines.push_str(&format!("server_id={}\n", Hex(server_id)));
+        }
+        if let Some(started_at) = self.started_at {
+            lines.push_str(&format!("started_at={}\n", started_at));
+        }
+        lines
+    }
+
+    /// Take over the values set in `lines`
+    fn update(&mut self, lines: &str) {
+        for (key, value) in lines.lines().filter_map(|line| line.split_once('=')) {
+            match key {
+                "device" => self.device = Some(value.to_string()),
+                "baud" => self.baud = value.parse().ok().or(self.baud),
+                "server_id" => self.server_id = parse_hex(value).or(self.server_id.take()),
+                "started_at" => self.started_at = value.parse().ok().or(self.started_at),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Writes a capture
+pub struct CaptureWriter<W> {
+    out: W,
+    start: Instant,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture with `metadata`, chunk times are relative to now
+    pub fn new(mut out: W, metadata: &CaptureMetadata) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        let mut writer = Self {
+            out,
+            start: Instant::now(),
+        };
+        writer.write_metadata(metadata)?;
+        Ok(writer)
+    }
+
+    /// Record `data` as read now
+    pub fn write_chunk(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_chunk_at(self.start.elapsed(), data)
+    }
+
+    /// Record `data` as read at `offset` after the start of the capture
+    pub fn write_chunk_at(&mut self, offset: Duration, data: &[u8]) -> io::Result<()> {
+        self.write_record(KIND_CHUNK, offset, data)
+    }
+
+    /// Add metadata, e.g. the server id once it is known
+    ///
+    /// Values set override earlier ones, unset values are left unchanged.
+    pub fn write_metadata(&mut self, metadata: &CaptureMetadata) -> io::Result<()> {
+        let offset = self.start.elapsed();
+        self.write_record(KIND_METADATA, offset, metadata.encode().as_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_record(&mut self, kind: u8, offset: Duration, payload: &[u8]) -> io::Result<()> {
+        let length = u32::try_from(payload.len())
+            .ok()
+            .filter(|length| *length <= MAX_RECORD_SIZE)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "record too large"))?;
+        self.out.write_u8(kind)?;
+        self.out
+            .write_u64::<LittleEndian>(offset.as_micros() as u64)?;
+        self.out.write_u32::<LittleEndian>(length)?;
+        self.out.write_all(payload)
+    }
+}
+
+/// Reads the chunks of a capture
+///
+/// Metadata records preceding the first chunk are read on creation, later ones
+/// while iterating.
+pub struct CaptureReader<R> {
+    input: R,
+    metadata: CaptureMetadata,
+    /// first chunk, read while looking for metadata
+    pending: Option<Chunk>,
+    /// set after an error, as the position of the next record is unknown
+    failed: bool,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut input: R) -> Result<Self, CaptureError> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => CaptureError::InvalidHeader,
+            _ => CaptureError::Io(e),
+        })?;
+        if magic != MAGIC {
+            return Err(CaptureError::InvalidHeader);
+        }
+        let mut reader = Self {
+            input,
+            metadata: CaptureMetadata::default(),
+            pending: None,
+            failed: false,
+        };
+        reader.pending = reader.read_chunk()?;
+        Ok(reader)
+    }
+
+    pub fn metadata(&self) -> &CaptureMetadata {
+        &self.metadata
+    }
+
+    /// Next chunk, applying metadata records on the way
+    fn read_chunk(&mut self) -> Result<Option<Chunk>, CaptureError> {
+        loop {
+            let kind = match self.input.read_u8() {
+                Ok(kind) => kind,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(CaptureError::Io(e)),
+            };
+            let (offset, payload) = self.read_record()?;
+            match kind {
+                KIND_CHUNK => {
+                    return Ok(Some(Chunk {
+                        offset,
+                        data: payload,
+                    }))
+                }
+                KIND_METADATA => self.metadata.update(&String::from_utf8_lossy(&payload)),
+                _ => (),
+            }
+        }
+    }
+
+    fn read_record(&mut self) -> Result<(Duration, Vec<u8>), CaptureError> {
+        let offset = self.input.read_u64::<LittleEndian>().map_err(truncated)?;
+        let length = self.input.read_u32::<LittleEndian>().map_err(truncated)?;
+        if length > MAX_RECORD_SIZE {
+            return Err(CaptureError::RecordTooLarge(length));
+        }
+        let mut payload = vec![0; length as usize];
+        self.input.read_exact(&mut payload).map_err(truncated)?;
+        Ok((Duration::from_micros(offset), payload))
+    }
+}
+
+fn truncated(e: io::Error) -> CaptureError {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => CaptureError::Truncated,
+        _ => CaptureError::Io(e),
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Chunk, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(chunk) = self.pending.take() {
+            return Some(Ok(chunk));
+        }
+        if self.failed {
+            return None;
+        }
+        let chunk = self.read_chunk();
+        self.failed = chunk.is_err();
+        chunk.transpose()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::transport::test::CLOSE_FRAME;
+
+    /// Capture of the close frame in two chunks, 20ms apart
+    pub(crate) fn capture() -> Vec<u8> {
+        let metadata = CaptureMetadata {
+            device: Some("/dev/ttyUSB0".to_string()),
+            baud: Some(9600),
+            ..Default::default()
+        };
+        let mut writer = CaptureWriter::new(vec![], &metadata).unwrap();
+        writer
+            .write_chunk_at(Duration::from_millis(5), &CLOSE_FRAME[..10])
+            .unwrap();
+        writer
+            .write_metadata(&CaptureMetadata {
+                server_id: Some(vec![0x0a, 0x01]),
+                ..Default::default()
+            })
+            .unwrap();
+        writer
+            .write_chunk_at(Duration::from_millis(25), &CLOSE_FRAME[10..])
+            .unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    pub fn round_trips_chunks_and_metadata() {
+        let mut reader = CaptureReader::new(Cursor::new(capture())).unwrap();
+
+        assert_eq!(reader.metadata().device.as_deref(), Some("/dev/ttyUSB0"));
+        assert_eq!(reader.metadata().baud, Some(9600));
+        let chunks: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    offset: Duration::from_millis(5),
+                    data: CLOSE_FRAME[..10].to_vec()
+                },
+                Chunk {
+                    offset: Duration::from_millis(25),
+                    data: CLOSE_FRAME[10..].to_vec()
+                }
+            ]
+        );
+        assert_eq!(reader.metadata().server_id, Some(vec![0x0a, 0x01]));
+        assert_eq!(reader.metadata().baud, Some(9600));
+    }
+
+    #[test]
+    pub fn reports_truncated_and_foreign_data() {
+        let capture = capture();
+
+        let chunks: Vec<_> = CaptureReader::new(Cursor::new(&capture[..capture.len() - 1]))
+            .unwrap()
+            .collect();
+
+        assert!(matches!(&chunks[..], [Ok(_), Err(CaptureError::Truncated)]));
+        assert!(matches!(
+            CaptureReader::new(Cursor::new(CLOSE_FRAME)),
+            Err(CaptureError::InvalidHeader)
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    io::{self, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{CaptureReader, Chunk};
+
+/// Pace of a [Replay]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ReplaySpeed {
+    /// chunks become available with the delays they were captured with
+    Original,
+    /// chunks are available immediately
+    AsFastAsPossible,
+}
+
+/// Plays back a capture as [Read], or as tokio `AsyncRead`
+///
+/// Each read returns data of at most one chunk. The capture itself is read with
+/// blocking reads, also when used as `AsyncRead`; at [ReplaySpeed::Original] the
+/// async variant needs a tokio runtime with time enabled.
+pub struct Replay<R> {
+    capture: CaptureReader<R>,
+    speed: ReplaySpeed,
+    /// instant the first chunk was replayed and its offset in the capture
+    origin: Option<(Instant, Duration)>,
+    current: Vec<u8>,
+    position: usize,
+    /// chunk waiting for its time to come
+    #[cfg(feature = "async-tokio")]
+    waiting: Option<(Chunk, std::pin::Pin<Box<tokio::time::Sleep>>)>,
+}
+
+impl<R: Read> Replay<R> {
+    pub fn new(capture: CaptureReader<R>, speed: ReplaySpeed) -> Self {
+        Self {
+            capture,
+            speed,
+            origin: None,
+            current: vec![],
+            position: 0,
+            #[cfg(feature = "async-tokio")]
+            waiting: None,
+        }
+    }
+
+    /// The capture being replayed, e.g. to read its metadata
+    pub fn capture(&self) -> &CaptureReader<R> {
+        &self.capture
+    }
+
+    /// Next chunk from the capture and the instant it is due
+    fn next_chunk(&mut self) -> io::Result<Option<(Chunk, Instant)>> {
+        let chunk = match self.capture.next() {
+            Some(chunk) => chunk?,
+            None => return Ok(None),
+        };
+        let now = Instant::now();
+        let (start, first_offset) = *self.origin.get_or_insert((now, chunk.offset));
+        let due = match self.speed {
+            ReplaySpeed::Original => start + chunk.offset.saturating_sub(first_offset),
+            ReplaySpeed::AsFastAsPossible => now,
+        };
+        Ok(Some((chunk, due)))
+    }
+
+    /// Copy from the current chunk, returning 0 if it is exhausted
+    fn copy_current(&mut self, buf: &mut [u8]) -> usize {
+        let remaining = &self.current[self.position..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.position += n;
+        n
+    }
+
+    fn start(&mut self, chunk: Chunk) {
+        self.current = chunk.data;
+        self.position = 0;
+    }
+}
+
+impl<R: Read> Read for Replay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = self.copy_current(buf);
+            if n > 0 {
+                return Ok(n);
+            }
+            let Some((chunk, due)) = self.next_chunk()? else {
+                return Ok(0);
+            };
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+            self.start(chunk);
+        }
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl<R: Read + Unpin> tokio::io::AsyncRead for Replay<R> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        use std::{future::Future, task::Poll};
+
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if let Some((_, sleep)) = &mut this.waiting {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let (chunk, _) = this.waiting.take().expect("waiting chunk");
+                this.start(chunk);
+            }
+            let n = this.copy_current(buf.initialize_unfilled());
+            if n > 0 {
+                buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+            match this.next_chunk()? {
+                None => return Poll::Ready(Ok(())),
+                Some((chunk, due)) if due <= Instant::now() => this.start(chunk),
+                Some((chunk, due)) => {
+                    let sleep = Box::pin(tokio::time::sleep_until(due.into()));
+                    this.waiting = Some((chunk, sleep));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{capture::test::capture, reader::SmlReader, transport::test::CLOSE_FRAME};
+
+    fn replay(speed: ReplaySpeed) -> Replay<Cursor<Vec<u8>>> {
+        Replay::new(CaptureReader::new(Cursor::new(capture())).unwrap(), speed)
+    }
+
+    #[test]
+    pub fn replays_chunks_into_reader() {
+        let mut data = vec![];
+        replay(ReplaySpeed::AsFastAsPossible)
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, CLOSE_FRAME);
+
+        let frames: Vec<_> = SmlReader::new(replay(ReplaySpeed::AsFastAsPossible)).collect();
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    pub fn replays_at_original_speed() {
+        let start = Instant::now();
+
+        let frames: Vec<_> = SmlReader::new(replay(ReplaySpeed::Original)).collect();
+
+        assert_eq!(frames.len(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    pub fn replays_into_message_stream() {
+        use tokio_stream::StreamExt;
+
+        use crate::application::domain::{SmlMessageEnvelope, SmlMessages};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let start = Instant::now();
+
+        let messages: Vec<_> = runtime.block_on(async {
+            crate::message_stream::sml_message_stream(replay(ReplaySpeed::Original))
+                .collect()
+                .await
+        });
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(matches!(
+            &messages[..],
+            [Ok(SmlMessages { messages })] if messages == &vec![SmlMessageEnvelope::GetCloseResponse]
+        ));
+    }
+}
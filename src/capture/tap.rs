@@ -0,0 +1,135 @@
+use std::io::{self, Read, Write};
+
+use super::CaptureWriter;
+
+/// Reader recording everything read through it into a capture
+///
+/// Wraps the input of an [crate::reader::SmlReader] or
+/// [crate::message_stream::sml_message_stream]; implements [Read] and tokio's
+/// `AsyncRead` depending on the wrapped reader. Chunks are written to the capture
+/// with blocking writes, so use a buffered writer. Failing to write the capture
+/// fails the read.
+pub struct CaptureTap<R, W> {
+    inner: R,
+    writer: CaptureWriter<W>,
+}
+
+impl<R, W: Write> CaptureTap<R, W> {
+    pub fn new(inner: R, writer: CaptureWriter<W>) -> Self {
+        Self { inner, writer }
+    }
+
+    /// The capture, e.g. to add metadata once it is known
+    pub fn writer_mut(&mut self) -> &mut CaptureWriter<W> {
+        &mut self.writer
+    }
+
+    /// Return the wrapped reader and the capture
+    pub fn into_inner(self) -> (R, CaptureWriter<W>) {
+        (self.inner, self.writer)
+    }
+}
+
+impl<R: Read, W: Write> Read for CaptureTap<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.writer.write_chunk(&buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl<R: tokio::io::AsyncRead + Unpin, W: Write + Unpin> tokio::io::AsyncRead for CaptureTap<R, W> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        use std::task::Poll;
+
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        match std::pin::Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let read = &buf.filled()[before..];
+                if !read.is_empty() {
+                    this.writer.write_chunk(read)?;
+                }
+                Poll::Ready(Ok(()))
+            }
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        capture::{CaptureMetadata, CaptureReader},
+        reader::SmlReader,
+        transport::test::CLOSE_FRAME,
+    };
+
+    /// Data of all chunks in `capture`
+    fn captured(capture: Vec<u8>) -> Vec<u8> {
+        CaptureReader::new(Cursor::new(capture))
+            .unwrap()
+            .flat_map(|chunk| chunk.unwrap().data)
+            .collect()
+    }
+
+    #[test]
+    pub fn records_bytes_read_by_reader() {
+        let writer = CaptureWriter::new(vec![], &CaptureMetadata::default()).unwrap();
+        let mut tap = CaptureTap::new(Cursor::new(CLOSE_FRAME), writer);
+
+        let frames: Vec<_> = SmlReader::with_buffer_size(&mut tap, 8).collect();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(captured(tap.into_inner().1.into_inner()), CLOSE_FRAME);
+    }
+
+    /// Capture buffer shared with the task reading the stream
+    #[cfg(feature = "async-tokio")]
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "async-tokio")]
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    pub fn records_bytes_read_by_message_stream() {
+        use tokio_stream::StreamExt;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let capture = Shared::default();
+        let writer = CaptureWriter::new(capture.clone(), &CaptureMetadata::default()).unwrap();
+        let tap = CaptureTap::new(Cursor::new(CLOSE_FRAME), writer);
+
+        let messages: Vec<_> = runtime.block_on(async {
+            crate::message_stream::sml_message_stream(tap)
+                .collect()
+                .await
+        });
+
+        assert_eq!(messages.len(), 1);
+        let capture = capture.0.lock().unwrap().clone();
+        assert_eq!(captured(capture), CLOSE_FRAME);
+    }
+}
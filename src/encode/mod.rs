@@ -0,0 +1,610 @@
+//! Serialize SML messages back to bytes
+//!
+//! This is the inverse of [crate::application::parser] and [crate::transport]:
+//! [encode_body] writes the messages of an [SmlMessages] as SML type-length-value
+//! structures, [encode_frame] wraps a body into a transport frame.
+//! ```
+//! use hackdose_sml_parser::{
+//!     application::domain::{SmlMessageEnvelope, SmlMessages},
+//!     application::parser::parse_message,
+//!     encode::encode,
+//! };
+//!
+//! let messages = SmlMessages {
+//!     messages: vec![SmlMessageEnvelope::GetCloseResponse],
+//! };
+//! let frame = encode(&messages);
+//! assert_eq!(parse_message(&frame).unwrap(), messages);
+//! ```
+use alloc::vec::Vec;
+
+use crate::{
+    application::domain::{
+        AnyValue, AttentionResponseBody, GetListResponseBody, GetOpenResponseBody,
+        GetProcParameterResponseBody, ProcParValue, SmlListEntry, SmlMessageEnvelope, SmlMessages,
+        SmlTime, SmlTree, ValueTime,
+    },
+    transport::crc16,
+};
+
+pub mod request;
+
+static ESCAPE_SEQUENCE: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b];
+static START_SEQUENCE: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01];
+
+const TYPE_OCTET_STRING: u8 = 0x00;
+const TYPE_SIGNED: u8 = 0x50;
+const TYPE_UNSIGNED: u8 = 0x60;
+const TYPE_LIST: u8 = 0x70;
+
+/// No value for optional fields
+const OPTIONAL: u8 = 0x01;
+const END_OF_MESSAGE: u8 = 0x00;
+
+const GET_OPEN_RESPONSE: u16 = 0x0101;
+const GET_CLOSE_RESPONSE: u16 = 0x0201;
+const GET_PROC_PARAMETER_RESPONSE: u16 = 0x0501;
+const GET_LIST_RESPONSE: u16 = 0x0701;
+const ATTENTION_RESPONSE: u16 = 0xff01;
+
+const PROC_PAR_VALUE: u64 = 0x01;
+const PROC_PAR_TIME: u64 = 0x04;
+const SEC_INDEX: u64 = 0x01;
+const TIMESTAMP: u64 = 0x02;
+
+/// Write a value as SML type-length-value bytes
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// Write the type-length field using as few bytes as possible
+///
+/// For octet strings and integers the length includes the type-length field itself,
+/// for lists it is the number of elements.
+fn write_type_length(out: &mut Vec<u8>, kind: u8, length: usize) {
+    let includes_itself = kind != TYPE_LIST;
+    let mut tl_bytes = 1;
+    let total = loop {
+        let total = if includes_itself {
+            length + tl_bytes
+        } else {
+            length
+        };
+        if total < 1 << (4 * tl_bytes) {
+            break total;
+        }
+        tl_bytes += 1;
+    };
+    for i in (0..tl_bytes).rev() {
+        let nibble = ((total >> (4 * i)) & 0x0f) as u8;
+        let more = if i > 0 { 0x80 } else { 0x00 };
+        let kind = if i == tl_bytes - 1 { kind } else { 0x00 };
+        out.push(more | kind | nibble);
+    }
+}
+
+fn write_list(out: &mut Vec<u8>, length: usize) {
+    write_type_length(out, TYPE_LIST, length);
+}
+
+fn write_unsigned(out: &mut Vec<u8>, value: u64) {
+    let bytes = value.to_be_bytes();
+    let width = match value {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    };
+    write_type_length(out, TYPE_UNSIGNED, width);
+    out.extend_from_slice(&bytes[8 - width..]);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    let bytes = value.to_be_bytes();
+    let width = if i8::try_from(value).is_ok() {
+        1
+    } else if i16::try_from(value).is_ok() {
+        2
+    } else if i32::try_from(value).is_ok() {
+        4
+    } else {
+        8
+    };
+    write_type_length(out, TYPE_SIGNED, width);
+    out.extend_from_slice(&bytes[8 - width..]);
+}
+
+/// Unsigned16 as used for message tags and crc: always two bytes
+fn write_unsigned_16(out: &mut Vec<u8>, value: u16) {
+    write_type_length(out, TYPE_UNSIGNED, 2);
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Unsigned32 as used for times: always four bytes
+fn write_unsigned_32(out: &mut Vec<u8>, value: u32) {
+    write_type_length(out, TYPE_UNSIGNED, 4);
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_optional<T: Encode + ?Sized>(out: &mut Vec<u8>, value: Option<&T>) {
+    match value {
+        Some(value) => value.encode(out),
+        None => out.push(OPTIONAL),
+    }
+}
+
+fn write_tree_path(out: &mut Vec<u8>, path: &[Vec<u8>]) {
+    write_list(out, path.len());
+    for entry in path {
+        entry.encode(out);
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_type_length(out, TYPE_OCTET_STRING, self.len());
+        out.extend_from_slice(self);
+    }
+}
+
+impl Encode for AnyValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            AnyValue::Unsigned(v) => write_unsigned(out, *v as u64),
+            AnyValue::Signed(v) => write_signed(out, *v as i64),
+            AnyValue::String(v) => v.encode(out),
+        }
+    }
+}
+
+impl Encode for SmlTime {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (choice, seconds) = match self {
+            SmlTime::SecIndex(seconds) => (SEC_INDEX, seconds),
+            SmlTime::Timestamp(seconds) => (TIMESTAMP, seconds),
+        };
+        write_list(out, 2);
+        write_unsigned(out, choice);
+        write_unsigned_32(out, *seconds);
+    }
+}
+
+impl Encode for ValueTime {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            ValueTime::OctetString(value) => value.encode(out),
+            ValueTime::Time(time) => time.encode(out),
+        }
+    }
+}
+
+impl Encode for SmlListEntry {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 7);
+        self.object_name.encode(out);
+        match self.status {
+            Some(status) => write_unsigned(out, status as u64),
+            None => out.push(OPTIONAL),
+        }
+        write_optional(out, self.value_time.as_ref());
+        match self.unit {
+            Some(unit) => write_unsigned(out, unit as u64),
+            None => out.push(OPTIONAL),
+        }
+        match self.scaler {
+            Some(scaler) => write_signed(out, scaler as i64),
+            None => out.push(OPTIONAL),
+        }
+        self.value.encode(out);
+        // valueSignature
+        out.push(OPTIONAL);
+    }
+}
+
+impl Encode for GetOpenResponseBody {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, GET_OPEN_RESPONSE);
+        write_list(out, 6);
+        // codepage, clientId
+        out.extend_from_slice(&[OPTIONAL, OPTIONAL]);
+        self.req_file_id.encode(out);
+        self.server_id.encode(out);
+        // refTime, smlVersion
+        out.extend_from_slice(&[OPTIONAL, OPTIONAL]);
+    }
+}
+
+impl Encode for GetListResponseBody {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, GET_LIST_RESPONSE);
+        write_list(out, 7);
+        // clientId
+        out.push(OPTIONAL);
+        self.server_id.encode(out);
+        self.list_name.encode(out);
+        // actSensorTime: secIndex 0
+        write_list(out, 2);
+        write_unsigned(out, SEC_INDEX);
+        write_unsigned_32(out, 0);
+        write_list(out, self.value_list.len());
+        for entry in self.value_list.iter() {
+            entry.encode(out);
+        }
+        // listSignature, actGatewayTime
+        out.extend_from_slice(&[OPTIONAL, OPTIONAL]);
+    }
+}
+
+impl Encode for ProcParValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        match self {
+            ProcParValue::Value(value) => {
+                write_unsigned(out, PROC_PAR_VALUE);
+                value.encode(out);
+            }
+            ProcParValue::Time(time) => {
+                write_unsigned(out, PROC_PAR_TIME);
+                write_list(out, 2);
+                write_unsigned(out, SEC_INDEX);
+                write_unsigned_32(out, *time);
+            }
+        }
+    }
+}
+
+impl Encode for SmlTree {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 3);
+        self.parameter_name.encode(out);
+        write_optional(out, self.parameter_value.as_ref());
+        if self.child_list.is_empty() {
+            out.push(OPTIONAL);
+        } else {
+            write_list(out, self.child_list.len());
+            for child in self.child_list.iter() {
+                child.encode(out);
+            }
+        }
+    }
+}
+
+impl Encode for GetProcParameterResponseBody {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, GET_PROC_PARAMETER_RESPONSE);
+        write_list(out, 3);
+        self.server_id.encode(out);
+        write_tree_path(out, &self.parameter_tree_path);
+        self.parameter_tree.encode(out);
+    }
+}
+
+impl Encode for AttentionResponseBody {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, ATTENTION_RESPONSE);
+        write_list(out, 4);
+        self.server_id.encode(out);
+        self.attention_no.encode(out);
+        self.attention_msg.encode(out);
+        write_optional(out, self.attention_details.as_ref());
+    }
+}
+
+impl Encode for SmlMessageEnvelope {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            SmlMessageEnvelope::GetOpenResponse(body) => body.encode(out),
+            SmlMessageEnvelope::GetListResponse(body) => body.encode(out),
+            SmlMessageEnvelope::GetProcParameterResponse(body) => body.encode(out),
+            SmlMessageEnvelope::AttentionResponse(body) => body.encode(out),
+            SmlMessageEnvelope::GetCloseResponse => {
+                write_list(out, 2);
+                write_unsigned_16(out, GET_CLOSE_RESPONSE);
+                write_list(out, 1);
+                // globalSignature
+                out.push(OPTIONAL);
+            }
+        }
+    }
+}
+
+/// Write a complete SML message: envelope, body and crc
+pub fn encode_message(
+    out: &mut Vec<u8>,
+    transaction_id: &[u8],
+    group_no: u8,
+    message: &(impl Encode + ?Sized),
+) {
+    let start = out.len();
+    write_list(out, 6);
+    transaction_id.encode(out);
+    write_unsigned(out, group_no as u64);
+    // abortOnError: continue
+    write_unsigned(out, 0);
+    message.encode(out);
+    let crc = crc16(&out[start..]);
+    write_type_length(out, TYPE_UNSIGNED, 2);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out.push(END_OF_MESSAGE);
+}
+
+/// Encode messages as a message body (omitting header and footer)
+///
+/// Transaction ids are numbered consecutively starting at 1.
+pub fn encode_body(messages: &SmlMessages) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (index, message) in messages.messages.iter().enumerate() {
+        let transaction_id = (index as u32 + 1).to_be_bytes();
+        encode_message(&mut out, &transaction_id, 0, message);
+    }
+    out
+}
+
+/// Wrap a message body into a transport frame
+///
+/// Escapes escape sequences aligned to four bytes in the body, pads it to a multiple
+/// of four bytes and appends the frame crc.
+pub fn encode_frame(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + 16);
+    out.extend_from_slice(START_SEQUENCE);
+    let mut index = 0;
+    while index < body.len() {
+        if index % ESCAPE_SEQUENCE.len() == 0 && body[index..].starts_with(ESCAPE_SEQUENCE) {
+            out.extend_from_slice(ESCAPE_SEQUENCE);
+            out.extend_from_slice(ESCAPE_SEQUENCE);
+            index += ESCAPE_SEQUENCE.len();
+        } else {
+            out.push(body[index]);
+            index += 1;
+        }
+    }
+    let padding = (4 - out.len() % 4) % 4;
+    out.resize(out.len() + padding, 0x00);
+    out.extend_from_slice(ESCAPE_SEQUENCE);
+    out.push(0x1a);
+    out.push(padding as u8);
+    let crc = crc16(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// Encode messages as a complete transport frame
+pub fn encode(messages: &SmlMessages) -> Vec<u8> {
+    encode_frame(&encode_body(messages))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{application::parser::parse_body, transport::SMLMessageBuilder};
+    use proptest::{collection::vec, option, prelude::*};
+
+    #[test]
+    pub fn encodes_close_response_like_meter() {
+        let mut out = vec![];
+
+        encode_message(
+            &mut out,
+            &[0x03, 0x2b, 0x18, 0x11],
+            0,
+            &SmlMessageEnvelope::GetCloseResponse,
+        );
+
+        assert_eq!(
+            out,
+            vec![
+                0x76, 0x05, 0x03, 0x2b, 0x18, 0x11, 0x62, 0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01,
+                0x71, 0x01, 0x63, 0xfa, 0x36, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    pub fn uses_minimal_type_length_fields() {
+        let mut out = vec![];
+        [0xaa; 14].encode(&mut out);
+        assert_eq!(out[0], 0x0f);
+
+        let mut out = vec![];
+        [0xaa; 15].encode(&mut out);
+        assert_eq!(out[..2], [0x81, 0x01]);
+
+        let mut out = vec![];
+        write_list(&mut out, 16);
+        assert_eq!(out, vec![0xf1, 0x00]);
+
+        let mut out = vec![];
+        AnyValue::Signed(-200).encode(&mut out);
+        assert_eq!(out, vec![0x53, 0xff, 0x38]);
+    }
+
+    #[test]
+    pub fn escapes_and_pads_frame() {
+        let frame = encode_frame(&[0x1b, 0x1b, 0x1b, 0x1b, 0x42]);
+
+        assert_eq!(
+            frame[..21],
+            [
+                0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b,
+                0x1b, 0x1b, 0x42, 0x00, 0x00, 0x00, 0x1b
+            ]
+        );
+        assert_eq!(frame[24..26], [0x1a, 0x03]);
+        let crc = crc16(&frame[..frame.len() - 2]).to_le_bytes();
+        assert_eq!(frame[frame.len() - 2..], crc);
+    }
+
+    #[test]
+    pub fn escapes_only_aligned_escape_sequences() {
+        let frame = encode_frame(&[0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a]);
+
+        assert_eq!(
+            frame[8..18],
+            [0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x00, 0x1b, 0x1b]
+        );
+        assert_eq!(frame[20..22], [0x1a, 0x02]);
+    }
+
+    #[test]
+    pub fn round_trips_escape_sequence_in_value() {
+        let messages = SmlMessages {
+            messages: vec![SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                server_id: vec![0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x00, 0x00],
+                req_file_id: vec![0x1b; 9],
+            })],
+        };
+        let mut builder = SMLMessageBuilder::Empty;
+
+        builder.record(&encode(&messages));
+
+        match builder {
+            SMLMessageBuilder::Complete { data, .. } => {
+                assert_eq!(parse_body(&data).unwrap(), messages)
+            }
+            _ => panic!("frame not complete"),
+        }
+    }
+
+    fn octet_string(max: usize) -> impl Strategy<Value = Vec<u8>> {
+        vec(any::<u8>(), 0..=max)
+    }
+
+    fn any_value() -> impl Strategy<Value = AnyValue> {
+        prop_oneof![
+            any::<usize>().prop_map(AnyValue::Unsigned),
+            any::<isize>().prop_map(AnyValue::Signed),
+            octet_string(48).prop_map(AnyValue::String),
+        ]
+    }
+
+    fn value_time() -> impl Strategy<Value = ValueTime> {
+        prop_oneof![
+            // an empty octet string is the same as no valTime
+            vec(any::<u8>(), 1..=48).prop_map(ValueTime::OctetString),
+            any::<u32>().prop_map(|x| ValueTime::Time(SmlTime::SecIndex(x))),
+            any::<u32>().prop_map(|x| ValueTime::Time(SmlTime::Timestamp(x))),
+        ]
+    }
+
+    fn list_entry() -> impl Strategy<Value = SmlListEntry> {
+        (
+            octet_string(48),
+            option::of(any::<u32>()),
+            option::of(value_time()),
+            option::of(any::<u8>()),
+            option::of(any::<i8>()),
+            any_value(),
+        )
+            .prop_map(|(object_name, status, value_time, unit, scaler, value)| {
+                SmlListEntry {
+                    object_name,
+                    status,
+                    value_time,
+                    unit,
+                    scaler,
+                    value,
+                }
+            })
+    }
+
+    fn tree() -> impl Strategy<Value = SmlTree> {
+        let leaf = (
+            octet_string(48),
+            option::of(prop_oneof![
+                any_value().prop_map(ProcParValue::Value),
+                any::<u32>().prop_map(ProcParValue::Time),
+            ]),
+        )
+            .prop_map(|(parameter_name, parameter_value)| SmlTree {
+                parameter_name,
+                parameter_value,
+                child_list: vec![],
+            });
+        leaf.prop_recursive(3, 16, 4, |inner| {
+            (octet_string(48), vec(inner, 1..4)).prop_map(|(parameter_name, child_list)| SmlTree {
+                parameter_name,
+                parameter_value: None,
+                child_list,
+            })
+        })
+    }
+
+    fn envelope() -> impl Strategy<Value = SmlMessageEnvelope> {
+        prop_oneof![
+            (octet_string(48), vec(octet_string(48), 0..=15), tree()).prop_map(
+                |(server_id, parameter_tree_path, parameter_tree)| {
+                    SmlMessageEnvelope::GetProcParameterResponse(GetProcParameterResponseBody {
+                        server_id,
+                        parameter_tree_path,
+                        parameter_tree,
+                    })
+                }
+            ),
+            (
+                octet_string(48),
+                octet_string(48),
+                octet_string(48),
+                option::of(tree())
+            )
+                .prop_map(
+                    |(server_id, attention_no, attention_msg, attention_details)| {
+                        SmlMessageEnvelope::AttentionResponse(AttentionResponseBody {
+                            server_id,
+                            attention_no,
+                            attention_msg,
+                            attention_details,
+                        })
+                    }
+                ),
+            (octet_string(48), octet_string(48)).prop_map(|(server_id, req_file_id)| {
+                SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                    server_id,
+                    req_file_id,
+                })
+            }),
+            (
+                octet_string(48),
+                octet_string(48),
+                vec(list_entry(), 1..=15)
+            )
+                .prop_map(|(server_id, list_name, value_list)| {
+                    SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+                        server_id,
+                        list_name,
+                        value_list,
+                    })
+                }),
+            Just(SmlMessageEnvelope::GetCloseResponse),
+        ]
+    }
+
+    fn messages() -> impl Strategy<Value = SmlMessages> {
+        vec(envelope(), 0..4).prop_map(|messages| SmlMessages { messages })
+    }
+
+    proptest! {
+        #[test]
+        fn body_round_trip(messages in messages()) {
+            prop_assert_eq!(parse_body(&encode_body(&messages)).unwrap(), messages);
+        }
+
+        #[test]
+        fn transport_round_trip(messages in messages()) {
+            let mut builder = SMLMessageBuilder::Empty;
+            builder.record(&encode(&messages));
+
+            match builder {
+                SMLMessageBuilder::Complete { data, rest } => {
+                    prop_assert_eq!(parse_body(&data).unwrap(), messages);
+                    // only escape bytes of the crc which may start the next frame are kept
+                    prop_assert!(rest.iter().all(|x| *x == 0x1b), "rest {:02x?}", rest);
+                }
+                _ => prop_assert!(false, "frame not complete"),
+            }
+        }
+    }
+}
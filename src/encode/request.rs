@@ -0,0 +1,364 @@
+//! Requests to bidirectional meters and gateways
+//!
+//! A [RequestBuilder] wraps requests into a request file (OpenRequest, requests, CloseRequest),
+//! numbers transactions and computes all checksums.
+//! ```
+//! use hackdose_sml_parser::encode::request::{GetListRequest, RequestBuilder, SmlRequest};
+//!
+//! let mut builder = RequestBuilder::new(b"client").password(b"1234");
+//! let file = builder.build(vec![SmlRequest::GetList(GetListRequest::default())]);
+//! // write file.frame to the meter, then match replies with file.request_for(&reply)
+//! ```
+use alloc::vec::Vec;
+
+use super::{
+    encode_frame, encode_message, write_list, write_optional, write_tree_path, write_unsigned,
+    write_unsigned_16, Encode, OPTIONAL,
+};
+use crate::application::domain::SmlMessage;
+
+const OPEN_REQUEST: u16 = 0x0100;
+const CLOSE_REQUEST: u16 = 0x0200;
+const GET_PROC_PARAMETER_REQUEST: u16 = 0x0500;
+const GET_LIST_REQUEST: u16 = 0x0700;
+
+/// SML version 1
+const SML_VERSION: u64 = 1;
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct OpenRequest {
+    pub client_id: Vec<u8>,
+    pub req_file_id: Vec<u8>,
+    pub server_id: Option<Vec<u8>>,
+    pub username: Option<Vec<u8>>,
+    /// password or PIN to unlock full resolution values
+    pub password: Option<Vec<u8>>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct GetListRequest {
+    pub client_id: Vec<u8>,
+    pub server_id: Option<Vec<u8>>,
+    pub username: Option<Vec<u8>>,
+    pub password: Option<Vec<u8>>,
+    /// the list to read, `None` for the default list
+    pub list_name: Option<Vec<u8>>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct GetProcParameterRequest {
+    pub server_id: Option<Vec<u8>>,
+    pub username: Option<Vec<u8>>,
+    pub password: Option<Vec<u8>>,
+    /// OBIS numbers leading to the parameter
+    pub parameter_tree_path: Vec<Vec<u8>>,
+    pub attribute: Option<Vec<u8>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SmlRequest {
+    Open(OpenRequest),
+    GetList(GetListRequest),
+    GetProcParameter(GetProcParameterRequest),
+    Close,
+}
+
+impl Encode for OpenRequest {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, OPEN_REQUEST);
+        write_list(out, 7);
+        // codepage
+        out.push(OPTIONAL);
+        self.client_id.encode(out);
+        self.req_file_id.encode(out);
+        write_optional(out, self.server_id.as_deref());
+        write_optional(out, self.username.as_deref());
+        write_optional(out, self.password.as_deref());
+        write_unsigned(out, SML_VERSION);
+    }
+}
+
+impl Encode for GetListRequest {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, GET_LIST_REQUEST);
+        write_list(out, 5);
+        self.client_id.encode(out);
+        write_optional(out, self.server_id.as_deref());
+        write_optional(out, self.username.as_deref());
+        write_optional(out, self.password.as_deref());
+        write_optional(out, self.list_name.as_deref());
+    }
+}
+
+impl Encode for GetProcParameterRequest {
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_list(out, 2);
+        write_unsigned_16(out, GET_PROC_PARAMETER_REQUEST);
+        write_list(out, 5);
+        write_optional(out, self.server_id.as_deref());
+        write_optional(out, self.username.as_deref());
+        write_optional(out, self.password.as_deref());
+        write_tree_path(out, &self.parameter_tree_path);
+        write_optional(out, self.attribute.as_deref());
+    }
+}
+
+impl Encode for SmlRequest {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            SmlRequest::Open(request) => request.encode(out),
+            SmlRequest::GetList(request) => request.encode(out),
+            SmlRequest::GetProcParameter(request) => request.encode(out),
+            SmlRequest::Close => {
+                write_list(out, 2);
+                write_unsigned_16(out, CLOSE_REQUEST);
+                write_list(out, 1);
+                // globalSignature
+                out.push(OPTIONAL);
+            }
+        }
+    }
+}
+
+/// A request sent as part of a [RequestFile]
+#[derive(PartialEq, Debug, Clone)]
+pub struct PendingRequest {
+    pub transaction_id: Vec<u8>,
+    pub group_no: u8,
+    pub request: SmlRequest,
+}
+
+/// A complete transport frame containing an SML request file
+#[derive(PartialEq, Debug, Clone)]
+pub struct RequestFile {
+    /// bytes to send to the meter
+    pub frame: Vec<u8>,
+    pub requests: Vec<PendingRequest>,
+}
+
+impl RequestFile {
+    /// Find the request a reply (parsed by [crate::application::parser::parse_body_with_headers])
+    /// answers
+    pub fn request_for(&self, reply: &SmlMessage) -> Option<&SmlRequest> {
+        self.requests
+            .iter()
+            .find(|pending| pending.transaction_id == reply.transaction_id)
+            .map(|pending| &pending.request)
+    }
+}
+
+/// Builds request files, filling in transaction ids, group numbers and checksums
+pub struct RequestBuilder {
+    client_id: Vec<u8>,
+    server_id: Option<Vec<u8>>,
+    username: Option<Vec<u8>>,
+    password: Option<Vec<u8>>,
+    next_file: u32,
+}
+
+impl RequestBuilder {
+    pub fn new(client_id: &[u8]) -> Self {
+        Self {
+            client_id: client_id.to_vec(),
+            server_id: None,
+            username: None,
+            password: None,
+            next_file: 1,
+        }
+    }
+
+    /// Address a specific meter
+    pub fn server_id(mut self, server_id: &[u8]) -> Self {
+        self.server_id = Some(server_id.to_vec());
+        self
+    }
+
+    pub fn username(mut self, username: &[u8]) -> Self {
+        self.username = Some(username.to_vec());
+        self
+    }
+
+    /// Password or PIN sent with every request
+    pub fn password(mut self, password: &[u8]) -> Self {
+        self.password = Some(password.to_vec());
+        self
+    }
+
+    /// Build a request file around the given requests
+    ///
+    /// Adds OpenRequest and CloseRequest. Client id, server id and credentials
+    /// of the builder are filled into the requests where they are not set.
+    pub fn build(&mut self, requests: Vec<SmlRequest>) -> RequestFile {
+        let file = self.next_file;
+        self.next_file = self.next_file.wrapping_add(1);
+
+        let open = SmlRequest::Open(OpenRequest {
+            client_id: self.client_id.clone(),
+            req_file_id: file.to_be_bytes().to_vec(),
+            server_id: self.server_id.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+        });
+
+        let mut all = Vec::with_capacity(requests.len() + 2);
+        all.push(open);
+        all.extend(requests.into_iter().map(|request| self.complete(request)));
+        all.push(SmlRequest::Close);
+
+        let mut body = Vec::new();
+        let mut pending = Vec::with_capacity(all.len());
+        for (index, request) in all.into_iter().enumerate() {
+            let mut transaction_id = file.to_be_bytes().to_vec();
+            transaction_id.extend_from_slice(&(index as u16).to_be_bytes());
+            let group_no = 0;
+            encode_message(&mut body, &transaction_id, group_no, &request);
+            pending.push(PendingRequest {
+                transaction_id,
+                group_no,
+                request,
+            });
+        }
+
+        RequestFile {
+            frame: encode_frame(&body),
+            requests: pending,
+        }
+    }
+
+    fn complete(&self, request: SmlRequest) -> SmlRequest {
+        match request {
+            SmlRequest::GetList(mut request) => {
+                if request.client_id.is_empty() {
+                    request.client_id = self.client_id.clone();
+                }
+                request.server_id = request.server_id.or_else(|| self.server_id.clone());
+                request.username = request.username.or_else(|| self.username.clone());
+                request.password = request.password.or_else(|| self.password.clone());
+                SmlRequest::GetList(request)
+            }
+            SmlRequest::GetProcParameter(mut request) => {
+                request.server_id = request.server_id.or_else(|| self.server_id.clone());
+                request.username = request.username.or_else(|| self.username.clone());
+                request.password = request.password.or_else(|| self.password.clone());
+                SmlRequest::GetProcParameter(request)
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        application::{
+            domain::{
+                AnyValue, GetOpenResponseBody, GetProcParameterResponseBody, ProcParValue,
+                SmlMessageEnvelope, SmlTree,
+            },
+            parser::parse_body_with_headers,
+        },
+        transport::{crc16, SMLMessageBuilder},
+    };
+
+    #[test]
+    pub fn builds_request_file() {
+        let mut builder = RequestBuilder::new(&[0xaa, 0xbb]).password(b"1234");
+
+        let file = builder.build(vec![SmlRequest::GetList(GetListRequest::default())]);
+
+        assert_eq!(file.requests.len(), 3);
+        assert!(matches!(file.requests[0].request, SmlRequest::Open(_)));
+        assert_eq!(
+            file.requests[1].request,
+            SmlRequest::GetList(GetListRequest {
+                client_id: vec![0xaa, 0xbb],
+                password: Some(b"1234".to_vec()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(file.requests[2].request, SmlRequest::Close);
+        assert_eq!(file.requests[1].transaction_id, vec![0, 0, 0, 1, 0, 1]);
+
+        let frame = &file.frame;
+        assert_eq!(frame.len() % 4, 0);
+        let crc = crc16(&frame[..frame.len() - 2]).to_le_bytes();
+        assert_eq!(frame[frame.len() - 2..], crc);
+    }
+
+    #[test]
+    pub fn encodes_get_list_request() {
+        let mut out = vec![];
+
+        GetListRequest {
+            client_id: vec![0x01, 0x02],
+            list_name: Some(vec![0x01, 0x00, 0x62, 0x0a, 0xff, 0xff]),
+            ..Default::default()
+        }
+        .encode(&mut out);
+
+        assert_eq!(
+            out,
+            vec![
+                0x72, 0x63, 0x07, 0x00, 0x75, 0x03, 0x01, 0x02, 0x01, 0x01, 0x01, 0x07, 0x01, 0x00,
+                0x62, 0x0a, 0xff, 0xff
+            ]
+        );
+    }
+
+    #[test]
+    pub fn matches_replies_by_transaction_id() {
+        let mut builder = RequestBuilder::new(b"client");
+        let path = vec![vec![0x81, 0x00, 0x60, 0x05, 0x00, 0x00]];
+        let file = builder.build(vec![SmlRequest::GetProcParameter(
+            GetProcParameterRequest {
+                parameter_tree_path: path.clone(),
+                ..Default::default()
+            },
+        )]);
+
+        // the meter echoes the transaction ids of the requests
+        let mut reply = vec![];
+        encode_message(
+            &mut reply,
+            &file.requests[0].transaction_id,
+            0,
+            &SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                server_id: vec![1, 2, 3],
+                req_file_id: vec![0, 0, 0, 1],
+            }),
+        );
+        encode_message(
+            &mut reply,
+            &file.requests[1].transaction_id,
+            0,
+            &SmlMessageEnvelope::GetProcParameterResponse(GetProcParameterResponseBody {
+                server_id: vec![1, 2, 3],
+                parameter_tree_path: path.clone(),
+                parameter_tree: SmlTree {
+                    parameter_name: path[0].clone(),
+                    parameter_value: Some(ProcParValue::Value(AnyValue::Unsigned(0x0102))),
+                    child_list: vec![],
+                },
+            }),
+        );
+        let mut transport = SMLMessageBuilder::Empty;
+        transport.record(&encode_frame(&reply));
+        let SMLMessageBuilder::Complete { data, .. } = transport else {
+            panic!("frame not complete")
+        };
+
+        let replies = parse_body_with_headers(&data).unwrap();
+
+        assert!(matches!(
+            file.request_for(&replies[0]),
+            Some(SmlRequest::Open(_))
+        ));
+        assert!(matches!(
+            file.request_for(&replies[1]),
+            Some(SmlRequest::GetProcParameter(request)) if request.parameter_tree_path == path
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use super::list_responses;
+use crate::{
+    application::{
+        domain::{SmlMessages, SmlTime},
+        obis::ObisNotation,
+    },
+    hex::Hex,
+};
+
+/// Encoder of readings into CSV with one column per OBIS number
+///
+/// The columns `time` and `server_id` are followed by the OBIS numbers in ascending
+/// order, so the column order only depends on the set of OBIS numbers. Each meter
+/// becomes one row; values of OBIS numbers without column are left out and missing
+/// values are left empty.
+#[derive(Debug, Clone)]
+pub struct CsvEncoder {
+    columns: Vec<Vec<u8>>,
+}
+
+impl CsvEncoder {
+    /// Columns for the given OBIS numbers
+    pub fn new<T: AsRef<[u8]>>(numbers: impl IntoIterator<Item = T>) -> Self {
+        let mut columns: Vec<Vec<u8>> = numbers.into_iter().map(|x| x.as_ref().to_vec()).collect();
+        columns.sort();
+        columns.dedup();
+        Self { columns }
+    }
+
+    /// Columns for the numeric values in `messages`, e.g. the first telegram of a meter
+    pub fn from_messages(messages: &SmlMessages) -> Self {
+        Self::new(list_responses(messages).flat_map(|body| {
+            body.value_list
+                .iter()
+                .filter(|entry| entry.scaled_value().is_some())
+                .map(|entry| &entry.object_name)
+        }))
+    }
+
+    /// OBIS numbers of the value columns in order
+    pub fn columns(&self) -> &[Vec<u8>] {
+        &self.columns
+    }
+
+    /// The header line
+    pub fn header(&self) -> String {
+        let mut header = String::from("time,server_id");
+        for column in &self.columns {
+            write!(header, ",{}", ObisNotation(column)).expect("writing to string");
+        }
+        header.push('\n');
+        header
+    }
+
+    /// One row per meter in `messages`, each terminated by a newline
+    ///
+    /// The time of a row is the first timestamp sent with its values, `received_at`
+    /// if there is none.
+    pub fn encode(&self, messages: &SmlMessages, received_at: u64) -> String {
+        let mut out = String::new();
+        for body in list_responses(messages) {
+            let time = body
+                .value_list
+                .iter()
+                .find_map(|entry| match entry.time() {
+                    Some(SmlTime::Timestamp(seconds)) => Some(seconds as u64),
+                    _ => None,
+                })
+                .unwrap_or(received_at);
+            write!(out, "{},{}", time, Hex(&body.server_id)).expect("writing to string");
+            for column in &self.columns {
+                let value = body
+                    .value_list
+                    .iter()
+                    .find(|entry| &entry.object_name == column)
+                    .and_then(|entry| entry.scaled_value());
+                out.push(',');
+                if let Some(value) = value {
+                    out.push_str(&value.to_string());
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue, SmlTime, ValueTime};
+
+    #[test]
+    pub fn orders_columns_by_obis_number() {
+        let first = ListResponse::new(&[1])
+            .value(&[1, 0, 16, 7, 0, 255], AnyValue::Signed(120))
+            .scaler(0)
+            .value(&[129, 129, 199, 130, 3, 255], AnyValue::String(vec![0x49]))
+            .scaler(0)
+            .value(&[1, 0, 2, 8, 0, 255], AnyValue::Unsigned(7))
+            .scaler(-1)
+            .value(&[1, 0, 1, 8, 0, 255], AnyValue::Unsigned(25))
+            .scaler(-1)
+            .messages();
+        let second = SmlMessages {
+            messages: vec![
+                ListResponse::new(&[1])
+                    .value(&[1, 0, 1, 8, 0, 255], AnyValue::Unsigned(26))
+                    .scaler(-1)
+                    .value(&[1, 0, 32, 7, 0, 255], AnyValue::Unsigned(2301))
+                    .scaler(-1)
+                    .value(&[1, 0, 16, 7, 0, 255], AnyValue::Signed(-230))
+                    .scaler(0)
+                    .time(ValueTime::Time(SmlTime::Timestamp(1704067200)))
+                    .envelope(),
+                ListResponse::new(&[2])
+                    .value(&[1, 0, 2, 8, 0, 255], AnyValue::Unsigned(3))
+                    .scaler(0)
+                    .envelope(),
+            ],
+        };
+
+        let encoder = CsvEncoder::from_messages(&first);
+
+        assert_eq!(
+            encoder.header(),
+            "time,server_id,1-0:1.8.0*255,1-0:2.8.0*255,1-0:16.7.0*255\n"
+        );
+        assert_eq!(
+            encoder.encode(&first, 1704067260),
+            "1704067260,01,2.5,0.7,120\n"
+        );
+        assert_eq!(
+            encoder.encode(&second, 1704067260),
+            "1704067200,01,2.6,,-230\n1704067260,02,,3,\n"
+        );
+    }
+
+    #[test]
+    pub fn ignores_order_of_given_numbers() {
+        let encoder = CsvEncoder::new([[1, 0, 2, 8, 0, 255], [1, 0, 1, 8, 0, 255]]);
+
+        assert_eq!(
+            encoder.columns(),
+            CsvEncoder::new([[1, 0, 1, 8, 0, 255], [1, 0, 2, 8, 0, 255]]).columns()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use super::{list_responses, timestamp};
+use crate::{
+    application::{
+        domain::SmlMessages,
+        obis::{Obis, ObisNotation},
+    },
+    hex::Hex,
+};
+
+/// Encoder of readings into InfluxDB line protocol
+///
+/// Each meter is a measurement named by prefix and server id, e.g. `sml_0a01`, each
+/// OBIS number a field, e.g. `1-0:16.7.0*255`. Entries with phase or tariff get `phase`
+/// and `tariff` tags and so end up in lines of their own. Timestamps are in seconds,
+/// so write with `precision=s`.
+#[derive(Debug, Clone)]
+pub struct InfluxEncoder {
+    prefix: String,
+}
+
+impl Default for InfluxEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InfluxEncoder {
+    pub fn new() -> Self {
+        Self {
+            prefix: String::from("sml"),
+        }
+    }
+
+    /// Prefix of the measurement names, defaults to `sml`
+    pub fn measurement_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Lines of the numeric values in `messages`, each terminated by a newline
+    ///
+    /// `received_at` is used for values without timestamp of their own.
+    pub fn encode(&self, messages: &SmlMessages, received_at: u64) -> String {
+        let mut out = String::new();
+        for body in list_responses(messages) {
+            let measurement = escape(&format!("{}_{}", self.prefix, Hex(&body.server_id)));
+            let mut lines: BTreeMap<(u64, String), Vec<String>> = BTreeMap::new();
+            for entry in &body.value_list {
+                let Some(value) = entry.scaled_value() else {
+                    continue;
+                };
+                let field = format!(
+                    "{}={}",
+                    escape(&ObisNotation(&entry.object_name).to_string()),
+                    value
+                );
+                lines
+                    .entry((timestamp(entry, received_at), tags(&entry.object_name)))
+                    .or_default()
+                    .push(field);
+            }
+            for ((time, tags), fields) in lines {
+                writeln!(out, "{}{} {} {}", measurement, tags, fields.join(","), time)
+                    .expect("writing to string");
+            }
+        }
+        out
+    }
+}
+
+/// Tag set of an OBIS number including the leading comma, empty without tags
+fn tags(obis: &[u8]) -> String {
+    let mut tags = String::new();
+    let Some(obis) = Obis::from_number(obis) else {
+        return tags;
+    };
+    if let Some(phase) = obis.phase() {
+        write!(tags, ",phase={}", phase).expect("writing to string");
+    }
+    if let Some(tariff) = obis.tariff() {
+        write!(tags, ",tariff={}", tariff).expect("writing to string");
+    }
+    tags
+}
+
+/// Escape commas, equal signs and spaces in measurement names and keys
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue, SmlTime, ValueTime};
+
+    #[test]
+    pub fn encodes_line_per_tag_set_and_timestamp() {
+        let messages = ListResponse::new(&[0x0a, 0x01])
+            .value(&[1, 0, 1, 8, 0, 255], AnyValue::Unsigned(25))
+            .scaler(-1)
+            .value(&[1, 0, 16, 7, 0, 255], AnyValue::Signed(-230))
+            .scaler(0)
+            .value(&[1, 0, 1, 8, 1, 255], AnyValue::Unsigned(20))
+            .scaler(0)
+            .value(&[1, 0, 32, 7, 0, 255], AnyValue::Unsigned(2301))
+            .scaler(-1)
+            .value(&[129, 129, 199, 130, 3, 255], AnyValue::String(vec![0x49]))
+            .scaler(0)
+            .value(&[1, 0, 2, 8, 0, 255], AnyValue::Unsigned(7))
+            .scaler(-1)
+            .time(ValueTime::Time(SmlTime::Timestamp(1704067200)))
+            .messages();
+
+        let lines = InfluxEncoder::new()
+            .measurement_prefix("meter")
+            .encode(&messages, 1704067260);
+
+        assert_eq!(
+            lines,
+            "meter_0a01 1-0:2.8.0*255=0.7 1704067200\n\
+             meter_0a01 1-0:1.8.0*255=2.5,1-0:16.7.0*255=-230 1704067260\n\
+             meter_0a01,phase=L1 1-0:32.7.0*255=230.1 1704067260\n\
+             meter_0a01,tariff=1 1-0:1.8.1*255=20 1704067260\n"
+        );
+    }
+
+    #[test]
+    pub fn escapes_measurement_names() {
+        assert_eq!(escape("my meter,1"), "my\\ meter\\,1");
+    }
+}
@@ -0,0 +1,69 @@
+//! Encoding of readings for time series databases and spreadsheets
+//!
+//! [InfluxEncoder] writes InfluxDB line protocol with one measurement per meter and one
+//! field per OBIS number; [CsvEncoder] writes CSV with one column per OBIS number.
+//! Values are scaled but keep the unit sent by the meter, octet strings are left out.
+//! Both take the time a telegram was received in seconds since the unix epoch, which
+//! is used for values the meter sent without timestamp.
+//! ```
+//! use hackdose_sml_parser::{
+//!     application::domain::{
+//!         AnyValue, GetListResponseBody, SmlListEntry, SmlMessageEnvelope, SmlMessages,
+//!     },
+//!     export::{CsvEncoder, InfluxEncoder},
+//! };
+//!
+//! let messages = SmlMessages {
+//!     messages: vec![SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+//!         server_id: vec![0x0a, 0x01],
+//!         list_name: vec![],
+//!         value_list: vec![SmlListEntry {
+//!             object_name: vec![1, 0, 1, 8, 0, 255],
+//!             status: None,
+//!             value_time: None,
+//!             unit: Some(30),
+//!             scaler: Some(-1),
+//!             value: AnyValue::Unsigned(25),
+//!         }],
+//!     })],
+//! };
+//!
+//! assert_eq!(
+//!     InfluxEncoder::new().encode(&messages, 1704067200),
+//!     "sml_0a01 1-0:1.8.0*255=2.5 1704067200\n"
+//! );
+//!
+//! let csv = CsvEncoder::from_messages(&messages);
+//! assert_eq!(csv.header(), "time,server_id,1-0:1.8.0*255\n");
+//! assert_eq!(csv.encode(&messages, 1704067200), "1704067200,0a01,2.5\n");
+//! ```
+use crate::application::domain::{
+    GetListResponseBody, SmlListEntry, SmlMessageEnvelope, SmlMessages, SmlTime,
+};
+
+mod csv;
+mod influx;
+
+pub use csv::CsvEncoder;
+pub use influx::InfluxEncoder;
+
+/// List responses of `messages`, i.e. the readings of each meter
+fn list_responses(messages: &SmlMessages) -> impl Iterator<Item = &GetListResponseBody> {
+    messages
+        .messages
+        .iter()
+        .filter_map(|message| match message {
+            SmlMessageEnvelope::GetListResponse(body) => Some(body),
+            _ => None,
+        })
+}
+
+/// Seconds since the unix epoch the value of `entry` refers to
+///
+/// A secIndex only counts from an unknown point, so it is replaced by `received_at`, too.
+pub(crate) fn timestamp(entry: &SmlListEntry, received_at: u64) -> u64 {
+    match entry.time() {
+        Some(SmlTime::Timestamp(seconds)) => seconds as u64,
+        _ => received_at,
+    }
+}
@@ -0,0 +1,57 @@
+//! Hex notation of octet strings like server ids
+//!
+//! ```
+//! use hackdose_sml_parser::hex::{parse_hex, Hex};
+//!
+//! assert_eq!(Hex(&[0x0a, 0x01, 0xff]).to_string(), "0a01ff");
+//! assert_eq!(format!("{:#}", Hex(&[0x0a, 0x01, 0xff])), "0a 01 ff");
+//! assert_eq!(parse_hex("0a01ff"), Some(vec![0x0a, 0x01, 0xff]));
+//! ```
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+/// Displays bytes as lower case hex, separated by spaces with `{:#}`
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if f.alternate() { " " } else { "" };
+        self.0.iter().enumerate().try_for_each(|(i, x)| {
+            let separator = if i == 0 { "" } else { separator };
+            write!(f, "{}{:02x}", separator, x)
+        })
+    }
+}
+
+/// Parse bytes written as hex without separators, e.g. a server id
+pub fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    pub fn displays_hex() {
+        assert_eq!(Hex(&[]).to_string(), "");
+        assert_eq!(alloc::format!("{:#}", Hex(&[0x1b])), "1b");
+        assert_eq!(alloc::format!("{:#}", Hex(&[0x1b, 0x01])), "1b 01");
+    }
+
+    #[test]
+    pub fn parses_hex() {
+        assert_eq!(parse_hex("0A1b"), Some(alloc::vec![0x0a, 0x1b]));
+        assert_eq!(parse_hex(""), Some(alloc::vec![]));
+        assert_eq!(parse_hex("0a1"), None);
+        assert_eq!(parse_hex("0x"), None);
+        assert_eq!(parse_hex("ä0"), None);
+    }
+}
@@ -0,0 +1,256 @@
+//! HTTP API serving the latest readings as JSON
+//!
+//! [ReadingsApi] keeps the latest list entries of each meter in [Meters], fed from a
+//! stream of messages or the updates of a [crate::message_stream::MeterHub], and serves
+//! them to clients like `curl`:
+//!
+//! * `GET /latest`: the latest values of all meters, keyed by server id and OBIS number
+//! * `GET /meters`: the server ids seen so far
+//! * `GET /events`: server-sent events, a `reading` per telegram of a meter
+//!
+//! Each meter carries `ageSeconds` since its last telegram and `stale` once that exceeds
+//! [ReadingsApi::stale_after]. When no telegram arrives within that time `/events` sends
+//! a `stale` event with the meters.
+//! ```no_run
+//! use hackdose_sml_parser::{http::ReadingsApi, message_stream::sml_message_stream};
+//!
+//! # async fn run(port: impl tokio::io::AsyncRead + Unpin + Send + 'static) {
+//! let api = ReadingsApi::new();
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+//! tokio::spawn(api.clone().serve(listener));
+//! api.feed(sml_message_stream(port)).await;
+//! # }
+//! ```
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+
+use crate::{
+    application::{
+        domain::SmlMessages,
+        obis::{Obis, ObisNotation},
+        unit,
+    },
+    hex::Hex,
+    meters::{MeterReading, MeterUpdate, Meters, ReadingValue},
+};
+
+mod server;
+
+/// Time without telegram after which a meter is stale unless configured
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// Events buffered per `/events` client before it misses some
+const EVENT_CAPACITY: usize = 16;
+
+/// Latest readings of all meters, shared between feeding and serving
+///
+/// Clones refer to the same readings.
+#[derive(Debug, Clone)]
+pub struct ReadingsApi {
+    meters: Arc<Mutex<Meters>>,
+    events: broadcast::Sender<String>,
+    stale_after: Duration,
+}
+
+impl Default for ReadingsApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadingsApi {
+    pub fn new() -> Self {
+        Self {
+            meters: Arc::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            stale_after: DEFAULT_STALE_AFTER,
+        }
+    }
+
+    /// Mark meters stale if no telegram arrived for `duration`, defaults to 30 s
+    pub fn stale_after(mut self, duration: Duration) -> Self {
+        self.stale_after = duration;
+        self
+    }
+
+    /// Keep the list entries of `messages` as latest readings and notify `/events`
+    pub fn record(&self, messages: &SmlMessages) {
+        let updates = self.lock().record(0, messages);
+        updates.iter().for_each(|update| self.notify(update));
+    }
+
+    /// Keep the values of an update of a [crate::message_stream::MeterHub] and notify
+    /// `/events`
+    pub fn update(&self, update: &MeterUpdate) {
+        self.lock().update(update);
+        self.notify(update);
+    }
+
+    fn notify(&self, update: &MeterUpdate) {
+        let values = values(&update.readings);
+        let event = json!({ "serverId": Hex(&update.server_id).to_string(), "values": values });
+        // without subscribers there is nobody to tell
+        let _ = self.events.send(event.to_string());
+    }
+
+    /// Record the messages of `stream` until it ends, skipping errors
+    pub async fn feed<E>(&self, stream: impl Stream<Item = Result<SmlMessages, E>>) {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(messages) = stream.next().await {
+            if let Ok(messages) = messages {
+                self.record(&messages);
+            }
+        }
+    }
+
+    /// Body of `GET /latest`
+    pub fn latest(&self) -> Value {
+        let now = Instant::now();
+        let meters = self.lock();
+        let stale = meters
+            .meters()
+            .all(|server_id| self.is_stale(&meters, server_id, now));
+        let meters: Map<String, Value> = meters
+            .meters()
+            .map(|server_id| {
+                let mut status = self.status(&meters, server_id, now);
+                let readings: Vec<_> = meters.readings(server_id).cloned().collect();
+                status.insert("values".into(), values(&readings).into());
+                (Hex(server_id).to_string(), status.into())
+            })
+            .collect();
+        json!({ "stale": stale, "meters": meters })
+    }
+
+    /// Body of `GET /meters`
+    pub fn meters(&self) -> Value {
+        let now = Instant::now();
+        let meters = self.lock();
+        meters
+            .meters()
+            .map(|server_id| {
+                let mut status = Map::new();
+                status.insert("serverId".into(), Hex(server_id).to_string().into());
+                status.extend(self.status(&meters, server_id, now));
+                Value::Object(status)
+            })
+            .collect()
+    }
+
+    fn status(&self, meters: &Meters, server_id: &[u8], now: Instant) -> Map<String, Value> {
+        let mut status = Map::new();
+        let age = age(meters, server_id, now);
+        status.insert("ageSeconds".into(), age.as_secs_f64().into());
+        status.insert("stale".into(), self.is_stale(meters, server_id, now).into());
+        status
+    }
+
+    fn is_stale(&self, meters: &Meters, server_id: &[u8], now: Instant) -> bool {
+        age(meters, server_id, now) > self.stale_after
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Meters> {
+        self.meters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Time since the last telegram of the meter with `server_id`
+fn age(meters: &Meters, server_id: &[u8], now: Instant) -> Duration {
+    meters
+        .received(server_id)
+        .map_or(Duration::MAX, |received| {
+            now.saturating_duration_since(received)
+        })
+}
+
+/// Readings keyed by OBIS number with name, scaled value and unit
+fn values(readings: &[MeterReading]) -> Map<String, Value> {
+    readings
+        .iter()
+        .map(|reading| {
+            let mut value = Map::new();
+            if let Some(obis) = Obis::from_number(&reading.obis) {
+                value.insert("name".into(), obis.name().into());
+            }
+            value.insert(
+                "value".into(),
+                match &reading.value {
+                    ReadingValue::Octets(octets) => Hex(octets).to_string().into(),
+                    ReadingValue::Number(number) => (*number).into(),
+                },
+            );
+            if let Some(symbol) = reading.unit.and_then(unit::symbol) {
+                value.insert("unit".into(), symbol.into());
+            }
+            (ObisNotation(&reading.obis).to_string(), value.into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue, SmlMessageEnvelope};
+
+    pub(super) fn readings(server_id: u8, energy: usize) -> SmlMessages {
+        SmlMessages {
+            messages: vec![
+                SmlMessageEnvelope::GetCloseResponse,
+                ListResponse::new(&[0x0a, server_id])
+                    .value(
+                        &[129, 129, 199, 130, 3, 255],
+                        AnyValue::String(b"ISK".to_vec()),
+                    )
+                    .value(&[1, 0, 1, 8, 0, 255], AnyValue::Unsigned(energy))
+                    .unit(30)
+                    .scaler(-1)
+                    .envelope(),
+            ],
+        }
+    }
+
+    #[test]
+    pub fn keeps_latest_values_per_meter() {
+        let api = ReadingsApi::new();
+        assert_eq!(api.latest(), json!({ "stale": true, "meters": {} }));
+
+        api.record(&readings(1, 25));
+        api.record(&readings(2, 10));
+        api.record(&readings(1, 26));
+
+        let latest = api.latest();
+        assert_eq!(latest["stale"], false);
+        assert_eq!(latest["meters"]["0a01"]["stale"], false);
+        assert_eq!(
+            latest["meters"]["0a01"]["values"],
+            json!({
+                "129-129:199.130.3*255": { "value": "49534b" },
+                "1-0:1.8.0*255": {
+                    "name": "PositiveActiveEnergyTotal",
+                    "value": 2.6,
+                    "unit": "Wh",
+                },
+            })
+        );
+        let meters = api.meters();
+        assert_eq!(meters[0]["serverId"], "0a01");
+        assert_eq!(meters[1]["serverId"], "0a02");
+    }
+
+    #[test]
+    pub fn marks_meters_without_recent_telegram_stale() {
+        let api = ReadingsApi::new().stale_after(Duration::ZERO);
+        api.record(&readings(1, 25));
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(api.latest()["stale"], true);
+        assert_eq!(api.meters()[0]["stale"], true);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{io, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::error::RecvError,
+    time::timeout,
+};
+
+use super::ReadingsApi;
+use crate::request::{Request, RequestHead};
+
+/// Time a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl ReadingsApi {
+    /// Serve the API to clients of `listener`
+    ///
+    /// Handles each connection on its own task and returns only when accepting
+    /// connections fails.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let api = self.clone();
+            tokio::spawn(async move {
+                // a client going away only concerns its own request
+                let _ = api.respond(stream).await;
+            });
+        }
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        let request = match timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+            Ok(request) => request?,
+            Err(_) => return Ok(()),
+        };
+
+        let (status, body) = match request {
+            Request::Get(path) => match path.as_str() {
+                "/latest" => ("200 OK", self.latest().to_string()),
+                "/meters" => ("200 OK", self.meters().to_string()),
+                "/events" => return self.events(stream).await,
+                _ => ("404 Not Found", String::from(r#"{"error":"not found"}"#)),
+            },
+            Request::Other => (
+                "405 Method Not Allowed",
+                String::from(r#"{"error":"method not allowed"}"#),
+            ),
+            Request::Invalid => (
+                "400 Bad Request",
+                String::from(r#"{"error":"bad request"}"#),
+            ),
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.flush().await
+    }
+
+    /// Stream readings as server-sent events until the client disconnects
+    ///
+    /// Sends one `stale` event when no reading arrived for [ReadingsApi::stale_after],
+    /// the next only after another reading.
+    async fn events(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut events = self.events.subscribe();
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            )
+            .await?;
+        stream.flush().await?;
+        let mut stale = false;
+        loop {
+            let received = match stale {
+                true => Ok(events.recv().await),
+                false => timeout(self.stale_after, events.recv()).await,
+            };
+            let event = match received {
+                Ok(Ok(reading)) => {
+                    stale = false;
+                    format!("event: reading\ndata: {}\n\n", reading)
+                }
+                // the client missed readings, the next one is complete again
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) => return Ok(()),
+                Err(_) => {
+                    stale = true;
+                    format!("event: stale\ndata: {}\n\n", self.meters())
+                }
+            };
+            stream.write_all(event.as_bytes()).await?;
+            stream.flush().await?;
+        }
+    }
+}
+
+/// Read the head of the request, ignoring any body
+async fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut head = RequestHead::default();
+    let mut buf = [0; 1024];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(request) = head.record(&buf[..n]) {
+            return Ok(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::test::readings;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    async fn connect(address: std::net::SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        stream
+    }
+
+    async fn get(address: std::net::SocketAddr, path: &str) -> String {
+        let mut response = String::new();
+        connect(address, path)
+            .await
+            .read_to_string(&mut response)
+            .await
+            .unwrap();
+        response
+    }
+
+    /// Read from `stream` until the received bytes end with `end`
+    async fn read_until(stream: &mut TcpStream, end: &str) -> String {
+        let mut received = vec![];
+        let mut buf = [0; 1024];
+        while !received.ends_with(end.as_bytes()) {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed");
+            received.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(received).unwrap()
+    }
+
+    #[test]
+    pub fn serves_latest_readings_and_meters() {
+        runtime().block_on(async {
+            let api = ReadingsApi::new();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(api.clone().serve(listener));
+
+            api.feed(tokio_stream::iter(vec![
+                Ok(readings(1, 25)),
+                Err(()),
+                Ok(readings(2, 10)),
+            ]))
+            .await;
+
+            let latest = get(address, "/latest").await;
+            assert!(latest.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(latest.contains("Content-Type: application/json\r\n"));
+            let body: serde_json::Value =
+                serde_json::from_str(latest.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+            assert_eq!(body["stale"], false);
+            assert_eq!(
+                body["meters"]["0a02"]["values"],
+                api.latest()["meters"]["0a02"]["values"]
+            );
+
+            let meters = get(address, "/meters").await;
+            assert!(meters.contains(r#""serverId":"0a01""#));
+            assert!(meters.contains(r#""serverId":"0a02""#));
+            assert!(get(address, "/").await.starts_with("HTTP/1.1 404"));
+        });
+    }
+
+    #[test]
+    pub fn streams_readings_and_staleness_as_events() {
+        runtime().block_on(async {
+            let api = ReadingsApi::new().stale_after(Duration::from_millis(200));
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(api.clone().serve(listener));
+
+            let mut stream = connect(address, "/events").await;
+            let headers = read_until(&mut stream, "\r\n\r\n").await;
+            assert!(headers.contains("Content-Type: text/event-stream\r\n"));
+
+            api.record(&readings(1, 25));
+            let reading = read_until(&mut stream, "\n\n").await;
+            assert!(reading.starts_with("event: reading\ndata: {"));
+            assert!(reading.contains(r#""serverId":"0a01""#));
+            assert!(reading.contains(r#""value":2.5"#));
+
+            let stale = read_until(&mut stream, "\n\n").await;
+            assert!(stale.starts_with("event: stale\ndata: ["));
+            assert!(stale.contains(r#""stale":true"#));
+
+            // stale once until the next reading
+            let mut buf = [0; 1];
+            let next = timeout(Duration::from_millis(500), stream.read(&mut buf)).await;
+            assert!(next.is_err(), "unexpected event after stale");
+            api.record(&readings(1, 30));
+            let reading = read_until(&mut stream, "\n\n").await;
+            assert!(reading.starts_with("event: reading\ndata: {"));
+        });
+    }
+}
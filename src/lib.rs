@@ -9,12 +9,87 @@
 //! # Application Layer
 //!
 //! The application layer handles parsing of SML messages from an SML message body.
-//! It reads actual data from SML messages
+//! It reads actual data from SML messages. The parsers ending in `_borrowed` return
+//! [application::borrowed] messages referring to the input instead of copying it.
 //!
 //! # Message Stream
 //! This reflects the main use-case for using this crate: It converts a byte-stream
-//! to a stream of valid SML messages.
+//! to a stream of valid SML messages. [message_stream::TcpSource] reads from WiFi
+//! read heads and ser2net, reconnecting after failures and stalls.
+//! [message_stream::MeterHub] combines several sources and tracks each meter.
 //!
+//! # Meters
+//! [meters] keeps the latest values of several meters, fed from messages or from the
+//! updates of a hub. The exporters for Prometheus, MQTT and HTTP build on it.
+//!
+//! # Encoding
+//! The [encode] module turns SML messages back into bytes, e.g. to simulate a meter.
+//!
+//! # Reader
+//! The [reader] offers the same without an async runtime: it reads SML frames from
+//! a blocking [std::io::Read].
+//!
+//! # Annotation
+//! [annotate] explains every byte of a frame as annotated hex listing, even if the
+//! frame cannot be parsed.
+//!
+//! # Export
+//! The [export] module encodes readings as InfluxDB line protocol or as CSV with one
+//! column per OBIS number.
+//!
+//! # Analytics
+//! [analytics] sums up energy counters per quarter hour, hour or day and integrates
+//! power readings into energy.
+//!
+//! # Capture
+//! The [capture] module records raw bytes read from a device with their timing and
+//! replays them later, e.g. to reproduce field problems in tests.
+//!
+//! # Simulator
+//! The [simulator] emits telegrams of a virtual meter, e.g. to test dashboards without
+//! a meter. The `sml-simulator` binary writes them to stdout, a file, a PTY or TCP clients.
+//!
+//! # Features
+//!
+//! * `std` (default): enables the [reader] and [meters]. Without it, [transport] and [application]
+//!   only require `alloc`; [transport::FrameDecoder] decodes without allocating.
+//! * `async-tokio` (default): enables the [message_stream] for tokio readers.
+//! * `async-futures`: enables the [message_stream] for `futures::AsyncRead` readers.
+//! * `simulator`: enables the [simulator] and the `sml-simulator` binary.
+//! * `serial`: enables [serial] for reading from serial ports like optical read heads.
+//! * `prometheus`: enables [prometheus] exporting readings in OpenMetrics format.
+//! * `mqtt`: enables [mqtt] publishing readings with Home Assistant discovery.
+//! * `http`: enables [http] serving the latest readings as JSON and server-sent events.
+//! * `cli`: enables the `sml` binary printing decoded frames as tree, JSON lines or CSV.
+//!
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analytics;
+pub mod annotate;
 pub mod application;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod encode;
+pub mod export;
+pub mod hex;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(any(feature = "async-tokio", feature = "async-futures"))]
 pub mod message_stream;
+#[cfg(feature = "std")]
+pub mod meters;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "std")]
+pub mod reader;
+#[cfg(any(feature = "prometheus", feature = "http"))]
+mod request;
+#[cfg(feature = "serial")]
+pub mod serial;
+#[cfg(feature = "simulator")]
+pub mod simulator;
 pub mod transport;
@@ -0,0 +1,196 @@
+use std::{
+    io::ErrorKind,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use super::SmlStreamError;
+use crate::{
+    application::domain::SmlMessages,
+    transport::{FrameAssembler, TransportStats},
+};
+
+/// Byte source which can be polled for data
+///
+/// Implemented by the adapters [TokioRead] and [FuturesRead] so that
+/// [SmlFrameStream] works with either flavour of `AsyncRead`.
+pub trait PollRead {
+    fn poll_read_bytes(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>>;
+}
+
+/// Adapter for [tokio::io::AsyncRead]
+#[cfg(feature = "async-tokio")]
+pub struct TokioRead<R>(pub R);
+
+#[cfg(feature = "async-tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> PollRead for TokioRead<R> {
+    fn poll_read_bytes(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+        match Pin::new(&mut self.0).poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Adapter for [futures_io::AsyncRead]
+#[cfg(feature = "async-futures")]
+pub struct FuturesRead<R>(pub R);
+
+#[cfg(feature = "async-futures")]
+impl<R: futures_io::AsyncRead + Unpin> PollRead for FuturesRead<R> {
+    fn poll_read_bytes(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+/// Stream of SML messages read directly from an async reader
+///
+/// Does not spawn tasks or use channels: reading only happens while the
+/// stream is polled, and dropping it cancels reading.
+/// The stream ends on end of file or after an I/O error.
+/// ```
+/// use hackdose_sml_parser::message_stream::SmlFrameStream;
+///
+/// // from a tokio reader with the async-tokio feature
+/// #[cfg(feature = "async-tokio")]
+/// let stream = SmlFrameStream::from_tokio(std::io::Cursor::new(vec![0x01, 0x02, 0x03]));
+/// // from a futures reader with the async-futures feature
+/// #[cfg(feature = "async-futures")]
+/// let stream = SmlFrameStream::from_futures(futures::io::Cursor::new(vec![0x01, 0x02, 0x03]));
+/// ```
+pub struct SmlFrameStream<R> {
+    reader: R,
+    buf: Vec<u8>,
+    frames: FrameAssembler,
+    done: bool,
+}
+
+const DEFAULT_BUFFER_SIZE: usize = 512;
+
+impl<R: PollRead + Unpin> SmlFrameStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0; DEFAULT_BUFFER_SIZE],
+            frames: FrameAssembler::default(),
+            done: false,
+        }
+    }
+
+    /// Drop frames longer than `size` bytes instead of buffering them
+    ///
+    /// Defaults to [crate::transport::DEFAULT_MAX_FRAME_SIZE].
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.frames.set_max_frame_size(size);
+        self
+    }
+
+    /// Counters of the frames read so far
+    pub fn stats(&self) -> TransportStats {
+        self.frames.stats()
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> SmlFrameStream<TokioRead<R>> {
+    pub fn from_tokio(reader: R) -> Self {
+        Self::new(TokioRead(reader))
+    }
+}
+
+#[cfg(feature = "async-futures")]
+impl<R: futures_io::AsyncRead + Unpin> SmlFrameStream<FuturesRead<R>> {
+    pub fn from_futures(reader: R) -> Self {
+        Self::new(FuturesRead(reader))
+    }
+}
+
+impl<R: PollRead + Unpin> Stream for SmlFrameStream<R> {
+    type Item = Result<SmlMessages, SmlStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(frame) = this.frames.next_frame() {
+                return Poll::Ready(Some(
+                    frame
+                        .map(|(_, messages)| messages)
+                        .map_err(|body| SmlStreamError::Parse { body }),
+                ));
+            }
+
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut this.reader).poll_read_bytes(cx, &mut this.buf) {
+                Poll::Ready(Ok(0)) => this.done = true,
+                Poll::Ready(Ok(n)) => this.frames.record(&this.buf[..n]),
+                Poll::Ready(Err(e)) if e.kind() == ErrorKind::Interrupted => (),
+                Poll::Ready(Err(e)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(SmlStreamError::Io(e))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{application::domain::SmlMessageEnvelope, transport::test::CLOSE_FRAME};
+
+    fn close_response() -> SmlMessages {
+        SmlMessages {
+            messages: vec![SmlMessageEnvelope::GetCloseResponse],
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    pub fn reads_from_tokio_reader_without_runtime() {
+        use futures::{executor::block_on, StreamExt};
+
+        let mut input = CLOSE_FRAME.to_vec();
+        input.extend_from_slice(CLOSE_FRAME);
+        let stream = SmlFrameStream::from_tokio(std::io::Cursor::new(input));
+
+        let mut stream = stream;
+        let messages: Vec<_> = block_on((&mut stream).map(|x| x.unwrap()).collect());
+
+        assert_eq!(messages, vec![close_response(), close_response()]);
+        assert_eq!(stream.stats().frames, 2);
+    }
+
+    #[cfg(feature = "async-futures")]
+    #[test]
+    pub fn reads_from_futures_reader() {
+        use futures::{executor::block_on, io::Cursor, StreamExt};
+
+        let mut input = vec![0x00, 0x1b];
+        input.extend_from_slice(CLOSE_FRAME);
+        let stream = SmlFrameStream::from_futures(Cursor::new(input));
+
+        let messages: Vec<_> = block_on(stream.map(|x| x.unwrap()).collect());
+
+        assert_eq!(messages, vec![close_response()]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::{
+    application::domain::SmlMessages,
+    meters::{Derived, MeterReading, MeterUpdate, Meters},
+};
+
+type Source = Box<dyn Stream<Item = Option<SmlMessages>> + Send + Unpin>;
+
+/// Combines the telegrams of several meters and keeps their latest values
+///
+/// Sources are streams of messages, e.g. from [super::sml_message_stream] or
+/// [super::SmlFrameStream]. List responses are told apart by server id, so one source
+/// may carry several meters like on an RS-485 bus. The hub yields a [MeterUpdate] per
+/// list response and keeps the last value of every OBIS number of every meter in
+/// [Meters]. Errors of sources are skipped, the hub ends when all sources ended.
+/// ```
+/// use hackdose_sml_parser::{
+///     application::{domain::SmlMessages, obis::Obis},
+///     message_stream::{Derived, MeterHub, SmlStreamError},
+/// };
+///
+/// // e.g. an SmlFrameStream of each read head
+/// let grid = futures::stream::iter(Vec::<Result<SmlMessages, SmlStreamError>>::new());
+/// let pv = futures::stream::iter(Vec::<Result<SmlMessages, SmlStreamError>>::new());
+/// let hub = MeterHub::new().source(grid).source(pv).derive(
+///     Derived::new("house_consumption")
+///         .add([0x0a, 0x01], Obis::SumActiveInstantaneousPower)
+///         .add([0x0a, 0x02], Obis::SumActiveInstantaneousPower),
+/// );
+/// ```
+pub struct MeterHub {
+    sources: Vec<Option<Source>>,
+    /// source polled first next time, so that a busy source cannot starve the others
+    next: usize,
+    meters: Meters,
+    pending: VecDeque<MeterUpdate>,
+}
+
+impl Default for MeterHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MeterHub {
+    pub fn new() -> Self {
+        Self {
+            sources: vec![],
+            next: 0,
+            meters: Meters::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Read telegrams from `stream`
+    pub fn source<E>(
+        mut self,
+        stream: impl Stream<Item = Result<SmlMessages, E>> + Send + 'static,
+    ) -> Self {
+        self.sources
+            .push(Some(Box::new(SkipErrors(Box::pin(stream)))));
+        self
+    }
+
+    /// Compute `derived` whenever one of its meters sends an update
+    pub fn derive(mut self, derived: Derived) -> Self {
+        self.meters = self.meters.derive(derived);
+        self
+    }
+
+    /// Server ids of the meters seen so far
+    pub fn meters(&self) -> impl Iterator<Item = &[u8]> {
+        self.meters.meters()
+    }
+
+    /// Latest values of the meter with `server_id`, ordered by OBIS number
+    pub fn readings(&self, server_id: &[u8]) -> impl Iterator<Item = &MeterReading> {
+        self.meters.readings(server_id)
+    }
+
+    /// Latest value of `obis` of the meter with `server_id`
+    pub fn reading(&self, server_id: &[u8], obis: &[u8]) -> Option<&MeterReading> {
+        self.meters.reading(server_id, obis)
+    }
+
+    /// Current value of `derived`, `None` until all its terms are known
+    pub fn derived_value(&self, derived: &Derived) -> Option<f64> {
+        self.meters.derived_value(derived)
+    }
+}
+
+impl Stream for MeterHub {
+    type Item = MeterUpdate;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(update) = this.pending.pop_front() {
+                return Poll::Ready(Some(update));
+            }
+            if this.sources.iter().all(Option::is_none) {
+                return Poll::Ready(None);
+            }
+
+            let count = this.sources.len();
+            let mut received = false;
+            for offset in 0..count {
+                let index = (this.next + offset) % count;
+                let Some(source) = &mut this.sources[index] else {
+                    continue;
+                };
+                match Pin::new(source).poll_next(cx) {
+                    Poll::Ready(Some(Some(messages))) => {
+                        this.next = (index + 1) % count;
+                        let updates = this.meters.record(index, &messages);
+                        this.pending.extend(updates);
+                        received = true;
+                        break;
+                    }
+                    // errors are skipped, poll again
+                    Poll::Ready(Some(None)) => {
+                        this.next = (index + 1) % count;
+                        received = true;
+                        break;
+                    }
+                    Poll::Ready(None) => this.sources[index] = None,
+                    Poll::Pending => (),
+                }
+            }
+            if !received && this.sources.iter().any(Option::is_some) {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// Stream yielding `None` for errors
+struct SkipErrors<S>(Pin<Box<S>>);
+
+impl<S: Stream<Item = Result<SmlMessages, E>>, E> Stream for SkipErrors<S> {
+    type Item = Option<SmlMessages>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .as_mut()
+            .poll_next(cx)
+            .map(|item| item.map(Result::ok))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{executor::block_on, stream, StreamExt};
+
+    use super::*;
+    use crate::{
+        application::{
+            domain::{test::ListResponse, AnyValue},
+            obis::Obis,
+        },
+        meters::DerivedValue,
+    };
+
+    fn readings(server_id: u8, entries: &[(Obis, isize)]) -> SmlMessages {
+        entries
+            .iter()
+            .fold(
+                ListResponse::new(&[0x0a, server_id]),
+                |list, (obis, value)| {
+                    list.value(obis.obis_number(), AnyValue::Signed(*value))
+                        .unit(27)
+                        .scaler(0)
+                },
+            )
+            .messages()
+    }
+
+    fn house_consumption() -> Derived {
+        Derived::new("house_consumption")
+            .add([0x0a, 0x01], Obis::PositiveActiveEnergyTotal)
+            .add([0x0a, 0x02], Obis::NegativeActiveEnergyTotal)
+            .subtract([0x0a, 0x01], Obis::NegativeActiveEnergyTotal)
+    }
+
+    #[test]
+    pub fn demultiplexes_meters_and_derives_values() {
+        let bus = stream::iter(vec![
+            Ok(readings(
+                1,
+                &[
+                    (Obis::PositiveActiveEnergyTotal, 100),
+                    (Obis::NegativeActiveEnergyTotal, 30),
+                ],
+            )),
+            Err(()),
+            Ok(readings(3, &[(Obis::PositiveActiveEnergyTotal, 7)])),
+        ]);
+        let pv = stream::iter(vec![Ok::<_, ()>(readings(
+            2,
+            &[(Obis::NegativeActiveEnergyTotal, 50)],
+        ))]);
+
+        let mut hub = MeterHub::new()
+            .source(bus)
+            .source(pv)
+            .derive(house_consumption());
+        let updates: Vec<_> = block_on((&mut hub).collect());
+
+        let sources: Vec<_> = updates
+            .iter()
+            .map(|update| (update.source, update.server_id[1]))
+            .collect();
+        assert_eq!(sources, vec![(0, 1), (1, 2), (0, 3)]);
+        assert_eq!(updates[0].derived, vec![]);
+        assert_eq!(
+            updates[1].derived,
+            vec![DerivedValue {
+                name: String::from("house_consumption"),
+                value: 120.0,
+            }]
+        );
+        assert_eq!(updates[2].derived, vec![]);
+
+        assert_eq!(hub.meters().count(), 3);
+        assert_eq!(hub.readings(&[0x0a, 0x01]).count(), 2);
+        assert_eq!(
+            hub.reading(&[0x0a, 0x02], Obis::NegativeActiveEnergyTotal.obis_number())
+                .and_then(|reading| reading.value.as_number()),
+            Some(50.0)
+        );
+        assert_eq!(hub.derived_value(&house_consumption()), Some(120.0));
+    }
+
+    #[test]
+    pub fn polls_all_sources_until_they_end() {
+        let pending = stream::pending::<Result<SmlMessages, ()>>();
+        let ready = stream::iter(vec![Ok::<_, ()>(readings(1, &[]))]);
+        let mut hub = MeterHub::new().source(pending).source(ready);
+
+        let update = block_on(hub.next()).unwrap();
+
+        assert_eq!(update.source, 1);
+        assert!(futures::FutureExt::now_or_never(hub.next()).is_none());
+    }
+}
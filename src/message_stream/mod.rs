@@ -1,64 +1,172 @@
-use tokio::{
-    io::{AsyncRead, AsyncReadExt},
-    sync::mpsc::{self, Sender},
-};
-use tokio_stream::{wrappers::ReceiverStream, Stream};
+use std::fmt;
 
-use crate::{
-    application::{domain::SmlMessages, parser::parse_body},
-    transport::SMLMessageBuilder,
-};
+#[cfg(feature = "async-tokio")]
+use tokio::{io::AsyncRead, sync::mpsc};
+#[cfg(feature = "async-tokio")]
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+#[cfg(feature = "async-tokio")]
+use crate::application::domain::SmlMessages;
+
+mod frame_stream;
+mod hub;
+#[cfg(feature = "async-tokio")]
+mod tcp;
+
+pub use crate::meters::{Derived, DerivedValue, MeterReading, MeterUpdate};
+#[cfg(feature = "async-futures")]
+pub use frame_stream::FuturesRead;
+#[cfg(feature = "async-tokio")]
+pub use frame_stream::TokioRead;
+pub use frame_stream::{PollRead, SmlFrameStream};
+pub use hub::MeterHub;
+#[cfg(feature = "async-tokio")]
+pub use tcp::{TcpFrame, TcpSource, TcpStreamError};
+
+#[derive(Debug)]
+pub enum SmlStreamError {
+    /// Reading failed, the stream ends after this error
+    Io(std::io::Error),
+    /// A frame was found but its body could not be parsed
+    Parse {
+        /// the raw body of the frame
+        body: Vec<u8>,
+    },
+}
+
+impl fmt::Display for SmlStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmlStreamError::Io(e) => write!(f, "{}", e),
+            SmlStreamError::Parse { body } => {
+                write!(f, "cannot parse frame of {} bytes", body.len())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SmlStreamError {}
 
 /// Read SML message stream from a reader
+///
+/// Reads on a spawned tokio task. The stream ends when the reader reaches end of file or fails.
+/// Use [SmlFrameStream] to read without spawning.
 /// ```
 /// use std::io::Cursor;
+/// use hackdose_sml_parser::message_stream::sml_message_stream;
 ///
+/// # let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// # runtime.block_on(async {
 /// let cursor = Cursor::new(vec![0x01, 0x02, 0x03]);
 /// let message_stream = sml_message_stream(cursor);
+/// # });
 /// ```
+#[cfg(feature = "async-tokio")]
 pub fn sml_message_stream(
-    mut stream: impl AsyncRead + Unpin + Send + 'static,
-) -> impl Stream<Item = SmlMessages> {
-    let (tx, rx) = mpsc::channel::<SmlMessages>(256);
+    stream: impl AsyncRead + Unpin + Send + 'static,
+) -> impl Stream<Item = Result<SmlMessages, SmlStreamError>> {
+    let (tx, rx) = mpsc::channel::<Result<SmlMessages, SmlStreamError>>(256);
 
-    let mut buf = [0; 512];
-    let mut builder = SMLMessageBuilder::Empty;
+    let mut frames = SmlFrameStream::from_tokio(stream);
 
     tokio::spawn(async move {
-        while let Ok(n) = stream.read(&mut buf).await {
-            emit_message(&mut builder, &buf[..n], tx.clone()).await;
+        while let Some(frame) = frames.next().await {
+            if tx.send(frame).await.is_err() {
+                break;
+            }
         }
     });
 
     ReceiverStream::new(rx)
 }
 
-async fn emit_message<'a>(
-    builder: &'a mut SMLMessageBuilder,
-    buf: &'a [u8],
-    tx: Sender<SmlMessages>,
-) {
-    let mut to_process = buf.to_vec();
-    while to_process.len() > 0 {
-        builder.record(&to_process);
-        to_process = vec![];
-
-        match builder {
-            SMLMessageBuilder::Complete { ref data, ref rest } => {
-                let result = parse_body(data);
-                if let Ok(messages) = result {
-                    let _ = tx.send(messages).await;
-                }
-                if rest.len() == 0 {
-                    *builder = SMLMessageBuilder::Empty;
-                } else {
-                    to_process = rest.to_vec();
-                    *builder = SMLMessageBuilder::Empty;
-                }
+#[cfg(all(test, feature = "async-tokio"))]
+mod test {
+    use std::{
+        io::{self, Cursor},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::ReadBuf;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        application::domain::SmlMessageEnvelope,
+        transport::test::{CLOSE_FRAME, CORRUPT_FRAME},
+    };
+
+    /// Reader returning its data and then failing on every read
+    struct Failing(&'static [u8]);
+
+    impl AsyncRead for Failing {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if self.0.is_empty() {
+                return Poll::Ready(Err(io::Error::other("device unplugged")));
             }
-            SMLMessageBuilder::Empty => (),
-            SMLMessageBuilder::IncompleteStartSignature(_) => (),
-            SMLMessageBuilder::Recording(_) => (),
+            let n = self.0.len().min(buf.remaining());
+            buf.put_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Poll::Ready(Ok(()))
         }
     }
+
+    fn collect(
+        input: impl AsyncRead + Unpin + Send + 'static,
+    ) -> Vec<Result<SmlMessages, SmlStreamError>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async { sml_message_stream(input).collect().await })
+    }
+
+    #[test]
+    pub fn ends_on_eof() {
+        let mut input = CLOSE_FRAME.to_vec();
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let messages = collect(Cursor::new(input));
+
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            assert_eq!(
+                message.unwrap(),
+                SmlMessages {
+                    messages: vec![SmlMessageEnvelope::GetCloseResponse]
+                }
+            );
+        }
+    }
+
+    #[test]
+    pub fn surfaces_corrupt_frames() {
+        let mut input = CORRUPT_FRAME.to_vec();
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let messages = collect(Cursor::new(input));
+
+        assert!(matches!(
+            &messages[..],
+            [Err(SmlStreamError::Parse { body }), Ok(_)] if *body == vec![0x42, 0x43, 0x00, 0x00]
+        ));
+        assert_eq!(
+            messages[0].as_ref().unwrap_err().to_string(),
+            "cannot parse frame of 4 bytes"
+        );
+    }
+
+    #[test]
+    pub fn surfaces_io_error_and_ends() {
+        let messages = collect(Failing(CLOSE_FRAME));
+
+        assert!(matches!(
+            &messages[..],
+            [Ok(_), Err(SmlStreamError::Io(e))] if e.to_string() == "device unplugged"
+        ));
+    }
 }
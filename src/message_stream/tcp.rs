@@ -0,0 +1,313 @@
+use std::{fmt, io, net::SocketAddr, time::Duration};
+
+use tokio::{net::TcpStream, sync::mpsc, time::timeout};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+use super::{SmlFrameStream, SmlStreamError};
+use crate::application::domain::SmlMessages;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Messages of one frame and the address of the read head which sent it
+#[derive(PartialEq, Debug)]
+pub struct TcpFrame {
+    pub source: SocketAddr,
+    pub messages: SmlMessages,
+}
+
+/// Problems of a [TcpSource], after which it keeps going
+#[derive(Debug)]
+pub enum TcpStreamError {
+    /// Connecting to `address` failed or timed out, retried after the backoff
+    Connect { address: String, error: io::Error },
+    /// The connection was closed, by the read head if `error` is `None`
+    Disconnected {
+        source: SocketAddr,
+        error: Option<io::Error>,
+    },
+    /// No frame arrived within the stall timeout, the connection is dropped
+    Stalled { source: SocketAddr },
+    /// A frame was found but its body could not be parsed
+    Parse { source: SocketAddr, body: Vec<u8> },
+}
+
+impl fmt::Display for TcpStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpStreamError::Connect { address, error } => {
+                write!(f, "cannot connect to {}: {}", address, error)
+            }
+            TcpStreamError::Disconnected {
+                source,
+                error: Some(error),
+            } => write!(f, "{} disconnected: {}", source, error),
+            TcpStreamError::Disconnected {
+                source,
+                error: None,
+            } => {
+                write!(f, "{} closed the connection", source)
+            }
+            TcpStreamError::Stalled { source } => write!(f, "no frame from {}", source),
+            TcpStreamError::Parse { source, body } => {
+                write!(
+                    f,
+                    "cannot parse frame of {} bytes from {}",
+                    body.len(),
+                    source
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TcpStreamError {}
+
+/// Builder for reading SML from read heads exposing raw bytes on a TCP port
+///
+/// Covers WiFi read heads like Tasmota or Hichi and serial ports shared with ser2net.
+/// Reconnects with exponential backoff after connection failures and after stalls,
+/// i.e. when no frame arrives within the stall timeout.
+/// ```no_run
+/// use hackdose_sml_parser::message_stream::TcpSource;
+/// use tokio_stream::StreamExt;
+///
+/// # let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+/// # runtime.block_on(async {
+/// let mut frames = Box::pin(TcpSource::new("192.168.0.42:8888").stream());
+/// while let Some(frame) = frames.next().await {
+///     match frame {
+///         Ok(frame) => println!("{}: {:?}", frame.source, frame.messages),
+///         Err(e) => eprintln!("{:?}", e),
+///     }
+/// }
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct TcpSource {
+    address: String,
+    connect_timeout: Duration,
+    stall_timeout: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl TcpSource {
+    /// Read from `address`, e.g. `192.168.0.42:8888` or `reader.local:23`
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Give up connecting after `timeout`, defaults to 10 seconds
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Reconnect if no frame arrives within `timeout`, defaults to 30 seconds
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Wait `initial` before reconnecting, doubling up to `max` while no frame arrives
+    ///
+    /// Defaults to 1 second up to 1 minute.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Stream of frames and connection problems, which never ends
+    ///
+    /// Reads on a spawned tokio task, which stops at its next event once the stream
+    /// is dropped; the runtime needs io and time enabled.
+    pub fn stream(self) -> impl Stream<Item = Result<TcpFrame, TcpStreamError>> {
+        let (tx, rx) = mpsc::channel(256);
+        tokio::spawn(async move {
+            let mut backoff = self.initial_backoff;
+            loop {
+                match self.connect().await {
+                    Ok(stream) => {
+                        if self.read(stream, &tx, &mut backoff).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(error) => {
+                        let error = TcpStreamError::Connect {
+                            address: self.address.clone(),
+                            error,
+                        };
+                        if tx.send(Err(error)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                tokio::time::sleep(backoff).await;
+                if tx.is_closed() {
+                    return;
+                }
+                backoff = (backoff * 2).min(self.max_backoff);
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+
+    async fn connect(&self) -> io::Result<TcpStream> {
+        match timeout(self.connect_timeout, TcpStream::connect(&self.address)).await {
+            Ok(stream) => stream,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "connecting timed out",
+            )),
+        }
+    }
+
+    /// Forward frames until the connection ends, failing once the stream is dropped
+    async fn read(
+        &self,
+        stream: TcpStream,
+        tx: &mpsc::Sender<Result<TcpFrame, TcpStreamError>>,
+        backoff: &mut Duration,
+    ) -> Result<(), mpsc::error::SendError<Result<TcpFrame, TcpStreamError>>> {
+        let source = match stream.peer_addr() {
+            Ok(source) => source,
+            Err(error) => {
+                let error = TcpStreamError::Connect {
+                    address: self.address.clone(),
+                    error,
+                };
+                return tx.send(Err(error)).await;
+            }
+        };
+        let mut frames = SmlFrameStream::from_tokio(stream);
+        loop {
+            let event = match timeout(self.stall_timeout, frames.next()).await {
+                Ok(Some(Ok(messages))) => {
+                    *backoff = self.initial_backoff;
+                    Ok(TcpFrame { source, messages })
+                }
+                Ok(Some(Err(SmlStreamError::Parse { body }))) => {
+                    Err(TcpStreamError::Parse { source, body })
+                }
+                Ok(Some(Err(SmlStreamError::Io(error)))) => {
+                    return tx
+                        .send(Err(TcpStreamError::Disconnected {
+                            source,
+                            error: Some(error),
+                        }))
+                        .await;
+                }
+                Ok(None) => {
+                    return tx
+                        .send(Err(TcpStreamError::Disconnected {
+                            source,
+                            error: None,
+                        }))
+                        .await;
+                }
+                Err(_) => return tx.send(Err(TcpStreamError::Stalled { source })).await,
+            };
+            tx.send(event).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::capture::{test::capture, CaptureReader, Replay, ReplaySpeed};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    /// Replay the test capture to a client of `listener` and close the connection
+    async fn replay(listener: &TcpListener) {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let capture = CaptureReader::new(Cursor::new(capture())).unwrap();
+        let mut replay = Replay::new(capture, ReplaySpeed::Original);
+        tokio::io::copy(&mut replay, &mut socket).await.unwrap();
+    }
+
+    #[test]
+    pub fn reconnects_after_disconnect_and_stall() {
+        runtime().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let mut stream = Box::pin(
+                TcpSource::new(address.to_string())
+                    .stall_timeout(Duration::from_millis(100))
+                    .backoff(Duration::from_millis(10), Duration::from_millis(10))
+                    .stream(),
+            );
+
+            replay(&listener).await;
+            let frame = stream.next().await.unwrap().unwrap();
+            assert_eq!(frame.source, address);
+            let disconnected = stream.next().await.unwrap().unwrap_err();
+            assert!(matches!(
+                disconnected,
+                TcpStreamError::Disconnected { error: None, .. }
+            ));
+            assert_eq!(
+                disconnected.to_string(),
+                format!("{} closed the connection", address)
+            );
+
+            let (_silent, _) = listener.accept().await.unwrap();
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(TcpStreamError::Stalled { source })) if source == address
+            ));
+
+            replay(&listener).await;
+            assert!(stream.next().await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    pub fn retries_failed_connections() {
+        runtime().block_on(async {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            drop(listener);
+            let mut stream = Box::pin(
+                TcpSource::new(address.to_string())
+                    .backoff(Duration::from_millis(10), Duration::from_millis(20))
+                    .stream(),
+            );
+
+            assert!(matches!(
+                stream.next().await,
+                Some(Err(TcpStreamError::Connect { .. }))
+            ));
+
+            let listener = TcpListener::bind(address).await.unwrap();
+            replay(&listener).await;
+            loop {
+                match stream.next().await.unwrap() {
+                    Ok(frame) => break assert_eq!(frame.source, address),
+                    Err(TcpStreamError::Connect { .. }) => (),
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+        });
+    }
+}
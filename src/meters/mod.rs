@@ -0,0 +1,329 @@
+//! Latest values of several meters
+//!
+//! [Meters] keeps the last value of every OBIS number of every meter, told apart by
+//! server id, and computes [Derived] values over them. It is fed with messages or with
+//! the [MeterUpdate]s of a [crate::message_stream::MeterHub], so that the exporters
+//! share one view of the meters.
+//! ```
+//! use hackdose_sml_parser::{
+//!     application::{
+//!         domain::{
+//!             AnyValue, GetListResponseBody, SmlListEntry, SmlMessageEnvelope, SmlMessages,
+//!         },
+//!         obis::Obis,
+//!     },
+//!     meters::Meters,
+//! };
+//!
+//! let messages = SmlMessages {
+//!     messages: vec![SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+//!         server_id: vec![0x0a, 0x01],
+//!         list_name: vec![],
+//!         value_list: vec![SmlListEntry {
+//!             object_name: vec![1, 0, 16, 7, 0, 255],
+//!             status: None,
+//!             value_time: None,
+//!             unit: Some(27),
+//!             scaler: Some(0),
+//!             value: AnyValue::Signed(-300),
+//!         }],
+//!     })],
+//! };
+//! let mut meters = Meters::new();
+//! meters.record(0, &messages);
+//!
+//! let power = meters.reading(&[0x0a, 0x01], Obis::SumActiveInstantaneousPower.obis_number());
+//! assert_eq!(power.and_then(|x| x.value.as_number()), Some(-300.0));
+//! ```
+use std::{collections::BTreeMap, time::Instant};
+
+use crate::application::{
+    domain::{AnyValue, GetListResponseBody, SmlMessageEnvelope, SmlMessages, SmlTime},
+    obis::Obis,
+};
+
+/// Latest value of an OBIS number of a meter
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReading {
+    pub obis: Vec<u8>,
+    pub value: ReadingValue,
+    pub unit: Option<u8>,
+    /// time sent by the meter along with the value
+    pub time: Option<SmlTime>,
+    pub received: Instant,
+}
+
+/// Value of a [MeterReading]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadingValue {
+    /// value with its scaler applied, in the unit sent by the meter
+    Number(f64),
+    Octets(Vec<u8>),
+}
+
+impl ReadingValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            ReadingValue::Number(value) => Some(*value),
+            ReadingValue::Octets(_) => None,
+        }
+    }
+}
+
+/// The values of a meter received in one list response
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterUpdate {
+    /// index of the source in the order the sources were added
+    pub source: usize,
+    pub server_id: Vec<u8>,
+    pub readings: Vec<MeterReading>,
+    /// derived values depending on this meter, if all their terms are known
+    pub derived: Vec<DerivedValue>,
+}
+
+impl MeterUpdate {
+    /// The values of the list response `body`, without derived values
+    pub fn new(source: usize, body: &GetListResponseBody, received: Instant) -> Self {
+        let readings = body
+            .value_list
+            .iter()
+            .map(|entry| MeterReading {
+                obis: entry.object_name.clone(),
+                value: match (&entry.value, entry.scaled_value()) {
+                    (AnyValue::String(octets), _) => ReadingValue::Octets(octets.clone()),
+                    (_, value) => ReadingValue::Number(value.unwrap_or_default()),
+                },
+                unit: entry.unit,
+                time: entry.time(),
+                received,
+            })
+            .collect();
+        Self {
+            source,
+            server_id: body.server_id.clone(),
+            readings,
+            derived: vec![],
+        }
+    }
+}
+
+/// Sum of values of several meters, e.g. the consumption of a house with PV
+///
+/// All terms should have the same unit as they are added up as sent by the meters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derived {
+    pub name: String,
+    /// server id, OBIS number and factor of each term
+    pub terms: Vec<(Vec<u8>, Vec<u8>, f64)>,
+}
+
+/// Value of a [Derived] value at the time of an update
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedValue {
+    pub name: String,
+    pub value: f64,
+}
+
+impl Derived {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            terms: vec![],
+        }
+    }
+
+    /// Add the value of `obis` of the meter with `server_id`
+    pub fn add(mut self, server_id: impl Into<Vec<u8>>, obis: Obis) -> Self {
+        self.terms
+            .push((server_id.into(), obis.obis_number().to_vec(), 1.0));
+        self
+    }
+
+    /// Subtract the value of `obis` of the meter with `server_id`
+    pub fn subtract(mut self, server_id: impl Into<Vec<u8>>, obis: Obis) -> Self {
+        self.terms
+            .push((server_id.into(), obis.obis_number().to_vec(), -1.0));
+        self
+    }
+}
+
+/// Latest readings of a meter
+#[derive(Debug, Clone)]
+struct Meter {
+    readings: BTreeMap<Vec<u8>, MeterReading>,
+    /// time of the last list response
+    received: Instant,
+}
+
+/// Latest values of all meters, see the [module documentation](self)
+#[derive(Debug, Clone, Default)]
+pub struct Meters {
+    meters: BTreeMap<Vec<u8>, Meter>,
+    derived: Vec<Derived>,
+}
+
+impl Meters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute `derived` whenever one of its meters sends an update
+    pub fn derive(mut self, derived: Derived) -> Self {
+        self.derived.push(derived);
+        self
+    }
+
+    /// Keep the values of the list responses in `messages` read from `source`
+    ///
+    /// Returns an update per list response.
+    pub fn record(&mut self, source: usize, messages: &SmlMessages) -> Vec<MeterUpdate> {
+        let received = Instant::now();
+        messages
+            .messages
+            .iter()
+            .filter_map(|message| match message {
+                SmlMessageEnvelope::GetListResponse(body) => {
+                    let mut update = MeterUpdate::new(source, body, received);
+                    self.update(&update);
+                    update.derived = self.derived_values(&update.server_id);
+                    Some(update)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Keep the values of `update`, e.g. from a [crate::message_stream::MeterHub]
+    pub fn update(&mut self, update: &MeterUpdate) {
+        let received = update
+            .readings
+            .iter()
+            .map(|reading| reading.received)
+            .max()
+            .unwrap_or_else(Instant::now);
+        let meter = self
+            .meters
+            .entry(update.server_id.clone())
+            .or_insert_with(|| Meter {
+                readings: BTreeMap::new(),
+                received,
+            });
+        meter.received = received;
+        for reading in &update.readings {
+            meter.readings.insert(reading.obis.clone(), reading.clone());
+        }
+    }
+
+    /// Server ids of the meters seen so far
+    pub fn meters(&self) -> impl Iterator<Item = &[u8]> {
+        self.meters.keys().map(|server_id| server_id.as_slice())
+    }
+
+    /// Time of the last list response of the meter with `server_id`
+    pub fn received(&self, server_id: &[u8]) -> Option<Instant> {
+        Some(self.meters.get(server_id)?.received)
+    }
+
+    /// Latest values of the meter with `server_id`, ordered by OBIS number
+    pub fn readings(&self, server_id: &[u8]) -> impl Iterator<Item = &MeterReading> {
+        self.meters
+            .get(server_id)
+            .into_iter()
+            .flat_map(|meter| meter.readings.values())
+    }
+
+    /// Latest value of `obis` of the meter with `server_id`
+    pub fn reading(&self, server_id: &[u8], obis: &[u8]) -> Option<&MeterReading> {
+        self.meters.get(server_id)?.readings.get(obis)
+    }
+
+    /// Current value of `derived`, `None` until all its terms are known
+    ///
+    /// A [Derived] without terms has no value.
+    pub fn derived_value(&self, derived: &Derived) -> Option<f64> {
+        if derived.terms.is_empty() {
+            return None;
+        }
+        derived
+            .terms
+            .iter()
+            .map(|(server_id, obis, factor)| {
+                Some(self.reading(server_id, obis)?.value.as_number()? * factor)
+            })
+            .sum()
+    }
+
+    /// Values of the derived values depending on the meter with `server_id`
+    fn derived_values(&self, server_id: &[u8]) -> Vec<DerivedValue> {
+        self.derived
+            .iter()
+            .filter(|derived| derived.terms.iter().any(|(id, _, _)| id == server_id))
+            .filter_map(|derived| {
+                Some(DerivedValue {
+                    name: derived.name.clone(),
+                    value: self.derived_value(derived)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::domain::test::ListResponse;
+
+    static MANUFACTURER: [u8; 6] = [129, 129, 199, 130, 3, 255];
+
+    fn readings(entries: &[([u8; 6], AnyValue)]) -> SmlMessages {
+        entries
+            .iter()
+            .fold(
+                ListResponse::new(&[0x0a, 0x01]),
+                |list, (object_name, value)| list.value(object_name, value.clone()).scaler(-1),
+            )
+            .messages()
+    }
+
+    #[test]
+    pub fn keeps_octet_strings_and_takes_hub_updates() {
+        let messages = readings(&[
+            (MANUFACTURER, AnyValue::String(b"ISK".to_vec())),
+            ([1, 0, 16, 7, 0, 255], AnyValue::Signed(-25)),
+        ]);
+        let mut hub = Meters::new();
+        let updates = hub.record(3, &messages);
+        let mut exporter = Meters::new();
+        exporter.update(&updates[0]);
+
+        let values: Vec<_> = exporter
+            .readings(&[0x0a, 0x01])
+            .map(|reading| reading.value.clone())
+            .collect();
+        assert_eq!(updates[0].source, 3);
+        assert_eq!(
+            values,
+            vec![
+                ReadingValue::Number(-2.5),
+                ReadingValue::Octets(b"ISK".to_vec())
+            ]
+        );
+        assert_eq!(
+            exporter.received(&[0x0a, 0x01]),
+            hub.received(&[0x0a, 0x01])
+        );
+    }
+
+    #[test]
+    pub fn derives_nothing_without_terms_or_numbers() {
+        let mut meters = Meters::new();
+        meters.record(1, &readings(&[(MANUFACTURER, AnyValue::String(vec![1]))]));
+        let octets = Derived {
+            name: String::from("octets"),
+            terms: vec![(vec![0x0a, 0x01], MANUFACTURER.to_vec(), 1.0)],
+        };
+
+        assert_eq!(meters.derived_value(&Derived::new("empty")), None);
+        assert_eq!(meters.derived_value(&octets), None);
+    }
+}
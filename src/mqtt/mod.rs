@@ -0,0 +1,391 @@
+//! Publishing readings to MQTT, with Home Assistant discovery
+//!
+//! [MqttPublisher] turns the list entries with known [Obis] numbers into messages on
+//! `sml/<server_id>/<obis>`, e.g. `sml/0a01/1-0:1.8.0*255`, carrying the value with its
+//! scaler applied in the unit sent by the meter. Values are published at most once per
+//! interval and only if they changed by the threshold. The first reading of each value
+//! also announces it to Home Assistant on `homeassistant/sensor/<id>/config`. Messages
+//! and the updates of a [crate::message_stream::MeterHub] can be published alike.
+//! ```no_run
+//! use std::time::Duration;
+//! use hackdose_sml_parser::{message_stream::sml_message_stream, mqtt::MqttPublisher};
+//! use rumqttc::{AsyncClient, MqttOptions};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn run(port: impl tokio::io::AsyncRead + Unpin + Send + 'static) {
+//! let (client, mut eventloop) = AsyncClient::new(MqttOptions::new("sml", "localhost", 1883), 64);
+//! tokio::spawn(async move { while eventloop.poll().await.is_ok() {} });
+//!
+//! let mut publisher = MqttPublisher::new().min_interval(Duration::from_secs(10));
+//! let mut messages = sml_message_stream(port);
+//! while let Some(Ok(messages)) = messages.next().await {
+//!     publisher.publish(&client, &messages).await.unwrap();
+//! }
+//! # }
+//! ```
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use rumqttc::{AsyncClient, ClientError, QoS};
+use serde_json::json;
+
+use crate::{
+    application::{
+        domain::{SmlMessageEnvelope, SmlMessages},
+        obis::{quantity, Obis, ObisNotation},
+        unit,
+    },
+    hex::Hex,
+    meters::MeterUpdate,
+};
+
+/// A message to publish
+#[derive(PartialEq, Debug, Clone)]
+pub struct Publication {
+    pub topic: String,
+    pub payload: String,
+    pub retain: bool,
+}
+
+/// Last published value of a topic
+struct Published {
+    value: f64,
+    at: Instant,
+}
+
+/// Builder and state for publishing readings, see the [module documentation](self)
+pub struct MqttPublisher {
+    topic_prefix: String,
+    discovery_prefix: Option<String>,
+    min_interval: Duration,
+    change_threshold: f64,
+    thresholds: HashMap<Obis, f64>,
+    published: HashMap<String, Published>,
+    announced: HashSet<String>,
+}
+
+impl Default for MqttPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MqttPublisher {
+    pub fn new() -> Self {
+        Self {
+            topic_prefix: String::from("sml"),
+            discovery_prefix: Some(String::from("homeassistant")),
+            min_interval: Duration::ZERO,
+            change_threshold: 0.0,
+            thresholds: HashMap::new(),
+            published: HashMap::new(),
+            announced: HashSet::new(),
+        }
+    }
+
+    /// Publish values below `prefix` instead of `sml`
+    pub fn topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.topic_prefix = prefix.into();
+        self
+    }
+
+    /// Prefix of Home Assistant discovery topics, `None` disables discovery
+    ///
+    /// Defaults to `homeassistant`.
+    pub fn discovery_prefix(mut self, prefix: Option<String>) -> Self {
+        self.discovery_prefix = prefix;
+        self
+    }
+
+    /// Publish each value at most once per `interval`, by default every reading is published
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Publish values only once they changed by at least `threshold`
+    pub fn change_threshold(mut self, threshold: f64) -> Self {
+        self.change_threshold = threshold;
+        self
+    }
+
+    /// Use `threshold` instead of the change threshold for `obis`
+    pub fn obis_threshold(mut self, obis: Obis, threshold: f64) -> Self {
+        self.thresholds.insert(obis, threshold);
+        self
+    }
+
+    /// Publish the values of `messages` which are due
+    pub async fn publish(
+        &mut self,
+        client: &AsyncClient,
+        messages: &SmlMessages,
+    ) -> Result<(), ClientError> {
+        let publications = self.publications(messages, Instant::now());
+        send(client, publications).await
+    }
+
+    /// Publish the values of an update of a [crate::message_stream::MeterHub] which are due
+    pub async fn publish_update(
+        &mut self,
+        client: &AsyncClient,
+        update: &MeterUpdate,
+    ) -> Result<(), ClientError> {
+        let publications = self.update_publications(update, Instant::now());
+        send(client, publications).await
+    }
+
+    /// Messages for the values of `messages` which are due at `now`
+    ///
+    /// Discovery configs precede the first value of their sensor.
+    pub fn publications(&mut self, messages: &SmlMessages, now: Instant) -> Vec<Publication> {
+        let mut publications = vec![];
+        for message in messages.messages.iter() {
+            if let SmlMessageEnvelope::GetListResponse(body) = message {
+                let update = MeterUpdate::new(0, body, now);
+                publications.extend(self.update_publications(&update, now));
+            }
+        }
+        publications
+    }
+
+    /// Messages for the values of `update` which are due at `now`
+    pub fn update_publications(&mut self, update: &MeterUpdate, now: Instant) -> Vec<Publication> {
+        let mut publications = vec![];
+        let server_id = Hex(&update.server_id).to_string();
+        for reading in update.readings.iter() {
+            let (Some(obis), Some(value)) =
+                (Obis::from_number(&reading.obis), reading.value.as_number())
+            else {
+                continue;
+            };
+            let topic = format!(
+                "{}/{}/{}",
+                self.topic_prefix,
+                server_id,
+                ObisNotation(obis.obis_number())
+            );
+            if !self.is_due(&topic, &obis, value, now) {
+                continue;
+            }
+            if let Some(discovery) = self.discovery(&server_id, &obis, reading.unit, &topic) {
+                publications.push(discovery);
+            }
+            self.published
+                .insert(topic.clone(), Published { value, at: now });
+            publications.push(Publication {
+                topic,
+                payload: value.to_string(),
+                retain: true,
+            });
+        }
+        publications
+    }
+
+    fn is_due(&self, topic: &str, obis: &Obis, value: f64, now: Instant) -> bool {
+        let Some(last) = self.published.get(topic) else {
+            return true;
+        };
+        let threshold = self
+            .thresholds
+            .get(obis)
+            .copied()
+            .unwrap_or(self.change_threshold);
+        now.saturating_duration_since(last.at) >= self.min_interval
+            && (value - last.value).abs() >= threshold
+    }
+
+    /// Discovery config for the sensor publishing on `state_topic`, if not announced yet
+    fn discovery(
+        &mut self,
+        server_id: &str,
+        obis: &Obis,
+        unit: Option<u8>,
+        state_topic: &str,
+    ) -> Option<Publication> {
+        let prefix = self.discovery_prefix.as_ref()?;
+        let number: Vec<_> = obis.obis_number().iter().map(u8::to_string).collect();
+        let unique_id = format!("sml_{}_{}", server_id, number.join("_"));
+        if !self.announced.insert(unique_id.clone()) {
+            return None;
+        }
+        let (device_class, state_class) = classes(obis);
+        let name = match obis.description().split_once(" [") {
+            Some((name, _)) => name,
+            None => obis.description(),
+        };
+        let mut config = json!({
+            "name": name,
+            "unique_id": unique_id,
+            "state_topic": state_topic,
+            "state_class": state_class,
+            "device": {
+                "identifiers": [format!("sml_{}", server_id)],
+                "name": format!("SML meter {}", server_id),
+            },
+        });
+        if let Some(device_class) = device_class {
+            config["device_class"] = json!(device_class);
+        }
+        if let Some(unit) = unit.and_then(unit::symbol) {
+            config["unit_of_measurement"] = json!(unit);
+        }
+        Some(Publication {
+            topic: format!("{}/sensor/{}/config", prefix, unique_id),
+            payload: config.to_string(),
+            retain: true,
+        })
+    }
+}
+
+async fn send(client: &AsyncClient, publications: Vec<Publication>) -> Result<(), ClientError> {
+    for publication in publications {
+        client
+            .publish(
+                publication.topic,
+                QoS::AtLeastOnce,
+                publication.retain,
+                publication.payload,
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Home Assistant device and state class of the values of `obis`
+fn classes(obis: &Obis) -> (Option<&'static str>, &'static str) {
+    let number = obis.obis_number();
+    match (quantity(number), number[3]) {
+        (1 | 2 | 15, 8) => (Some("energy"), "total_increasing"),
+        (16, 8) => (Some("energy"), "total"),
+        (_, 8) => (None, "total_increasing"),
+        (1 | 2 | 15 | 16, 7) => (Some("power"), "measurement"),
+        (11, 7) => (Some("current"), "measurement"),
+        (12, 7) => (Some("voltage"), "measurement"),
+        (14, 7) => (Some("frequency"), "measurement"),
+        _ => (None, "measurement"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue};
+
+    fn readings(energy: usize, power: isize) -> SmlMessages {
+        ListResponse::new(&[0x0a, 0x01])
+            .value(&[1, 0, 1, 8, 0, 255], AnyValue::Unsigned(energy))
+            .unit(30)
+            .scaler(-1)
+            .value(&[1, 0, 16, 7, 0, 255], AnyValue::Signed(power))
+            .unit(27)
+            .scaler(-1)
+            .value(&[1, 0, 96, 50, 1, 1], AnyValue::Unsigned(1))
+            .unit(255)
+            .scaler(-1)
+            .messages()
+    }
+
+    fn topics(publications: &[Publication]) -> Vec<&str> {
+        publications.iter().map(|x| x.topic.as_str()).collect()
+    }
+
+    #[test]
+    pub fn announces_and_publishes_known_values() {
+        let mut publisher = MqttPublisher::new();
+
+        let publications = publisher.publications(&readings(12345, -3000), Instant::now());
+
+        assert_eq!(
+            topics(&publications),
+            vec![
+                "homeassistant/sensor/sml_0a01_1_0_1_8_0_255/config",
+                "sml/0a01/1-0:1.8.0*255",
+                "homeassistant/sensor/sml_0a01_1_0_16_7_0_255/config",
+                "sml/0a01/1-0:16.7.0*255",
+            ]
+        );
+        assert_eq!(publications[1].payload, "1234.5");
+        assert_eq!(publications[3].payload, "-300");
+        let config: serde_json::Value = serde_json::from_str(&publications[0].payload).unwrap();
+        assert_eq!(config["device_class"], "energy");
+        assert_eq!(config["state_class"], "total_increasing");
+        assert_eq!(config["unit_of_measurement"], "Wh");
+        assert_eq!(config["state_topic"], "sml/0a01/1-0:1.8.0*255");
+        assert_eq!(config["name"], "Positive active energy (A+) total");
+        let config: serde_json::Value = serde_json::from_str(&publications[2].payload).unwrap();
+        assert_eq!(config["device_class"], "power");
+        assert_eq!(config["state_class"], "measurement");
+    }
+
+    #[test]
+    pub fn limits_rate_and_skips_small_changes() {
+        let start = Instant::now();
+        let mut publisher = MqttPublisher::new()
+            .discovery_prefix(None)
+            .min_interval(Duration::from_secs(10))
+            .change_threshold(5.0)
+            .obis_threshold(Obis::PositiveActiveEnergyTotal, 0.0);
+        publisher.publications(&readings(12345, -3000), start);
+
+        let early = publisher.publications(&readings(12346, -2000), start + Duration::from_secs(5));
+        let small =
+            publisher.publications(&readings(12346, -3010), start + Duration::from_secs(10));
+        let large =
+            publisher.publications(&readings(12346, -2000), start + Duration::from_secs(10));
+
+        assert!(early.is_empty());
+        assert_eq!(topics(&small), vec!["sml/0a01/1-0:1.8.0*255"]);
+        assert_eq!(topics(&large), vec!["sml/0a01/1-0:16.7.0*255"]);
+    }
+
+    /// Publishes to a broker and reads the messages back, e.g. with
+    /// `mosquitto -p 1883` running: `cargo test --features mqtt -- --ignored`
+    #[test]
+    #[ignore = "needs an MQTT broker on localhost:1883"]
+    pub fn publishes_to_broker() {
+        use rumqttc::{Event, MqttOptions, Packet};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let options = MqttOptions::new("sml-test-subscriber", "localhost", 1883);
+            let (subscriber, mut events) = AsyncClient::new(options, 16);
+            subscriber
+                .subscribe("sml-test/#", QoS::AtLeastOnce)
+                .await
+                .unwrap();
+            loop {
+                if let Event::Incoming(Packet::SubAck(_)) = events.poll().await.unwrap() {
+                    break;
+                }
+            }
+            let options = MqttOptions::new("sml-test-publisher", "localhost", 1883);
+            let (client, mut publisher_events) = AsyncClient::new(options, 16);
+            tokio::spawn(async move { while publisher_events.poll().await.is_ok() {} });
+
+            let mut publisher = MqttPublisher::new()
+                .topic_prefix("sml-test")
+                .discovery_prefix(None);
+            publisher
+                .publish(&client, &readings(12345, -3000))
+                .await
+                .unwrap();
+
+            let mut received = vec![];
+            while received.len() < 2 {
+                if let Event::Incoming(Packet::Publish(publish)) = events.poll().await.unwrap() {
+                    received.push((publish.topic, publish.payload));
+                }
+            }
+            received.sort();
+            assert_eq!(received[0].0, "sml-test/0a01/1-0:1.8.0*255");
+            assert_eq!(&received[0].1[..], b"1234.5");
+            assert_eq!(&received[1].1[..], b"-300");
+        });
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::MetricsExporter;
+use crate::request::{Request, RequestHead};
+
+/// Content type of the OpenMetrics text format
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Time a client may take to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the metrics of `exporter` as `GET /metrics` to clients of `listener`
+///
+/// Answers one request at a time, which is plenty for scrapes, and returns only when
+/// accepting connections fails; run it on its own thread and keep recording into
+/// `exporter` while it runs.
+/// ```no_run
+/// use std::{net::TcpListener, sync::{Arc, Mutex}, thread};
+/// use hackdose_sml_parser::prometheus::{serve_metrics, MetricsExporter};
+///
+/// let exporter = Arc::new(Mutex::new(MetricsExporter::new()));
+/// let listener = TcpListener::bind("0.0.0.0:9464").unwrap();
+/// let serving = exporter.clone();
+/// thread::spawn(move || serve_metrics(listener, serving));
+/// ```
+pub fn serve_metrics(
+    listener: TcpListener,
+    exporter: Arc<Mutex<MetricsExporter>>,
+) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        // a client going away only concerns its own request
+        let _ = respond(stream, &exporter);
+    }
+}
+
+fn respond(mut stream: TcpStream, exporter: &Mutex<MetricsExporter>) -> io::Result<()> {
+    let (status, content_type, body) = match read_request(&mut stream, REQUEST_TIMEOUT)? {
+        Request::Get(path) if path == "/metrics" => {
+            let exporter = exporter.lock().unwrap_or_else(|e| e.into_inner());
+            ("200 OK", CONTENT_TYPE, exporter.to_string())
+        }
+        Request::Get(_) => ("404 Not Found", "text/plain", String::from("not found\n")),
+        Request::Other => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("method not allowed\n"),
+        ),
+        Request::Invalid => (
+            "400 Bad Request",
+            "text/plain",
+            String::from("bad request\n"),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Read the head of the request within `timeout`, ignoring any body
+///
+/// The timeout covers the whole head, so a client sending byte by byte cannot hold up
+/// the scrapes waiting behind it.
+fn read_request(stream: &mut TcpStream, timeout: Duration) -> io::Result<Request> {
+    let deadline = Instant::now() + timeout;
+    let mut head = RequestHead::default();
+    let mut buf = [0; 1024];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(remaining))?;
+        let n = match stream.read(&mut buf) {
+            // read timeouts are reported as WouldBlock on Unix
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                return Err(io::ErrorKind::TimedOut.into())
+            }
+            result => result?,
+        };
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(request) = head.record(&buf[..n]) {
+            return Ok(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    fn get(address: std::net::SocketAddr, path: &str) -> String {
+        send(
+            address,
+            &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path),
+        )
+    }
+
+    fn send(address: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    pub fn serves_metrics_over_http() {
+        let exporter = Arc::new(Mutex::new(MetricsExporter::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let serving = exporter.clone();
+        thread::spawn(move || serve_metrics(listener, serving));
+
+        exporter
+            .lock()
+            .unwrap()
+            .record(&super::super::test::readings());
+        let response = get(address, "/metrics");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("sml_voltage_volts{"));
+        assert!(response.ends_with("# EOF\n"));
+        assert!(get(address, "/").starts_with("HTTP/1.1 404"));
+        // ends where the server stops reading, so it has consumed all of it
+        let long_line = "a".repeat(crate::request::MAX_LINE_LENGTH + 1);
+        assert!(send(address, &long_line).starts_with("HTTP/1.1 400"));
+        assert!(send(address, "DELETE / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+    }
+
+    #[test]
+    pub fn limits_time_for_whole_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // slower than the timeout in total, but faster than it between bytes
+            for byte in b"GET /metrics HTTP/1.1\r\n\r\n" {
+                if stream.write_all(&[*byte]).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let started = Instant::now();
+        let result = read_request(&mut stream, Duration::from_millis(100));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_millis(400));
+        drop(stream);
+        client.join().unwrap();
+    }
+}
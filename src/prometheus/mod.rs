@@ -0,0 +1,386 @@
+//! Export of meter readings in the OpenMetrics text format read by Prometheus
+//!
+//! [MetricsExporter] keeps the latest value of each list entry per meter in [Meters],
+//! fed from messages or the updates of a [crate::message_stream::MeterHub]. Metric names
+//! follow the quantity of the OBIS number, e.g. `sml_active_energy_joules` or
+//! `sml_voltage_volts`; phase, tariff and direction become labels, as does the server id
+//! of the meter. Values are scaled to base units: W, V, A, Hz, and joules for energy
+//! in Wh. Energy registers are counters, everything else gauges. Entries without a
+//! known OBIS number are exported as `sml_value` with the number as label.
+//! ```
+//! use hackdose_sml_parser::{
+//!     application::domain::{
+//!         AnyValue, GetListResponseBody, SmlListEntry, SmlMessageEnvelope, SmlMessages,
+//!     },
+//!     prometheus::MetricsExporter,
+//! };
+//!
+//! let messages = SmlMessages {
+//!     messages: vec![SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+//!         server_id: vec![0x0a, 0x01],
+//!         list_name: vec![],
+//!         value_list: vec![SmlListEntry {
+//!             object_name: vec![1, 0, 1, 8, 0, 255],
+//!             status: None,
+//!             value_time: None,
+//!             unit: Some(30),
+//!             scaler: Some(-1),
+//!             value: AnyValue::Unsigned(25),
+//!         }],
+//!     })],
+//! };
+//! let mut exporter = MetricsExporter::new();
+//! exporter.record(&messages);
+//!
+//! assert!(exporter.to_string().contains(
+//!     r#"sml_active_energy_joules_total{server_id="0a01",obis="1-0:1.8.0*255",direction="import"} 9000"#
+//! ));
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Write},
+};
+
+use crate::{
+    application::{
+        domain::SmlMessages,
+        obis::{quantity, Direction, Obis, ObisNotation},
+    },
+    hex::Hex,
+    meters::{MeterUpdate, Meters},
+    transport::TransportStats,
+};
+
+mod http;
+
+pub use http::{serve_metrics, CONTENT_TYPE};
+
+/// Latest readings of all meters, displayed in OpenMetrics text format
+#[derive(Debug, Default)]
+pub struct MetricsExporter {
+    meters: Meters,
+    transport: TransportStats,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+/// Samples of one metric family
+struct Family {
+    kind: Kind,
+    unit: Option<&'static str>,
+    help: &'static str,
+    samples: Vec<String>,
+}
+
+impl MetricsExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the values of all list responses in `messages`
+    pub fn record(&mut self, messages: &SmlMessages) {
+        self.meters.record(0, messages);
+    }
+
+    /// Take the values of an update of a [crate::message_stream::MeterHub]
+    pub fn update(&mut self, update: &MeterUpdate) {
+        self.meters.update(update);
+    }
+
+    /// Export `stats` as frame and error counters, e.g. from [crate::reader::SmlReader::stats]
+    pub fn set_transport_stats(&mut self, stats: TransportStats) {
+        self.transport = stats;
+    }
+
+    fn families(&self) -> BTreeMap<String, Family> {
+        let mut families = BTreeMap::new();
+        let readings = self.meters.meters().flat_map(|server_id| {
+            self.meters
+                .readings(server_id)
+                .filter_map(move |reading| Some((server_id, reading, reading.value.as_number()?)))
+        });
+        for (server_id, reading, value) in readings {
+            let obis = &reading.obis;
+            let (quantity, help, kind) = describe(obis);
+            let (unit, factor) = base_unit(reading.unit);
+            let name = match unit {
+                Some(unit) => format!("sml_{}_{}", quantity, unit),
+                None => format!("sml_{}", quantity),
+            };
+            let family = families.entry(name.clone()).or_insert_with(|| Family {
+                kind,
+                unit,
+                help,
+                samples: vec![],
+            });
+            let suffix = if kind == Kind::Counter { "_total" } else { "" };
+            family.samples.push(format!(
+                "{}{}{{{}}} {}",
+                name,
+                suffix,
+                labels(server_id, obis),
+                value * factor
+            ));
+        }
+
+        let transport = [
+            (
+                "sml_frames",
+                "Complete SML frames read",
+                self.transport.frames,
+            ),
+            (
+                "sml_crc_errors",
+                "Frames with invalid crc",
+                self.transport.crc_errors,
+            ),
+            (
+                "sml_oversized_frames",
+                "Frames dropped for exceeding the maximum frame size",
+                self.transport.oversized,
+            ),
+            (
+                "sml_parse_errors",
+                "Frames whose body could not be parsed",
+                self.transport.parse_errors,
+            ),
+        ];
+        for (name, help, count) in transport {
+            let family = Family {
+                kind: Kind::Counter,
+                unit: None,
+                help,
+                samples: vec![format!("{}_total {}", name, count)],
+            };
+            families.insert(name.to_string(), family);
+        }
+        families
+    }
+}
+
+impl Display for MetricsExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, family) in self.families() {
+            let kind = match family.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+            };
+            writeln!(f, "# TYPE {} {}", name, kind)?;
+            if let Some(unit) = family.unit {
+                writeln!(f, "# UNIT {} {}", name, unit)?;
+            }
+            writeln!(f, "# HELP {} {}", name, family.help)?;
+            for sample in family.samples {
+                writeln!(f, "{}", sample)?;
+            }
+        }
+        writeln!(f, "# EOF")
+    }
+}
+
+/// Name of the measured quantity, help text and metric type of an OBIS number
+fn describe(obis: &[u8]) -> (String, &'static str, Kind) {
+    let fallback = (
+        String::from("value"),
+        "Values of list entries with unknown OBIS number",
+        Kind::Gauge,
+    );
+    if Obis::from_number(obis).is_none() {
+        return fallback;
+    }
+    let quantity = quantity(obis);
+    let energy = match quantity {
+        1 | 2 | 15 | 16 => "active",
+        3..=8 => "reactive",
+        9 => "apparent",
+        _ => "",
+    };
+    let (name, help, kind) = match (quantity, obis[3]) {
+        (16, 8) => (
+            "net_active_energy",
+            "Imported minus exported active energy",
+            Kind::Gauge,
+        ),
+        (_, 8) if !energy.is_empty() => ("energy", "Energy registers", Kind::Counter),
+        (_, 7) if !energy.is_empty() => ("power", "Instantaneous power", Kind::Gauge),
+        (_, 6) if !energy.is_empty() => ("maximum_demand", "Maximum demand", Kind::Gauge),
+        (_, 2) if !energy.is_empty() => (
+            "cumulative_maximum_demand",
+            "Cumulative maximum demand",
+            Kind::Gauge,
+        ),
+        (_, 4) if !energy.is_empty() => (
+            "demand_current_period",
+            "Demand in the current demand period",
+            Kind::Gauge,
+        ),
+        (_, 5) if !energy.is_empty() => (
+            "demand_last_period",
+            "Demand in the last completed demand period",
+            Kind::Gauge,
+        ),
+        (11, 7) => ("current", "Instantaneous current", Kind::Gauge),
+        (11, 6) => ("maximum_current", "Maximum current", Kind::Gauge),
+        (12, 7) => ("voltage", "Instantaneous voltage", Kind::Gauge),
+        (13, 7) => ("power_factor", "Instantaneous power factor", Kind::Gauge),
+        (14, 7) => ("frequency", "Frequency", Kind::Gauge),
+        _ => return fallback,
+    };
+    match (quantity, obis[3]) {
+        (16, 8) | (11..=14, _) => (String::from(name), help, kind),
+        _ => (format!("{}_{}", energy, name), help, kind),
+    }
+}
+
+/// Labels of a reading, `server_id` and `obis` followed by the OBIS metadata
+fn labels(server_id: &[u8], obis: &[u8]) -> String {
+    let mut labels = format!(
+        "server_id=\"{}\",obis=\"{}\"",
+        Hex(server_id),
+        ObisNotation(obis)
+    );
+    let Some(obis) = Obis::from_number(obis) else {
+        return labels;
+    };
+    if let Some(phase) = obis.phase() {
+        write!(labels, ",phase=\"{}\"", phase).expect("writing to string");
+    }
+    if let Some(tariff) = obis.tariff() {
+        write!(labels, ",tariff=\"{}\"", tariff).expect("writing to string");
+    }
+    match obis.direction() {
+        Some(quadrant @ Direction::Quadrant(_)) => {
+            write!(labels, ",quadrant=\"{}\"", quadrant).expect("writing to string")
+        }
+        Some(direction) => {
+            write!(labels, ",direction=\"{}\"", direction).expect("writing to string")
+        }
+        None => (),
+    }
+    labels
+}
+
+/// Metric unit of a DLMS unit code and the factor converting values to it
+///
+/// Only Wh is converted, to joules; there are no SI units for reactive and apparent energy.
+fn base_unit(unit: Option<u8>) -> (Option<&'static str>, f64) {
+    match unit {
+        Some(7) => (Some("seconds"), 1.0),
+        Some(9) => (Some("celsius"), 1.0),
+        Some(27) => (Some("watts"), 1.0),
+        Some(28) => (Some("voltamperes"), 1.0),
+        Some(29) => (Some("vars"), 1.0),
+        Some(30) => (Some("joules"), 3600.0),
+        Some(31) => (Some("voltampere_hours"), 1.0),
+        Some(32) => (Some("var_hours"), 1.0),
+        Some(33) => (Some("amperes"), 1.0),
+        Some(35) => (Some("volts"), 1.0),
+        Some(44) => (Some("hertz"), 1.0),
+        Some(52) => (Some("kelvin"), 1.0),
+        Some(56) => (Some("ratio"), 0.01),
+        _ => (None, 1.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::domain::{test::ListResponse, AnyValue, SmlMessageEnvelope};
+
+    pub(super) fn readings() -> SmlMessages {
+        SmlMessages {
+            messages: vec![
+                SmlMessageEnvelope::GetCloseResponse,
+                ListResponse::new(&[0x0a, 0x01])
+                    .value(&[1, 0, 1, 8, 1, 255], AnyValue::Unsigned(12345))
+                    .unit(30)
+                    .scaler(-1)
+                    .value(&[1, 0, 16, 7, 0, 255], AnyValue::Signed(-300))
+                    .unit(27)
+                    .scaler(0)
+                    .value(&[1, 0, 52, 7, 0, 255], AnyValue::Unsigned(2301))
+                    .unit(35)
+                    .scaler(-1)
+                    .value(&[1, 0, 96, 50, 1, 1], AnyValue::Unsigned(7))
+                    .unit(255)
+                    .scaler(0)
+                    .value(&[1, 0, 0, 0, 9, 255], AnyValue::String(vec![1]))
+                    .unit(255)
+                    .scaler(0)
+                    .envelope(),
+            ],
+        }
+    }
+
+    #[test]
+    pub fn exports_readings_with_obis_labels_in_base_units() {
+        let mut exporter = MetricsExporter::new();
+        exporter.record(&readings());
+
+        let text = exporter.to_string();
+
+        for line in [
+            "# TYPE sml_active_energy_joules counter",
+            "# UNIT sml_active_energy_joules joules",
+            r#"sml_active_energy_joules_total{server_id="0a01",obis="1-0:1.8.1*255",tariff="1",direction="import"} 4444200"#,
+            "# TYPE sml_active_power_watts gauge",
+            r#"sml_active_power_watts{server_id="0a01",obis="1-0:16.7.0*255",direction="net"} -300"#,
+            r#"sml_voltage_volts{server_id="0a01",obis="1-0:52.7.0*255",phase="L2"} 230.1"#,
+            r#"sml_value{server_id="0a01",obis="1-0:96.50.1*1"} 7"#,
+            "sml_frames_total 0",
+        ] {
+            assert!(
+                text.lines().any(|x| x == line),
+                "{} missing in\n{}",
+                line,
+                text
+            );
+        }
+        assert!(!text.contains("0:0.0.9"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    pub fn keeps_latest_reading_and_transport_counters() {
+        let mut exporter = MetricsExporter::new();
+        exporter.record(&readings());
+        let update = ListResponse::new(&[0x0a, 0x01])
+            .value(&[1, 0, 52, 7, 0, 255], AnyValue::Unsigned(229))
+            .unit(35)
+            .scaler(0)
+            .messages();
+        exporter.record(&update);
+        exporter.set_transport_stats(TransportStats {
+            frames: 12,
+            crc_errors: 1,
+            oversized: 0,
+            parse_errors: 2,
+        });
+
+        let text = exporter.to_string();
+
+        assert!(text.contains(
+            r#"sml_voltage_volts{server_id="0a01",obis="1-0:52.7.0*255",phase="L2"} 229"#
+        ));
+        assert!(!text.contains("230.1"));
+        assert!(text.contains("sml_frames_total 12\n"));
+        assert!(text.contains("sml_crc_errors_total 1\n"));
+        assert!(text.contains("sml_parse_errors_total 2\n"));
+    }
+
+    #[test]
+    pub fn exports_hub_updates() {
+        let mut exporter = MetricsExporter::new();
+        for update in Meters::new().record(0, &readings()) {
+            exporter.update(&update);
+        }
+
+        assert!(exporter.to_string().contains(
+            r#"sml_active_power_watts{server_id="0a01",obis="1-0:16.7.0*255",direction="net"} -300"#
+        ));
+    }
+}
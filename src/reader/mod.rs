@@ -0,0 +1,201 @@
+use std::io::{ErrorKind, Read};
+
+use crate::{
+    application::domain::SmlMessages,
+    transport::{FrameAssembler, TransportStats},
+};
+
+/// A complete SML frame read from a byte stream
+#[derive(PartialEq, Debug)]
+pub struct SmlFrame {
+    /// the body of the frame, omitting escape sequences, padding and crc
+    pub body: Vec<u8>,
+    /// the messages contained in the body
+    pub messages: SmlMessages,
+}
+
+#[derive(Debug)]
+pub enum SmlError {
+    /// Reading from the underlying reader failed
+    Io(std::io::Error),
+    /// A frame was found but its body could not be parsed
+    Parse {
+        /// the raw body of the frame
+        body: Vec<u8>,
+    },
+}
+
+/// Blocking reader of SML frames from a [Read]
+///
+/// Iterating yields one item per frame and ends on end of file.
+/// Corrupt frames and I/O errors are yielded as errors; iteration
+/// may continue afterwards.
+/// ```
+/// use std::io::Cursor;
+/// use hackdose_sml_parser::reader::SmlReader;
+///
+/// let cursor = Cursor::new(vec![0x01, 0x02, 0x03]);
+/// for frame in SmlReader::new(cursor) {
+///     println!("{:?}", frame);
+/// }
+/// ```
+pub struct SmlReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    frames: FrameAssembler,
+    eof: bool,
+}
+
+const DEFAULT_BUFFER_SIZE: usize = 512;
+
+impl<R: Read> SmlReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_buffer_size(reader, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Create a reader which reads at most `size` bytes at once
+    pub fn with_buffer_size(reader: R, size: usize) -> Self {
+        Self {
+            reader,
+            buf: vec![0; size.max(1)],
+            frames: FrameAssembler::default(),
+            eof: false,
+        }
+    }
+
+    /// Drop frames longer than `size` bytes instead of buffering them
+    ///
+    /// Defaults to [crate::transport::DEFAULT_MAX_FRAME_SIZE].
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.frames.set_max_frame_size(size);
+        self
+    }
+
+    /// Counters of the frames read so far
+    pub fn stats(&self) -> TransportStats {
+        self.frames.stats()
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for SmlReader<R> {
+    type Item = Result<SmlFrame, SmlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.frames.next_frame() {
+                return Some(match frame {
+                    Ok((body, messages)) => Ok(SmlFrame { body, messages }),
+                    Err(body) => Err(SmlError::Parse { body }),
+                });
+            }
+
+            if self.eof {
+                return None;
+            }
+
+            match self.reader.read(&mut self.buf) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.frames.record(&self.buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Some(Err(SmlError::Io(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        application::domain::SmlMessageEnvelope,
+        transport::test::{CLOSE_FRAME, CORRUPT_FRAME},
+    };
+
+    /// Reader returning at most one byte per read
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((first, rest)) if !buf.is_empty() => {
+                    buf[0] = *first;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn close_frame() -> SmlFrame {
+        SmlFrame {
+            body: CLOSE_FRAME[8..28].to_vec(),
+            messages: SmlMessages {
+                messages: vec![SmlMessageEnvelope::GetCloseResponse],
+            },
+        }
+    }
+
+    #[test]
+    pub fn reads_consecutive_frames() {
+        let mut input = vec![0x42];
+        input.extend_from_slice(CLOSE_FRAME);
+        input.extend_from_slice(&[0x00, 0x01]);
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let frames: Vec<_> = SmlReader::new(Cursor::new(input))
+            .map(|frame| frame.unwrap())
+            .collect();
+
+        assert_eq!(frames, vec![close_frame(), close_frame()]);
+    }
+
+    #[test]
+    pub fn reads_frames_byte_by_byte() {
+        let mut input = CLOSE_FRAME.to_vec();
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let frames: Vec<_> = SmlReader::new(Trickle(&input))
+            .map(|frame| frame.unwrap())
+            .collect();
+
+        assert_eq!(frames, vec![close_frame(), close_frame()]);
+    }
+
+    #[test]
+    pub fn yields_corrupt_frames_and_continues() {
+        let mut input = CORRUPT_FRAME.to_vec();
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let mut reader = SmlReader::new(Cursor::new(input));
+
+        assert!(matches!(
+            reader.next(),
+            Some(Err(SmlError::Parse { body })) if body == vec![0x42, 0x43, 0x00, 0x00]
+        ));
+        assert_eq!(reader.next().unwrap().unwrap(), close_frame());
+        assert!(reader.next().is_none());
+        assert_eq!(reader.stats().frames, 2);
+        assert_eq!(reader.stats().parse_errors, 1);
+    }
+
+    #[test]
+    pub fn drops_frames_exceeding_max_frame_size() {
+        let mut input = crate::encode::encode_frame(&[0x42; 100]);
+        input.extend_from_slice(CLOSE_FRAME);
+
+        let frames: Vec<_> = SmlReader::new(Trickle(&input))
+            .max_frame_size(64)
+            .map(|frame| frame.unwrap())
+            .collect();
+
+        assert_eq!(frames, vec![close_frame()]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Bounded parsing of the HTTP request heads served by [crate::prometheus] and [crate::http]
+
+/// Longest request line or header accepted
+pub(crate) const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Most headers accepted after the request line
+pub(crate) const MAX_HEADERS: usize = 100;
+
+/// Request as far as the servers care about it
+#[derive(Debug, PartialEq)]
+pub(crate) enum Request {
+    /// `GET` of the path without query
+    Get(String),
+    /// Any other method
+    Other,
+    /// Head which is not HTTP or exceeds the limits
+    Invalid,
+}
+
+/// Head of a request assembled from the bytes received from a client
+///
+/// Headers are skipped but counted, lines are limited to [MAX_LINE_LENGTH] so that a
+/// client cannot make the server buffer without bound.
+#[derive(Debug, Default)]
+pub(crate) struct RequestHead {
+    line: Vec<u8>,
+    request_line: Option<String>,
+    headers: usize,
+}
+
+impl RequestHead {
+    /// Feed bytes received from the client, the request once its head is complete
+    pub(crate) fn record(&mut self, buf: &[u8]) -> Option<Request> {
+        for byte in buf {
+            if *byte != b'\n' {
+                if self.line.len() == MAX_LINE_LENGTH {
+                    return Some(Request::Invalid);
+                }
+                self.line.push(*byte);
+                continue;
+            }
+            let mut line = core::mem::take(&mut self.line);
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            match self.request_line {
+                None => match String::from_utf8(line) {
+                    Ok(line) => self.request_line = Some(line),
+                    Err(_) => return Some(Request::Invalid),
+                },
+                Some(_) if line.is_empty() => return self.request_line.as_deref().map(parse),
+                Some(_) => {
+                    self.headers += 1;
+                    if self.headers > MAX_HEADERS {
+                        return Some(Request::Invalid);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+fn parse(request_line: &str) -> Request {
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("GET"), Some(path), Some(version)) if version.starts_with("HTTP/") => {
+            Request::Get(String::from(path.split('?').next().unwrap_or(path)))
+        }
+        (Some(_), Some(_), Some(version)) if version.starts_with("HTTP/") => Request::Other,
+        _ => Request::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn head(request: &str) -> Option<Request> {
+        RequestHead::default().record(request.as_bytes())
+    }
+
+    #[test]
+    pub fn parses_request_line_and_skips_headers() {
+        assert_eq!(
+            head("GET /metrics?x=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some(Request::Get(String::from("/metrics")))
+        );
+        assert_eq!(head("POST / HTTP/1.1\n\n"), Some(Request::Other));
+        assert_eq!(head("hello\r\n\r\n"), Some(Request::Invalid));
+        assert_eq!(head("GET / HTTP/1.1\r\nHost: localhost\r\n"), None);
+    }
+
+    #[test]
+    pub fn assembles_head_from_single_bytes() {
+        let mut head = RequestHead::default();
+        let request = b"GET /latest HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let (last, bytes) = request.split_last().unwrap();
+        for byte in bytes {
+            assert_eq!(head.record(&[*byte]), None);
+        }
+        assert_eq!(
+            head.record(&[*last]),
+            Some(Request::Get(String::from("/latest")))
+        );
+    }
+
+    #[test]
+    pub fn rejects_long_lines_and_many_headers() {
+        let long_line = "a".repeat(MAX_LINE_LENGTH + 1);
+        assert_eq!(head(&long_line), Some(Request::Invalid));
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", long_line);
+        assert_eq!(head(&long_header), Some(Request::Invalid));
+
+        let headers = "X: 1\r\n".repeat(MAX_HEADERS);
+        let request = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+        assert_eq!(head(&request), Some(Request::Get(String::from("/"))));
+        let request = format!("GET / HTTP/1.1\r\n{}X: 1\r\n\r\n", headers);
+        assert_eq!(head(&request), Some(Request::Invalid));
+    }
+}
@@ -0,0 +1,410 @@
+//! Reading SML from serial ports, e.g. optical read heads
+//!
+//! [SerialSmlSource] opens a port with the line settings of common read heads,
+//! locks it against other processes and reopens it when it goes away, e.g. when
+//! a USB read head is unplugged.
+//! ```no_run
+//! use hackdose_sml_parser::{reader::SmlReader, serial::SerialSmlSource};
+//!
+//! let port = SerialSmlSource::new("/dev/ttyUSB0").open().unwrap();
+//! for frame in SmlReader::new(port) {
+//!     println!("{:?}", frame);
+//! }
+//! ```
+use std::{
+    io::{self, ErrorKind, Read},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use serialport::SerialPort;
+pub use serialport::{DataBits, Parity, StopBits};
+
+/// Time a read waits for data before checking whether reading should go on
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Line settings of common read heads
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ReadHead {
+    /// 9600 baud 8N1, as sent by most SML meters
+    Sml,
+    /// 300 baud 7E1, the initial speed of IEC 62056-21 mode C
+    Iec62056Initial,
+    /// 9600 baud 7E1, IEC 62056-21 after switching speed
+    Iec62056,
+}
+
+/// Builder for reading SML from a serial port
+///
+/// Defaults to [ReadHead::Sml] settings, exclusive access and reconnecting every second.
+#[derive(Debug, Clone)]
+pub struct SerialSmlSource {
+    path: String,
+    baud_rate: u32,
+    data_bits: DataBits,
+    parity: Parity,
+    stop_bits: StopBits,
+    exclusive: bool,
+    reconnect_delay: Option<Duration>,
+}
+
+impl SerialSmlSource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            baud_rate: 9600,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            exclusive: true,
+            reconnect_delay: Some(DEFAULT_RECONNECT_DELAY),
+        }
+    }
+
+    /// Use the line settings of `read_head`
+    pub fn read_head(self, read_head: ReadHead) -> Self {
+        let (baud_rate, data_bits, parity) = match read_head {
+            ReadHead::Sml => (9600, DataBits::Eight, Parity::None),
+            ReadHead::Iec62056Initial => (300, DataBits::Seven, Parity::Even),
+            ReadHead::Iec62056 => (9600, DataBits::Seven, Parity::Even),
+        };
+        Self {
+            baud_rate,
+            data_bits,
+            parity,
+            stop_bits: StopBits::One,
+            ..self
+        }
+    }
+
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Lock the port against other processes, enabled by default
+    ///
+    /// Opening a port locked by another process fails with [ErrorKind::ResourceBusy].
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Wait `delay` between attempts to reopen the port after reading failed
+    ///
+    /// With `None` reading ends on the first error instead.
+    pub fn reconnect(mut self, delay: Option<Duration>) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Open the port for blocking reads
+    ///
+    /// Fails if the port cannot be opened now, later failures are handled by
+    /// reconnecting.
+    pub fn open(self) -> io::Result<SerialReader> {
+        let port = self.open_port()?;
+        Ok(SerialReader {
+            source: self,
+            port: Some(port),
+            closed: false,
+        })
+    }
+
+    /// Open the port for use with [crate::message_stream::SmlFrameStream]
+    ///
+    /// Reads on a separate thread, which ends when the returned reader is dropped.
+    #[cfg(feature = "async-tokio")]
+    pub fn open_async(self) -> io::Result<AsyncSerialReader> {
+        let mut reader = self.open()?;
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while !tx.is_closed() {
+                let chunk = match reader.read_chunk(&mut buf) {
+                    Ok(Some(n)) => Ok(buf[..n].to_vec()),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = chunk.is_err();
+                if tx.blocking_send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(AsyncSerialReader {
+            rx,
+            current: vec![],
+            position: 0,
+        })
+    }
+
+    /// Stream of messages read from the port, see [crate::message_stream::sml_message_stream]
+    #[cfg(feature = "async-tokio")]
+    pub fn stream(
+        self,
+    ) -> io::Result<
+        impl tokio_stream::Stream<
+            Item = Result<
+                crate::application::domain::SmlMessages,
+                crate::message_stream::SmlStreamError,
+            >,
+        >,
+    > {
+        Ok(crate::message_stream::sml_message_stream(
+            self.open_async()?,
+        ))
+    }
+
+    fn open_port(&self) -> io::Result<Box<dyn SerialPort>> {
+        let port = serialport::new(self.path.as_str(), self.baud_rate)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .timeout(READ_TIMEOUT)
+            .exclusive(self.exclusive)
+            .open();
+        match port {
+            Ok(port) => Ok(port),
+            // serialport reports ports in use as missing devices
+            Err(e)
+                if e.kind() == serialport::ErrorKind::NoDevice
+                    && Path::new(&self.path).exists() =>
+            {
+                Err(io::Error::new(
+                    ErrorKind::ResourceBusy,
+                    format!("{} is in use by another process", self.path),
+                ))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Blocking reader of a serial port, reopening it after failures
+///
+/// Returns end of file once reading failed and reconnecting is disabled.
+pub struct SerialReader {
+    source: SerialSmlSource,
+    port: Option<Box<dyn SerialPort>>,
+    closed: bool,
+}
+
+impl SerialReader {
+    /// Read once, `None` if no data arrived within [READ_TIMEOUT] or the port was reopened
+    fn read_chunk(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        if self.closed {
+            return Ok(Some(0));
+        }
+        let Some(port) = &mut self.port else {
+            let delay = self.source.reconnect_delay.unwrap_or_default();
+            thread::sleep(delay);
+            self.port = self.source.open_port().ok();
+            return Ok(None);
+        };
+        let error = match port.read(buf) {
+            Ok(n) if n > 0 => return Ok(Some(n)),
+            Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::Interrupted => {
+                return Ok(None)
+            }
+            // a tty only reads nothing once it is gone
+            Ok(_) => io::Error::new(ErrorKind::UnexpectedEof, "serial port disconnected"),
+            Err(e) => e,
+        };
+        self.port = None;
+        match self.source.reconnect_delay {
+            Some(_) => Ok(None),
+            None => {
+                self.closed = true;
+                Err(error)
+            }
+        }
+    }
+}
+
+impl Read for SerialReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(n) = self.read_chunk(buf)? {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+/// Tokio reader of a serial port, see [SerialSmlSource::open_async]
+#[cfg(feature = "async-tokio")]
+pub struct AsyncSerialReader {
+    rx: tokio::sync::mpsc::Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+#[cfg(feature = "async-tokio")]
+impl tokio::io::AsyncRead for AsyncSerialReader {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        use std::task::Poll;
+
+        let this = self.get_mut();
+        while this.position == this.current.len() {
+            match this.rx.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    this.current = chunk;
+                    this.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let remaining = &this.current[this.position..];
+        let n = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..n]);
+        this.position += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{
+        fs,
+        io::Write,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use serialport::TTYPort;
+
+    use super::*;
+    use crate::{reader::SmlReader, transport::test::CLOSE_FRAME};
+
+    /// Pseudo-terminal standing in for a read head, and the path of its device
+    fn read_head() -> (TTYPort, String) {
+        let (master, slave) = TTYPort::pair().unwrap();
+        let path = slave.name().unwrap();
+        (master, path)
+    }
+
+    #[test]
+    pub fn reads_frames_from_pseudo_terminal() {
+        let (mut master, path) = read_head();
+        let port = SerialSmlSource::new(path)
+            .read_head(ReadHead::Iec62056)
+            .read_head(ReadHead::Sml)
+            .open()
+            .unwrap();
+
+        master.write_all(CLOSE_FRAME).unwrap();
+
+        let frame = SmlReader::new(port).next().unwrap();
+        assert!(frame.is_ok());
+    }
+
+    #[test]
+    pub fn reports_port_in_use() {
+        let (_master, path) = read_head();
+        let _port = SerialSmlSource::new(path.as_str()).open().unwrap();
+
+        let error = SerialSmlSource::new(path.as_str()).open().err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::ResourceBusy);
+    }
+
+    #[test]
+    pub fn reconnects_after_unplug() {
+        let link = std::env::temp_dir().join(format!("sml-serial-test-{}", std::process::id()));
+        let plug = |path: &str| {
+            let tmp = link.with_extension("tmp");
+            let _ = fs::remove_file(&tmp);
+            std::os::unix::fs::symlink(path, &tmp).unwrap();
+            fs::rename(&tmp, &link).unwrap();
+        };
+        let (mut master, path) = read_head();
+        plug(&path);
+        let port = SerialSmlSource::new(link.to_str().unwrap())
+            .reconnect(Some(Duration::from_millis(10)))
+            .open()
+            .unwrap();
+        let mut reader = SmlReader::new(port);
+        master.write_all(CLOSE_FRAME).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+
+        // unplug and plug in again as a different device
+        drop(master);
+        let (mut master, path) = read_head();
+        plug(&path);
+        let done = Arc::new(AtomicBool::new(false));
+        let sender = {
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    // fails until the reader opened the new device
+                    let _ = master.write_all(CLOSE_FRAME);
+                    thread::sleep(Duration::from_millis(20));
+                }
+            })
+        };
+
+        let frame = reader.next().unwrap();
+        done.store(true, Ordering::Relaxed);
+        sender.join().unwrap();
+        fs::remove_file(&link).unwrap();
+        assert!(frame.is_ok());
+    }
+
+    #[test]
+    pub fn ends_without_reconnect() {
+        let (master, path) = read_head();
+        let mut port = SerialSmlSource::new(path).reconnect(None).open().unwrap();
+
+        drop(master);
+
+        let mut buf = [0; 16];
+        assert!(port.read(&mut buf).is_err());
+        assert_eq!(port.read(&mut buf).unwrap(), 0);
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    pub fn feeds_message_stream() {
+        use tokio_stream::StreamExt;
+
+        let (mut master, path) = read_head();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let message = runtime.block_on(async {
+            let mut stream = Box::pin(SerialSmlSource::new(path).stream().unwrap());
+            master.write_all(CLOSE_FRAME).unwrap();
+            stream.next().await
+        });
+
+        assert!(matches!(message, Some(Ok(_))));
+    }
+}
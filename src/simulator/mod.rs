@@ -0,0 +1,365 @@
+//! Virtual smart meter emitting SML telegrams
+//!
+//! A [VirtualMeter] follows a [PowerProfile] and integrates the power into
+//! import and export counters. Each telegram consists of an open response,
+//! a list response with the configured OBIS values and a close response.
+//! ```
+//! use std::time::Duration;
+//! use hackdose_sml_parser::simulator::{PowerProfile, VirtualMeter};
+//!
+//! let mut meter = VirtualMeter::new(&[0x0a, 0x01, 0x48, 0x4c, 0x59, 0x00, 0x00, 0x00, 0x00, 0x01])
+//!     .profile(PowerProfile::Constant(500.0))
+//!     .interval(Duration::from_secs(2));
+//! let frame: Vec<u8> = meter.next_frame();
+//! ```
+use std::{
+    f64::consts::PI,
+    io::{self, BufRead, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    application::{
+        domain::{
+            AnyValue, GetListResponseBody, GetOpenResponseBody, SmlListEntry, SmlMessageEnvelope,
+            SmlMessages,
+        },
+        obis::Obis,
+    },
+    encode::encode,
+};
+
+/// Wh
+const UNIT_WATT_HOUR: u8 = 30;
+/// W
+const UNIT_WATT: u8 = 27;
+/// V
+const UNIT_VOLT: u8 = 35;
+
+const NOMINAL_VOLTAGE: f64 = 230.0;
+
+static LIST_NAME: &[u8] = &[0x01, 0x00, 0x62, 0x0a, 0xff, 0xff];
+
+/// Power drawn by the virtual meter in W; negative values are fed in
+#[derive(PartialEq, Debug, Clone)]
+pub enum PowerProfile {
+    Constant(f64),
+    Sine {
+        mean: f64,
+        amplitude: f64,
+        period: Duration,
+    },
+    /// One sample per interval, repeated when exhausted; no power without samples
+    Recorded(Vec<f64>),
+}
+
+impl PowerProfile {
+    /// Read a recorded profile from CSV
+    ///
+    /// The last column of each line is taken as power in W, lines which
+    /// do not end in a number (like headers) are skipped.
+    pub fn from_csv(reader: impl BufRead) -> io::Result<Self> {
+        let mut samples = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if let Some(Ok(sample)) = line.rsplit(',').next().map(|x| x.trim().parse::<f64>()) {
+                samples.push(sample);
+            }
+        }
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no power samples found",
+            ));
+        }
+        Ok(PowerProfile::Recorded(samples))
+    }
+
+    fn power(&self, elapsed: Duration, step: usize) -> f64 {
+        match self {
+            PowerProfile::Constant(power) => *power,
+            PowerProfile::Sine {
+                mean,
+                amplitude,
+                period,
+            } => {
+                let phase = elapsed.as_secs_f64() / period.as_secs_f64().max(f64::EPSILON);
+                mean + amplitude * (2.0 * PI * phase).sin()
+            }
+            PowerProfile::Recorded(samples) if samples.is_empty() => 0.0,
+            PowerProfile::Recorded(samples) => samples[step % samples.len()],
+        }
+    }
+}
+
+/// A simulated meter producing one telegram per interval
+pub struct VirtualMeter {
+    server_id: Vec<u8>,
+    obis: Vec<Obis>,
+    profile: PowerProfile,
+    interval: Duration,
+    elapsed: Duration,
+    step: usize,
+    import: f64,
+    export: f64,
+}
+
+impl VirtualMeter {
+    /// Create a meter reporting energy import, export and power, drawing no power
+    pub fn new(server_id: &[u8]) -> Self {
+        Self {
+            server_id: server_id.to_vec(),
+            obis: vec![
+                Obis::PositiveActiveEnergyTotal,
+                Obis::NegativeActiveEnergyTotal,
+                Obis::SumActiveInstantaneousPower,
+            ],
+            profile: PowerProfile::Constant(0.0),
+            interval: Duration::from_secs(1),
+            elapsed: Duration::ZERO,
+            step: 0,
+            import: 0.0,
+            export: 0.0,
+        }
+    }
+
+    /// Values to report
+    ///
+    /// Supported are energy import and export totals, power (total and per phase)
+    /// and phase voltages; other OBIS numbers are left out of the telegrams.
+    pub fn obis(mut self, obis: &[Obis]) -> Self {
+        self.obis = obis.to_vec();
+        self
+    }
+
+    pub fn profile(mut self, profile: PowerProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Time between two telegrams, also used to integrate the counters
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Initial counter readings in Wh
+    pub fn counters(mut self, import: f64, export: f64) -> Self {
+        self.import = import;
+        self.export = export;
+        self
+    }
+
+    /// Advance by one interval and return the telegram for it
+    pub fn next_messages(&mut self) -> SmlMessages {
+        let power = self.profile.power(self.elapsed, self.step);
+        let hours = self.interval.as_secs_f64() / 3600.0;
+        if power >= 0.0 {
+            self.import += power * hours;
+        } else {
+            self.export -= power * hours;
+        }
+        self.elapsed += self.interval;
+        self.step += 1;
+
+        let value_list = self
+            .obis
+            .iter()
+            .filter_map(|obis| self.entry(obis, power))
+            .collect();
+
+        SmlMessages {
+            messages: vec![
+                SmlMessageEnvelope::GetOpenResponse(GetOpenResponseBody {
+                    server_id: self.server_id.clone(),
+                    req_file_id: (self.step as u32).to_be_bytes().to_vec(),
+                }),
+                SmlMessageEnvelope::GetListResponse(GetListResponseBody {
+                    server_id: self.server_id.clone(),
+                    list_name: LIST_NAME.to_vec(),
+                    value_list,
+                }),
+                SmlMessageEnvelope::GetCloseResponse,
+            ],
+        }
+    }
+
+    /// Advance by one interval and return the transport frame for it
+    pub fn next_frame(&mut self) -> Vec<u8> {
+        encode(&self.next_messages())
+    }
+
+    /// Write frames to `out`
+    ///
+    /// Writes `count` frames or runs forever. With `realtime` set, frames are paced
+    /// by the interval, otherwise they are written as fast as possible.
+    pub fn run(
+        &mut self,
+        out: &mut impl Write,
+        count: Option<usize>,
+        realtime: bool,
+    ) -> io::Result<()> {
+        let start = Instant::now();
+        let mut written = 0;
+        while count.is_none_or(|count| written < count) {
+            out.write_all(&self.next_frame())?;
+            out.flush()?;
+            written += 1;
+            if realtime {
+                let due = self.interval * written as u32;
+                thread::sleep(due.saturating_sub(start.elapsed()));
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, obis: &Obis, power: f64) -> Option<SmlListEntry> {
+        let energy = |wh: f64| (UNIT_WATT_HOUR, -1, AnyValue::Unsigned((wh * 10.0) as usize));
+        let watts = |w: f64| (UNIT_WATT, 0, AnyValue::Signed(w.round() as isize));
+        let voltage = (
+            UNIT_VOLT,
+            -1,
+            AnyValue::Unsigned((NOMINAL_VOLTAGE * 10.0) as usize),
+        );
+        let (unit, scaler, value) = match obis {
+            Obis::PositiveActiveEnergyTotal => energy(self.import),
+            Obis::NegativeActiveEnergyTotal => energy(self.export),
+            Obis::SumActiveInstantaneousPower => watts(power),
+            Obis::SumActiveInstantaneousPowerPhaseL1
+            | Obis::SumActiveInstantaneousPowerPhaseL2
+            | Obis::SumActiveInstantaneousPowerPhaseL3 => watts(power / 3.0),
+            Obis::InstantaneousVoltagePhaseL1
+            | Obis::InstantaneousVoltagePhaseL2
+            | Obis::InstantaneousVoltagePhaseL3 => voltage,
+            _ => return None,
+        };
+        Some(SmlListEntry {
+            object_name: obis.obis_number().to_vec(),
+            status: None,
+            value_time: None,
+            unit: Some(unit),
+            scaler: Some(scaler),
+            value,
+        })
+    }
+}
+
+impl Iterator for VirtualMeter {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_frame())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::reader::SmlReader;
+
+    fn value_of(messages: &SmlMessages, obis: Obis) -> AnyValue {
+        messages
+            .messages
+            .iter()
+            .find_map(|message| match message {
+                SmlMessageEnvelope::GetListResponse(body) => body
+                    .value_list
+                    .iter()
+                    .find(|entry| entry.object_name == obis.obis_number())
+                    .map(|entry| entry.value.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    pub fn integrates_power_into_counters() {
+        let meter = VirtualMeter::new(&[0x01, 0x02])
+            .profile(PowerProfile::Recorded(vec![3600.0, -1800.0]))
+            .interval(Duration::from_secs(10))
+            .counters(1000.0, 0.0);
+        let mut out = vec![];
+
+        let frames: Vec<_> = meter.take(4).collect();
+        for frame in frames.iter() {
+            out.extend_from_slice(frame);
+        }
+        let messages: Vec<_> = SmlReader::new(Cursor::new(out))
+            .map(|frame| frame.unwrap().messages)
+            .collect();
+
+        assert_eq!(messages.len(), 4);
+        let last = &messages[3];
+        assert_eq!(
+            value_of(last, Obis::PositiveActiveEnergyTotal),
+            AnyValue::Unsigned(10200)
+        );
+        assert_eq!(
+            value_of(last, Obis::NegativeActiveEnergyTotal),
+            AnyValue::Unsigned(100)
+        );
+        assert_eq!(
+            value_of(last, Obis::SumActiveInstantaneousPower),
+            AnyValue::Signed(-1800)
+        );
+    }
+
+    #[test]
+    pub fn follows_sine_profile() {
+        let mut meter = VirtualMeter::new(&[0x01])
+            .profile(PowerProfile::Sine {
+                mean: 100.0,
+                amplitude: 50.0,
+                period: Duration::from_secs(4),
+            })
+            .obis(&[Obis::SumActiveInstantaneousPower]);
+
+        let powers: Vec<_> = (0..4)
+            .map(|_| value_of(&meter.next_messages(), Obis::SumActiveInstantaneousPower))
+            .collect();
+
+        assert_eq!(
+            powers,
+            vec![
+                AnyValue::Signed(100),
+                AnyValue::Signed(150),
+                AnyValue::Signed(100),
+                AnyValue::Signed(50)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn draws_no_power_without_recorded_samples() {
+        let mut meter = VirtualMeter::new(&[0x01])
+            .profile(PowerProfile::Recorded(vec![]))
+            .obis(&[Obis::SumActiveInstantaneousPower]);
+
+        assert_eq!(
+            value_of(&meter.next_messages(), Obis::SumActiveInstantaneousPower),
+            AnyValue::Signed(0)
+        );
+    }
+
+    #[test]
+    pub fn reads_recorded_profile_from_csv() {
+        let csv = "time,power\n0,100.5\n1,-20\n";
+
+        let profile = PowerProfile::from_csv(Cursor::new(csv)).unwrap();
+
+        assert_eq!(profile, PowerProfile::Recorded(vec![100.5, -20.0]));
+    }
+
+    #[test]
+    pub fn writes_requested_number_of_frames() {
+        let mut meter = VirtualMeter::new(&[0x01]);
+        let mut out = vec![];
+
+        meter.run(&mut out, Some(3), false).unwrap();
+
+        assert_eq!(SmlReader::new(Cursor::new(out)).count(), 3);
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+
+use super::{SMLMessageBuilder, TransportStats, DEFAULT_MAX_FRAME_SIZE};
+use crate::application::{domain::SmlMessages, parser::parse_body};
+
+/// Body and messages of a frame, or only the body if it cannot be parsed
+pub(crate) type AssembledFrame = Result<(Vec<u8>, SmlMessages), Vec<u8>>;
+
+/// Frames assembled from the chunks of a byte source, shared by the readers
+///
+/// Counts frames in [TransportStats], including bodies which cannot be parsed.
+#[derive(Debug)]
+pub(crate) struct FrameAssembler {
+    pending: Vec<u8>,
+    builder: SMLMessageBuilder,
+    max_frame_size: usize,
+    stats: TransportStats,
+}
+
+impl Default for FrameAssembler {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            builder: SMLMessageBuilder::Empty,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            stats: TransportStats::default(),
+        }
+    }
+}
+
+impl FrameAssembler {
+    pub(crate) fn set_max_frame_size(&mut self, size: usize) {
+        self.max_frame_size = size;
+    }
+
+    pub(crate) fn stats(&self) -> TransportStats {
+        self.stats
+    }
+
+    /// Feed bytes read from the source
+    pub(crate) fn record(&mut self, buf: &[u8]) {
+        self.builder
+            .record_counted(buf, self.max_frame_size, &mut self.stats);
+    }
+
+    /// Next complete frame with its parsed messages, `None` until more bytes are recorded
+    pub(crate) fn next_frame(&mut self) -> Option<AssembledFrame> {
+        loop {
+            if let SMLMessageBuilder::Complete { .. } = self.builder {
+                let complete = core::mem::replace(&mut self.builder, SMLMessageBuilder::Empty);
+                if let SMLMessageBuilder::Complete { data, rest } = complete {
+                    self.pending = rest;
+                    return Some(match parse_body(&data) {
+                        Ok(messages) => Ok((data, messages)),
+                        Err(_) => {
+                            self.stats.parse_errors += 1;
+                            Err(data)
+                        }
+                    });
+                }
+            }
+
+            if self.pending.is_empty() {
+                return None;
+            }
+            let pending = core::mem::take(&mut self.pending);
+            self.record(&pending);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+/// CRC-16/X-25 as used for SML messages and frames
+///
+/// The checksum is transmitted low byte first.
+/// ```
+/// use hackdose_sml_parser::transport::crc16;
+///
+/// assert_eq!(crc16(b"123456789"), 0x906e);
+/// ```
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffff_u16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
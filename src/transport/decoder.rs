@@ -0,0 +1,222 @@
+use super::{
+    advance_start_sequence, END_SEQUENCE_WITHOUT_CRC, ESCAPED_ESCAPE_SEQUENCE, ESCAPE_SEQUENCE,
+    START_SEQUENCE,
+};
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum FrameDecoderError {
+    /// The frame did not fit into the buffer and has been dropped
+    Overflow,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum State {
+    /// number of bytes of the start sequence seen so far
+    Searching(usize),
+    /// whether the last block of four bytes is an escape sequence awaiting its command
+    Recording(bool),
+    /// end sequence found, waiting for padding count and crc
+    Trailer {
+        end: usize,
+        remaining: usize,
+    },
+    Complete,
+}
+
+/// Heapless decoder reading SML frames byte-wise into a caller-provided buffer
+///
+/// Decodes the same frames as [super::SMLMessageBuilder] without allocating,
+/// e.g. in interrupt handlers or with a tiny heap.
+/// ```
+/// use hackdose_sml_parser::transport::FrameDecoder;
+///
+/// let mut buf = [0u8; 64];
+/// let mut decoder = FrameDecoder::new(&mut buf);
+/// let input = [
+///     0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x63, 0x01, 0x02, 0x00,
+///     0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x01, 0x02, 0x03,
+/// ];
+/// let (last, head) = input.split_last().unwrap();
+/// for byte in head {
+///     assert!(decoder.push(*byte).is_none());
+/// }
+/// assert_eq!(decoder.push(*last), Some(Ok(&[0x63, 0x01, 0x02, 0x00][..])));
+/// ```
+pub struct FrameDecoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    state: State,
+}
+
+impl<'a> FrameDecoder<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            state: State::Searching(0),
+        }
+    }
+
+    /// Feed a single byte
+    ///
+    /// Returns the body of the frame (omitting header, footer and crc) once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], FrameDecoderError>> {
+        match self.state {
+            State::Searching(matched) => {
+                self.state = State::Searching(advance_start_sequence(matched, byte));
+                if self.state == State::Searching(START_SEQUENCE.len()) {
+                    self.state = State::Recording(false);
+                    self.len = 0;
+                }
+                None
+            }
+            State::Recording(escaped) => {
+                let aligned = self.len % ESCAPE_SEQUENCE.len() == 0;
+                if escaped && aligned && byte == END_SEQUENCE_WITHOUT_CRC[ESCAPE_SEQUENCE.len()] {
+                    self.state = State::Trailer {
+                        end: self.len - ESCAPE_SEQUENCE.len(),
+                        remaining: 3,
+                    };
+                    return None;
+                }
+                if self.len == self.buf.len() {
+                    self.state = State::Searching(advance_start_sequence(0, byte));
+                    return Some(Err(FrameDecoderError::Overflow));
+                }
+                self.buf[self.len] = byte;
+                self.len += 1;
+                if self.len % ESCAPE_SEQUENCE.len() == 0 {
+                    let block = &self.buf[self.len - ESCAPE_SEQUENCE.len()..self.len];
+                    self.state = State::Recording(match escaped {
+                        false => block == ESCAPE_SEQUENCE,
+                        // a start sequence, the previous frame was truncated
+                        true if block == &START_SEQUENCE[ESCAPE_SEQUENCE.len()..] => {
+                            self.len = 0;
+                            false
+                        }
+                        // an escaped escape sequence or an unknown command
+                        true => false,
+                    });
+                }
+                None
+            }
+            State::Trailer { end, remaining } => {
+                if remaining > 1 {
+                    self.state = State::Trailer {
+                        end,
+                        remaining: remaining - 1,
+                    };
+                    return None;
+                }
+                self.state = State::Complete;
+                let len = unescape_in_place(&mut self.buf[..end]);
+                Some(Ok(&self.buf[..len]))
+            }
+            State::Complete => {
+                self.state = State::Searching(0);
+                self.push(byte)
+            }
+        }
+    }
+}
+
+/// Replace escaped escape sequences aligned to four bytes by plain ones, returning the
+/// new length
+fn unescape_in_place(buf: &mut [u8]) -> usize {
+    let mut read = 0;
+    let mut write = 0;
+    while read < buf.len() {
+        if read % ESCAPE_SEQUENCE.len() == 0 && buf[read..].starts_with(ESCAPED_ESCAPE_SEQUENCE) {
+            buf[write..write + ESCAPE_SEQUENCE.len()].copy_from_slice(ESCAPE_SEQUENCE);
+            write += ESCAPE_SEQUENCE.len();
+            read += ESCAPED_ESCAPE_SEQUENCE.len();
+        } else {
+            buf[write] = buf[read];
+            write += 1;
+            read += 1;
+        }
+    }
+    write
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode_all(input: &[u8], buf: &mut [u8]) -> Vec<Result<Vec<u8>, FrameDecoderError>> {
+        let mut decoder = FrameDecoder::new(buf);
+        input
+            .iter()
+            .filter_map(|byte| decoder.push(*byte).map(|result| result.map(|x| x.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    pub fn decodes_consecutive_frames() {
+        let input = &[
+            0x7b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x00, 0x00, 0x1b,
+            0x1b, 0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01,
+            0x01, 0x43, 0x00, 0x00, 0x00, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x02, 0x01,
+        ];
+
+        let frames = decode_all(input, &mut [0; 32]);
+
+        assert_eq!(
+            frames,
+            vec![
+                Ok(vec![0x42, 0x43, 0x00, 0x00]),
+                Ok(vec![0x43, 0x00, 0x00, 0x00])
+            ]
+        );
+    }
+
+    #[test]
+    pub fn accepts_long_runs_of_escape_bytes() {
+        let input = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x00, 0x00, 0x00,
+            0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x01, 0x02,
+        ];
+
+        let frames = decode_all(input, &mut [0; 32]);
+
+        assert_eq!(frames, vec![Ok(vec![0x42, 0x00, 0x00, 0x00])]);
+    }
+
+    #[test]
+    pub fn reports_overflow_and_resynchronises() {
+        let input = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+            0x48, 0x49, 0x4a, 0x4b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x00,
+            0x00, 0x00, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x01, 0x02,
+        ];
+
+        let frames = decode_all(input, &mut [0; 9]);
+
+        assert_eq!(
+            frames,
+            vec![
+                Err(FrameDecoderError::Overflow),
+                Ok(vec![0x42, 0x00, 0x00, 0x00])
+            ]
+        );
+    }
+
+    #[test]
+    pub fn unescapes_escape_sequences_in_body() {
+        let input = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x43, 0x00, 0x1b, 0x1b, 0x1b, 0x1b,
+            0x1a, 0x01, 0x01, 0x02,
+        ];
+
+        let frames = decode_all(input, &mut [0; 32]);
+
+        // escape sequences not aligned to four bytes are not escaped
+        assert_eq!(
+            frames,
+            vec![Ok(vec![
+                0x1b, 0x1b, 0x1b, 0x1b, 0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x43, 0x00
+            ])]
+        );
+    }
+}
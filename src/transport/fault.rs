@@ -0,0 +1,165 @@
+//! Fault injection for transport robustness tests
+//!
+//! Corrupts encoded frames the way serial lines and flaky readers do and checks
+//! that [SMLMessageBuilder] resynchronises on the following frames.
+use alloc::vec::Vec;
+
+use proptest::{collection::vec, prelude::*};
+
+use super::{SMLMessageBuilder, START_SEQUENCE};
+use crate::encode::encode_frame;
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+    BitFlip { offset: usize, bit: u8 },
+    DroppedByte { offset: usize },
+    Truncated { length: usize },
+    DuplicatedStart,
+    GarbageBefore(Vec<u8>),
+}
+
+/// Apply a fault to an encoded frame, offsets wrap around the frame length
+pub fn corrupt(frame: &[u8], fault: &Fault) -> Vec<u8> {
+    let mut frame = frame.to_vec();
+    match fault {
+        Fault::BitFlip { offset, bit } => {
+            let offset = offset % frame.len();
+            frame[offset] ^= 1 << (bit % 8);
+        }
+        Fault::DroppedByte { offset } => {
+            frame.remove(offset % frame.len());
+        }
+        Fault::Truncated { length } => frame.truncate(length % frame.len()),
+        Fault::DuplicatedStart => {
+            frame.splice(0..0, START_SEQUENCE.iter().copied());
+        }
+        Fault::GarbageBefore(garbage) => {
+            frame.splice(0..0, garbage.iter().copied());
+        }
+    }
+    frame
+}
+
+/// Bytes with plenty of escape and start sequence material
+pub fn noisy_byte() -> impl Strategy<Value = u8> {
+    prop_oneof![Just(0x1b), Just(0x01), Just(0x1a), any::<u8>()]
+}
+
+/// A frame body rich in escape sequences
+pub fn body() -> impl Strategy<Value = Vec<u8>> {
+    vec(noisy_byte(), 0..64)
+}
+
+pub fn fault() -> impl Strategy<Value = Fault> {
+    prop_oneof![
+        (any::<usize>(), any::<u8>()).prop_map(|(offset, bit)| Fault::BitFlip { offset, bit }),
+        any::<usize>().prop_map(|offset| Fault::DroppedByte { offset }),
+        any::<usize>().prop_map(|length| Fault::Truncated { length }),
+        Just(Fault::DuplicatedStart),
+        vec(noisy_byte(), 1..32).prop_map(Fault::GarbageBefore),
+    ]
+}
+
+/// Largest frame kept by the builder in these tests, more than any frame of [body]
+const MAX_FRAME_SIZE: usize = 256;
+
+/// Feed the chunks like a reader would and collect all complete bodies
+///
+/// Fails if the builder ever records more than [MAX_FRAME_SIZE] bytes or hands back
+/// more than these and the current chunk as rest of a complete frame.
+pub fn decode(chunks: &[&[u8]]) -> Result<Vec<Vec<u8>>, TestCaseError> {
+    let mut builder = SMLMessageBuilder::Empty;
+    let mut bodies = vec![];
+    for chunk in chunks {
+        builder.record_bounded(chunk, MAX_FRAME_SIZE);
+        while let SMLMessageBuilder::Complete { .. } = builder {
+            let complete = core::mem::replace(&mut builder, SMLMessageBuilder::Empty);
+            if let SMLMessageBuilder::Complete { data, rest } = complete {
+                prop_assert!(
+                    rest.len() <= MAX_FRAME_SIZE + chunk.len(),
+                    "rest of {} bytes after a chunk of {}",
+                    rest.len(),
+                    chunk.len()
+                );
+                bodies.push(data);
+                builder.record_bounded(&rest, MAX_FRAME_SIZE);
+            }
+        }
+        if let SMLMessageBuilder::Recording(recording) = &builder {
+            prop_assert!(
+                recording.data().len() <= MAX_FRAME_SIZE,
+                "recorded {} bytes",
+                recording.data().len()
+            );
+        }
+    }
+    Ok(bodies)
+}
+
+fn is_subsequence(needles: &[Vec<u8>], haystack: &[Vec<u8>]) -> bool {
+    let mut haystack = haystack.iter();
+    needles
+        .iter()
+        .all(|needle| haystack.any(|candidate| candidate == needle))
+}
+
+/// The body of an encoded frame as recorded by the builder, including padding
+fn padded(body: &[u8], frame: &[u8]) -> Vec<u8> {
+    let padding = frame[frame.len() - 3] as usize;
+    let mut body = body.to_vec();
+    body.resize(body.len() + padding, 0x00);
+    body
+}
+
+/// Frames with their bodies, some of them corrupted
+fn stream() -> impl Strategy<Value = (Vec<u8>, Vec<Vec<u8>>)> {
+    vec((body(), proptest::option::of(fault())), 1..6).prop_map(|frames| {
+        let mut input = vec![];
+        let mut intact = vec![];
+        for (body, fault) in frames {
+            let frame = encode_frame(&body);
+            match fault {
+                Some(fault) => input.extend_from_slice(&corrupt(&frame, &fault)),
+                None => {
+                    input.extend_from_slice(&frame);
+                    intact.push(padded(&body, &frame));
+                }
+            }
+        }
+        (input, intact)
+    })
+}
+
+proptest! {
+    #[test]
+    fn decodes_intact_frames_around_corrupted_ones((input, intact) in stream(), chunk_size in 1..64usize) {
+        let chunks: Vec<_> = input.chunks(chunk_size).collect();
+
+        let bodies = decode(&chunks)?;
+
+        prop_assert!(is_subsequence(&intact, &bodies), "decoded {:02x?}", bodies);
+    }
+
+    #[test]
+    fn resynchronises_on_split_reads_at_every_offset(first in body(), fault in fault(), second in body()) {
+        let mut input = corrupt(&encode_frame(&first), &fault);
+        let frame = encode_frame(&second);
+        input.extend_from_slice(&frame);
+        let second = padded(&second, &frame);
+
+        for split in 0..=input.len() {
+            let (head, tail) = input.split_at(split);
+
+            let bodies = decode(&[head, tail])?;
+
+            prop_assert_eq!(bodies.last(), Some(&second), "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn survives_arbitrary_input(input in vec(noisy_byte(), 0..512), chunk_size in 1..64usize) {
+        let chunks: Vec<_> = input.chunks(chunk_size).collect();
+
+        decode(&chunks)?;
+    }
+}
@@ -1,17 +1,31 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "std")]
+mod assembler;
+mod crc;
+mod decoder;
+#[cfg(test)]
+mod fault;
+
+#[cfg(feature = "std")]
+pub(crate) use assembler::FrameAssembler;
+pub use crc::crc16;
+pub use decoder::{FrameDecoder, FrameDecoderError};
+
 /// Builder to read SML messages byte-wise from a stream
 /// ```
 /// use hackdose_sml_parser::transport::SMLMessageBuilder;
 /// let mut builder = SMLMessageBuilder::Empty;
 /// builder.record(&[0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01]);
-/// builder.record(&[0x63, 0x01, 0x02]);
+/// builder.record(&[0x63, 0x01, 0x02, 0x00]);
 /// builder.record(&[0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x01,0x02, 0x03]);
-/// assert_eq!(builder, SMLMessageBuilder::Complete{ data: vec![0x63, 0x01, 0x02], rest: vec![]});
+/// assert_eq!(builder, SMLMessageBuilder::Complete{ data: vec![0x63, 0x01, 0x02, 0x00], rest: vec![]});
 /// ```
 #[derive(Eq, PartialEq, Debug)]
 pub enum SMLMessageBuilder {
     Empty,
     IncompleteStartSignature(usize),
-    Recording(Vec<u8>),
+    Recording(Recording),
     Complete {
         /// the body of the message, omitting crc and header/footer
         data: Vec<u8>,
@@ -20,108 +34,348 @@ pub enum SMLMessageBuilder {
     },
 }
 
-static START_SEQUENCE: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01];
-static END_SEQUENCE_WITHOUT_CRC: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a];
+/// Default limit for the size of a frame body, see [SMLMessageBuilder::record_bounded]
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024;
+
+pub(crate) static START_SEQUENCE: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01];
+pub(crate) static END_SEQUENCE_WITHOUT_CRC: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a];
+pub(crate) static ESCAPE_SEQUENCE: &[u8] = &[0x1b, 0x1b, 0x1b, 0x1b];
+pub(crate) static ESCAPED_ESCAPE_SEQUENCE: &[u8] =
+    &[0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b];
 
 impl SMLMessageBuilder {
+    /// Feed bytes read from the stream
+    ///
+    /// Bytes recorded after a message is complete are appended to its `rest`.
+    /// A start sequence inside a message starts a new message, so the builder
+    /// resynchronises on truncated or corrupted frames. Frames longer than
+    /// [DEFAULT_MAX_FRAME_SIZE] (16 KiB) are dropped; use [Self::record_bounded]
+    /// for meters sending larger frames.
     pub fn record(&mut self, buf: &[u8]) {
+        self.record_bounded(buf, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Feed bytes read from the stream, dropping frames longer than `max_frame_size`
+    ///
+    /// Once more than `max_frame_size` bytes are recorded without an end sequence,
+    /// the recording is discarded and the builder searches for the next start sequence.
+    pub fn record_bounded(&mut self, buf: &[u8], max_frame_size: usize) {
+        self.record_counted(buf, max_frame_size, &mut TransportStats::default())
+    }
+
+    /// Feed bytes like [SMLMessageBuilder::record_bounded], counting frames in `stats`
+    pub fn record_counted(
+        &mut self,
+        buf: &[u8],
+        max_frame_size: usize,
+        stats: &mut TransportStats,
+    ) {
         match self {
             SMLMessageBuilder::Empty | SMLMessageBuilder::IncompleteStartSignature(_) => {
-                let start = match self {
-                    SMLMessageBuilder::Empty => 0,
-                    SMLMessageBuilder::IncompleteStartSignature(start) => *start,
-                    SMLMessageBuilder::Recording(_) => todo!(),
-                    SMLMessageBuilder::Complete { data: _, rest: _ } => todo!(),
+                let mut matched = match self {
+                    SMLMessageBuilder::IncompleteStartSignature(matched) => *matched,
+                    _ => 0,
                 };
-                let remainder_of_start_sequence = &START_SEQUENCE[start..];
-                let remaining_start_sequence_bytes = remainder_of_start_sequence.len();
-
-                let buffer_length = buf.len();
-
-                struct MaximalOccurance {
-                    index: usize,
-                    length: usize,
+                for (index, byte) in buf.iter().enumerate() {
+                    matched = advance_start_sequence(matched, *byte);
+                    if matched == START_SEQUENCE.len() {
+                        *self = SMLMessageBuilder::Recording(Recording::default());
+                        self.record_counted(&buf[index + 1..], max_frame_size, stats);
+                        return;
+                    }
                 }
-                let maximal_start_sequence_occurance = (0..buffer_length)
-                    .map(|i| {
-                        let window = &&buf[buffer_length - i - 1
-                            ..usize::min(
-                                buffer_length,
-                                buffer_length - i - 1 + remainder_of_start_sequence.len(),
-                            )];
-                        let contained_length = contains(window, remainder_of_start_sequence);
-                        if contained_length < remaining_start_sequence_bytes
-                            && i + 1 > contained_length
-                        {
-                            MaximalOccurance {
-                                index: buffer_length - i - 1,
-                                length: 0,
-                            }
-                        } else {
-                            MaximalOccurance {
-                                index: buffer_length - i - 1,
-                                length: contained_length,
-                            }
-                        }
-                    })
-                    .max_by_key(|item| item.length)
-                    .unwrap_or(MaximalOccurance {
-                        index: 0,
-                        length: 0,
-                    });
-
-                if maximal_start_sequence_occurance.length == remaining_start_sequence_bytes {
-                    *self = SMLMessageBuilder::Recording([].to_vec());
-                    self.record(
-                        &buf[maximal_start_sequence_occurance.index
-                            + maximal_start_sequence_occurance.length..]
-                            .to_vec(),
-                    );
-                } else if maximal_start_sequence_occurance.length > 0 {
-                    *self = SMLMessageBuilder::IncompleteStartSignature(
-                        maximal_start_sequence_occurance.length + start,
-                    );
-                } else if maximal_start_sequence_occurance.length == 0 && buf.len() > 0 {
-                    *self = SMLMessageBuilder::Empty;
+                *self = if matched > 0 {
+                    SMLMessageBuilder::IncompleteStartSignature(matched)
+                } else {
+                    SMLMessageBuilder::Empty
                 };
             }
+            SMLMessageBuilder::Recording(recording) => {
+                recording.data.extend_from_slice(buf);
+                match recording.scan() {
+                    Some((start, end)) => {
+                        let valid = has_valid_crc(&recording.data[start..], end - start);
+                        let rest = recording.rest(end, valid).to_vec();
+                        if end - start > max_frame_size {
+                            stats.oversized += 1;
+                            *self = SMLMessageBuilder::Empty;
+                            self.record_counted(&rest, max_frame_size, stats);
+                            return;
+                        }
+                        stats.frames += 1;
+                        if !valid {
+                            stats.crc_errors += 1;
+                        }
+                        *self = SMLMessageBuilder::Complete {
+                            data: unescape(&recording.data[start..end]),
+                            rest,
+                        }
+                    }
+                    None if recording.data.len() > max_frame_size => {
+                        // continue with the next frame which may have started, otherwise
+                        // keep what may be the beginning of the next start sequence
+                        let tail = match recording.frames.get(1) {
+                            Some(next) => next.start - START_SEQUENCE.len(),
+                            None => recording
+                                .data
+                                .len()
+                                .saturating_sub(START_SEQUENCE.len() - 1),
+                        };
+                        let tail = recording.data.split_off(tail);
+                        stats.oversized += 1;
+                        *self = SMLMessageBuilder::Empty;
+                        self.record_counted(&tail, max_frame_size, stats);
+                    }
+                    None => (),
+                }
+            }
+            SMLMessageBuilder::Complete { rest, .. } => rest.extend_from_slice(buf),
+        }
+    }
+}
+
+/// Counters of the frames decoded from a byte stream
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct TransportStats {
+    /// complete frames, including those with invalid crc or body
+    pub frames: u64,
+    /// complete frames whose crc does not match
+    pub crc_errors: u64,
+    /// frames dropped for exceeding the maximum frame size
+    pub oversized: u64,
+    /// complete frames whose body could not be parsed, counted by the readers
+    pub parse_errors: u64,
+}
+
+/// Bytes recorded after a start sequence
+///
+/// Only newly recorded bytes are searched for start and end sequences.
+#[derive(Eq, Debug)]
+pub struct Recording {
+    data: Vec<u8>,
+    /// frames which may be recorded, ordered by their start; the first starts at 0
+    frames: Vec<Frame>,
+    /// position from which the search for start sequences continues
+    searched: usize,
+}
+
+/// A frame whose body begins at `start`, escape sequences are aligned to its start
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+struct Frame {
+    start: usize,
+    /// position of the next block of four bytes to look at
+    scanned: usize,
+}
+
+/// Escape sequence found when scanning a frame
+enum Escaped {
+    /// end sequence at the position
+    End(usize),
+    /// start sequence, the frame was truncated
+    Start,
+}
 
-            SMLMessageBuilder::Recording(recorded) => {
-                recorded.append(&mut buf.to_vec());
-                let end = recorded
-                    .windows(END_SEQUENCE_WITHOUT_CRC.len() + 3)
-                    .enumerate()
-                    .find(|(_, x)| x[..END_SEQUENCE_WITHOUT_CRC.len()] == *END_SEQUENCE_WITHOUT_CRC)
-                    .map(|(index, _)| index);
-                if let Some(end) = end {
-                    let (message, rest) = recorded.split_at_mut(end);
-                    *self = SMLMessageBuilder::Complete {
-                        data: message.to_vec(),
-                        rest: rest[END_SEQUENCE_WITHOUT_CRC.len() + 3..].to_vec(),
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            frames: vec![Frame {
+                start: 0,
+                scanned: 0,
+            }],
+            searched: 0,
+        }
+    }
+}
+
+impl Recording {
+    /// The recorded bytes, still escaped
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Search the recorded bytes for the end of a frame, returning its start and end
+    ///
+    /// Escape sequences are aligned to four bytes from the start of the body, so a
+    /// start sequence at another position may be data as well as the start of a frame
+    /// following a truncated one. Both are followed until one of them ends; a frame
+    /// ending with a wrong checksum gives way to the others started before its end.
+    fn scan(&mut self) -> Option<(usize, usize)> {
+        while self.searched + START_SEQUENCE.len() <= self.data.len() {
+            if self.data[self.searched..].starts_with(START_SEQUENCE) {
+                self.searched += START_SEQUENCE.len();
+                self.frames.push(Frame {
+                    start: self.searched,
+                    scanned: self.searched,
+                });
+            } else {
+                self.searched += 1;
+            }
+        }
+
+        let mut index = 0;
+        while index < self.frames.len() {
+            let start = self.frames[index].start;
+            match self.advance(index) {
+                Some(Escaped::End(end)) => {
+                    let overtaken = self
+                        .frames
+                        .iter()
+                        .any(|frame| frame.start != start && frame.start <= end);
+                    if !overtaken || has_valid_crc(&self.data[start..], end - start) {
+                        return Some((start, end));
                     }
+                    self.frames.remove(index);
+                }
+                // the start sequence has been found by the search above
+                Some(Escaped::Start) => {
+                    self.frames.remove(index);
                 }
+                None => index += 1,
+            }
+        }
+
+        // drop the bytes before the first frame still recorded
+        let first = self.frames[0].start;
+        if first > 0 {
+            self.data.drain(..first);
+            self.searched -= first;
+            for frame in &mut self.frames {
+                frame.start -= first;
+                frame.scanned -= first;
+            }
+        }
+        None
+    }
+
+    /// Advance the frame at `index` block by block until an end or start sequence
+    fn advance(&mut self, index: usize) -> Option<Escaped> {
+        let frame = &mut self.frames[index];
+        loop {
+            let block = self.data.get(frame.scanned..frame.scanned + 4)?;
+            if block != ESCAPE_SEQUENCE {
+                frame.scanned += 4;
+                continue;
+            }
+            let command = self.data.get(frame.scanned + 4..frame.scanned + 8)?;
+            if command[0] == END_SEQUENCE_WITHOUT_CRC[ESCAPE_SEQUENCE.len()] {
+                return Some(Escaped::End(frame.scanned));
+            } else if command == &START_SEQUENCE[ESCAPE_SEQUENCE.len()..] {
+                return Some(Escaped::Start);
+            } else if command == ESCAPE_SEQUENCE {
+                frame.scanned += 8;
+            } else {
+                // unknown commands are taken as data
+                frame.scanned += 4;
             }
-            _ => {}
         }
     }
+
+    /// Bytes following the trailer of the message ending at `end`
+    ///
+    /// The trailer is the padding count and the crc. If the crc does not match, the
+    /// frame may have been truncated within its trailer, so the trailer ends at the
+    /// first escape byte, which may start the next frame. A matching crc may still end
+    /// in the escape bytes of the next frame if the frame lost exactly these bytes, so
+    /// they are kept as well. Stray escape bytes left in the rest are skipped when
+    /// searching for the next start sequence.
+    fn rest(&self, end: usize, valid_crc: bool) -> &[u8] {
+        let trailer = &self.data[end + END_SEQUENCE_WITHOUT_CRC.len()..];
+        let length = match valid_crc {
+            true => {
+                3 - trailer[1..3]
+                    .iter()
+                    .rev()
+                    .take_while(|x| **x == ESCAPE_SEQUENCE[0])
+                    .count()
+            }
+            false => trailer
+                .iter()
+                .take(3)
+                .position(|x| *x == ESCAPE_SEQUENCE[0])
+                .unwrap_or(3),
+        };
+        &trailer[length..]
+    }
 }
-fn contains(this: &[u8], that: &[u8]) -> usize {
-    let mut counter = 0;
-    for pair in this.iter().zip(that.iter()) {
-        if pair.0 == pair.1 {
-            counter += 1;
+
+/// Recordings are equal if they recorded the same bytes, regardless of search progress
+impl PartialEq for Recording {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl From<Vec<u8>> for Recording {
+    fn from(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            ..Default::default()
+        }
+    }
+}
+
+/// Advance the number of matched bytes of the start sequence by one byte
+fn advance_start_sequence(matched: usize, byte: u8) -> usize {
+    let escape = ESCAPE_SEQUENCE[0];
+    if byte == START_SEQUENCE[matched] {
+        matched + 1
+    } else if byte == escape && matched == ESCAPE_SEQUENCE.len() {
+        // a run of escape bytes longer than four still ends in a valid prefix
+        matched
+    } else if byte == escape {
+        1
+    } else {
+        0
+    }
+}
+
+/// Check the crc of a recorded message whose end sequence begins at `end`
+fn has_valid_crc(recorded: &[u8], end: usize) -> bool {
+    let crc_start = end + END_SEQUENCE_WITHOUT_CRC.len() + 1;
+    let mut frame = START_SEQUENCE.to_vec();
+    frame.extend_from_slice(&recorded[..crc_start]);
+    recorded[crc_start..crc_start + 2] == crc16(&frame).to_le_bytes()
+}
+
+/// Replace escaped escape sequences in the message body by plain ones
+///
+/// Only escape sequences aligned to four bytes are escaped.
+fn unescape(message: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(message.len());
+    let mut index = 0;
+    while index < message.len() {
+        if index % ESCAPE_SEQUENCE.len() == 0
+            && message[index..].starts_with(ESCAPED_ESCAPE_SEQUENCE)
+        {
+            result.extend_from_slice(ESCAPE_SEQUENCE);
+            index += ESCAPED_ESCAPE_SEQUENCE.len();
         } else {
-            break;
+            result.push(message[index]);
+            index += 1;
         }
     }
-    counter
+    result
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
 
     use super::*;
 
+    /// Frame with a single close response
+    pub(crate) static CLOSE_FRAME: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x76, 0x05, 0x03, 0x2b, 0x18, 0x11, 0x62,
+        0x00, 0x62, 0x00, 0x72, 0x63, 0x02, 0x01, 0x71, 0x01, 0x63, 0xfa, 0x36, 0x00, 0x1b, 0x1b,
+        0x1b, 0x1b, 0x1a, 0x00, 0x70, 0xb2,
+    ];
+
+    /// Frame with a matching crc whose body is no SML
+    pub(crate) static CORRUPT_FRAME: &[u8] = &[
+        0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x00, 0x00, 0x1b, 0x1b, 0x1b,
+        0x1b, 0x1a, 0x02, 0x70, 0xb2,
+    ];
+
     #[test]
     pub fn extends_if_start_of_sequence_is_found() {
         let buf = vec![0x1b];
@@ -176,7 +430,7 @@ mod test {
         let mut rec = SMLMessageBuilder::Empty;
 
         rec.record(buf);
-        assert_eq!(rec, SMLMessageBuilder::Recording(vec![]));
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![].into()));
     }
 
     #[test]
@@ -227,7 +481,7 @@ mod test {
 
         rec.record(buf);
         rec.record(buf2);
-        assert_eq!(rec, SMLMessageBuilder::Recording(vec![]));
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![].into()));
     }
 
     #[test]
@@ -237,43 +491,43 @@ mod test {
         let mut rec = SMLMessageBuilder::Empty;
 
         rec.record(buf);
-        assert_eq!(rec, SMLMessageBuilder::Recording(vec![0x42, 0x43]));
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![0x42, 0x43].into()));
     }
 
     #[test]
     pub fn extends_buffer_when_recording() {
         let buf = &[0x42, 0x43];
 
-        let mut rec = SMLMessageBuilder::Recording(vec![]);
+        let mut rec = SMLMessageBuilder::Recording(vec![].into());
 
         rec.record(buf);
-        assert_eq!(rec, SMLMessageBuilder::Recording(vec![0x42, 0x43]));
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![0x42, 0x43].into()));
     }
 
     #[test]
     pub fn extends_recording_buffer() {
         let buf = &[0x44, 0x45];
 
-        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43]);
+        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43].into());
 
         rec.record(buf);
         assert_eq!(
             rec,
-            SMLMessageBuilder::Recording(vec![0x42, 0x43, 0x44, 0x45])
+            SMLMessageBuilder::Recording(vec![0x42, 0x43, 0x44, 0x45].into())
         );
     }
 
     #[test]
     pub fn puts_into_ended_state() {
-        let buf = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x01, 0x02, 0x03];
+        let buf = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02, 0x03];
 
-        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43]);
+        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43, 0x00, 0x00].into());
 
         rec.record(buf);
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![0x03]
             }
         );
@@ -281,15 +535,15 @@ mod test {
 
     #[test]
     pub fn keeps_rest() {
-        let buf = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x01, 0x02, 0x03];
+        let buf = &[0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02, 0x03];
 
-        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43]);
+        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43, 0x00, 0x00].into());
 
         rec.record(buf);
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![0x03]
             }
         );
@@ -299,16 +553,16 @@ mod test {
     pub fn accepts_end_signature_in_two_parts() {
         let buf = &[0x1b, 0x1b, 0x1b, 0x1b];
 
-        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43]);
+        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x43, 0x00, 0x00].into());
 
         rec.record(buf);
-        let buf = &[0x1a, 0x00, 0x01, 0x02, 0x03];
+        let buf = &[0x1a, 0x02, 0x01, 0x02, 0x03];
         rec.record(buf);
 
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![0x03]
             }
         );
@@ -317,8 +571,8 @@ mod test {
     #[test]
     pub fn perform_recording_and_finishing_in_one_step() {
         let buf = &[
-            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x1b, 0x1b, 0x1b, 0x1b,
-            0x1a, 0x00, 0x01, 0x02,
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x00, 0x00, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02,
         ];
 
         let mut rec = SMLMessageBuilder::Empty;
@@ -328,7 +582,7 @@ mod test {
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![]
             }
         );
@@ -337,8 +591,8 @@ mod test {
     #[test]
     pub fn ignores_data_between_end_and_start() {
         let buf = &[
-            0x7b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x1b, 0x1b, 0x1b,
-            0x1b, 0x1a, 0x00, 0x01, 0x02,
+            0x7b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x00, 0x00, 0x1b,
+            0x1b, 0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02,
         ];
 
         let mut rec = SMLMessageBuilder::Empty;
@@ -348,7 +602,7 @@ mod test {
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![]
             }
         );
@@ -357,9 +611,9 @@ mod test {
     #[test]
     pub fn takes_first_of_two_messages() {
         let buf = &[
-            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x1b, 0x1b, 0x1b, 0x1b,
-            0x1a, 0x00, 0x01, 0x02, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x43, 0x1b,
-            0x1b, 0x1b, 0x1b, 0x1a, 0x00, 0x02, 0x01,
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x43, 0x00, 0x00, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x1a, 0x02, 0x01, 0x02, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01,
+            0x43, 0x00, 0x00, 0x00, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x02, 0x01,
         ];
 
         let mut rec = SMLMessageBuilder::Empty;
@@ -369,12 +623,297 @@ mod test {
         assert_eq!(
             rec,
             SMLMessageBuilder::Complete {
-                data: vec![0x42, 0x43],
+                data: vec![0x42, 0x43, 0x00, 0x00],
                 rest: vec![
-                    0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x43, 0x1b, 0x1b, 0x1b, 0x1b,
-                    0x1a, 0x00, 0x02, 0x01
+                    0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x43, 0x00, 0x00, 0x00, 0x1b,
+                    0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x02, 0x01
                 ]
             }
         );
     }
+
+    #[test]
+    pub fn unescapes_escape_sequences_in_body() {
+        let buf = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x43, 0x00, 0x1b, 0x1b, 0x1b, 0x1b,
+            0x1a, 0x01, 0x01, 0x02,
+        ];
+
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(buf);
+
+        // escape sequences not aligned to four bytes are not escaped
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x1b, 0x1b, 0x1b, 0x1b, 0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x43, 0x00],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn restarts_on_start_sequence_while_recording() {
+        let buf = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42, 0x00, 0x00, 0x00, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01,
+            0x43, 0x00, 0x00, 0x00, 0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x01, 0x02,
+        ];
+
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(buf);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x43, 0x00, 0x00, 0x00],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn finds_start_sequence_after_partial_one() {
+        let buf = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01,
+        ];
+
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(buf);
+
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![].into()));
+    }
+
+    #[test]
+    pub fn leaves_start_of_next_frame_after_truncated_trailer() {
+        let buf = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x1a, 0x03, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01,
+        ];
+
+        let mut rec = SMLMessageBuilder::Recording(vec![0x42, 0x00, 0x00, 0x00].into());
+
+        rec.record(buf);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x42, 0x00, 0x00, 0x00],
+                rest: vec![0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01]
+            }
+        );
+    }
+
+    #[test]
+    pub fn consumes_crc_containing_escape_bytes() {
+        let buf = &[
+            0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x00, 0xe7, 0x00, 0x00, 0x1b, 0x1b,
+            0x1b, 0x1b, 0x1a, 0x00, 0x1b, 0x16, 0x42,
+        ];
+
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(buf);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x00, 0xe7, 0x00, 0x00],
+                rest: vec![0x42]
+            }
+        );
+    }
+
+    #[test]
+    pub fn keeps_escaped_start_sequence_in_body() {
+        // found by fuzzing
+        let body = [0x07, 0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x01];
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(&crate::encode::encode_frame(&body));
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: [&body[..], &[0x00, 0x00]].concat(),
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn restarts_after_frame_truncated_within_escaped_escape_sequence() {
+        let mut buf = vec![0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01];
+        buf.extend_from_slice(&[0x42, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b, 0x1b]);
+        buf.extend_from_slice(&crate::encode::encode_frame(&[0x63, 0x01, 0x02, 0x00]));
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(&buf);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x63, 0x01, 0x02, 0x00],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn finds_next_frame_starting_with_crc_of_truncated_frame() {
+        // found by proptest: a frame whose crc ends in an escape byte lost that byte,
+        // so the first escape byte of the next frame completes it
+        let mut buf = (0..=u8::MAX)
+            .map(|x| crate::encode::encode_frame(&[x]))
+            .find(|frame| frame.last() == Some(&0x1b))
+            .unwrap();
+        let first = buf[START_SEQUENCE.len()..buf.len() - 8].to_vec();
+        buf.pop();
+        buf.extend_from_slice(&crate::encode::encode_frame(&[0x43; 4]));
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(&buf);
+
+        let SMLMessageBuilder::Complete { data, rest } = rec else {
+            panic!("frame not complete: {:?}", rec);
+        };
+        assert_eq!(data, first);
+        let mut rec = SMLMessageBuilder::Empty;
+        rec.record(&rest);
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x43; 4],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn finds_empty_frame_started_within_escaped_escape_sequence() {
+        // found by proptest: the start sequence of the empty frame is data of the
+        // truncated one, which ends with the same end sequence and a wrong crc
+        let mut buf = vec![0x1b, 0x1b, 0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01];
+        buf.extend_from_slice(&[0x42, 0x42, 0x42, 0x42, 0x1b, 0x1b, 0x1b, 0x1b]);
+        buf.extend_from_slice(&crate::encode::encode_frame(&[]));
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record(&buf);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn drops_recording_exceeding_max_frame_size() {
+        let mut rec = SMLMessageBuilder::Empty;
+        rec.record_bounded(START_SEQUENCE, 16);
+
+        rec.record_bounded(&[0x42; 14], 16);
+        rec.record_bounded(&[0x42, 0x42, 0x1b, 0x1b], 16);
+
+        assert_eq!(rec, SMLMessageBuilder::IncompleteStartSignature(2));
+        rec.record_bounded(&[0x1b, 0x1b, 0x01, 0x01, 0x01, 0x01, 0x42], 16);
+        assert_eq!(rec, SMLMessageBuilder::Recording(vec![0x42].into()));
+    }
+
+    #[test]
+    pub fn drops_all_frames_for_tiny_max_frame_size() {
+        let mut buf = crate::encode::encode_frame(&[0x42; 4]);
+        buf.extend_from_slice(&crate::encode::encode_frame(&[0x43]));
+
+        for max_frame_size in 0..START_SEQUENCE.len() {
+            let mut rec = SMLMessageBuilder::Empty;
+            let mut stats = TransportStats::default();
+            for byte in &buf {
+                rec.record_counted(&[*byte], max_frame_size, &mut stats);
+            }
+
+            assert_eq!(rec, SMLMessageBuilder::Empty);
+            assert_eq!(stats.oversized, 2);
+        }
+    }
+
+    #[test]
+    pub fn drops_complete_frame_exceeding_max_frame_size() {
+        let mut buf = crate::encode::encode_frame(&[0x42; 32]);
+        buf.extend_from_slice(&crate::encode::encode_frame(&[0x43; 4]));
+        let mut rec = SMLMessageBuilder::Empty;
+
+        rec.record_bounded(&buf, 16);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x43; 4],
+                rest: vec![]
+            }
+        );
+    }
+
+    #[test]
+    pub fn searches_only_new_bytes() {
+        let mut rec = SMLMessageBuilder::Recording(vec![].into());
+
+        for _ in 0..1000 {
+            rec.record(&[0x42]);
+        }
+
+        match rec {
+            SMLMessageBuilder::Recording(recording) => {
+                assert_eq!(recording.searched, 993);
+                assert_eq!(recording.frames[0].scanned, 1000);
+            }
+            _ => panic!("not recording"),
+        }
+    }
+
+    #[test]
+    pub fn appends_to_rest_when_complete() {
+        let mut rec = SMLMessageBuilder::Complete {
+            data: vec![0x42],
+            rest: vec![0x1b],
+        };
+
+        rec.record(&[0x1b, 0x1b]);
+
+        assert_eq!(
+            rec,
+            SMLMessageBuilder::Complete {
+                data: vec![0x42],
+                rest: vec![0x1b, 0x1b, 0x1b]
+            }
+        );
+    }
+
+    #[test]
+    pub fn counts_frames_with_invalid_crc_and_oversized_frames() {
+        let mut buf = crate::encode::encode_frame(&[0x42; 32]);
+        let mut corrupt = crate::encode::encode_frame(&[0x43; 4]);
+        let crc = corrupt.len() - 1;
+        corrupt[crc] ^= 0xff;
+        buf.extend_from_slice(&corrupt);
+        let mut rec = SMLMessageBuilder::Empty;
+        let mut stats = TransportStats::default();
+
+        rec.record_counted(&buf, 16, &mut stats);
+
+        assert_eq!(
+            stats,
+            TransportStats {
+                frames: 1,
+                crc_errors: 1,
+                oversized: 1,
+                parse_errors: 0
+            }
+        );
+    }
 }